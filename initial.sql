@@ -1,9 +1,21 @@
 -- MySQL (Planetscale)
+-- Creates a new database; existing ones are upgraded with migrations/ instead
 CREATE TABLE IF NOT EXISTS boards (
     id INTEGER NOT NULL AUTO_INCREMENT,
     name TEXT NOT NULL,
     board_key TEXT NOT NULL,
     default_name VARCHAR(255) NOT NULL DEFAULT 'デフォルトの名無し',
+    max_response_count INTEGER NOT NULL DEFAULT 1000,
+    max_thread_count INTEGER NOT NULL DEFAULT 500,
+    subject_max_length INTEGER NOT NULL DEFAULT 96,
+    name_max_length INTEGER NOT NULL DEFAULT 64,
+    mail_max_length INTEGER NOT NULL DEFAULT 64,
+    message_max_length INTEGER NOT NULL DEFAULT 4096,
+    message_max_lines INTEGER NOT NULL DEFAULT 32,
+    allow_unicode INTEGER NOT NULL DEFAULT 1,
+    duplicate_post_window INTEGER NOT NULL DEFAULT 300,
+    -- ip_daily, user_daily, ip_user_daily, none or forced
+    author_id_scheme VARCHAR(32) NOT NULL DEFAULT 'ip_daily',
     PRIMARY KEY (id)
 );
 
@@ -18,9 +30,22 @@ CREATE TABLE IF NOT EXISTS threads (
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     update_unix_timestamp INTEGER NOT NULL,
     author_id TEXT NOT NULL,
+    stopped INTEGER NOT NULL DEFAULT 0,
+    archived INTEGER NOT NULL DEFAULT 0,
+    -- Deleted threads are kept for moderators but no longer served
+    deleted INTEGER NOT NULL DEFAULT 0,
     PRIMARY KEY (id)
 );
 
+-- Where threads moved to another board went, so that old dat URLs still resolve
+CREATE TABLE IF NOT EXISTS thread_redirects (
+    board_id INTEGER NOT NULL,
+    thread_key INTEGER NOT NULL,
+    to_board_id INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (board_id, thread_key)
+);
+
 CREATE TABLE IF NOT EXISTS responses (
     id VARCHAR(255) NOT NULL,
     thread_id VARCHAR(255) NOT NULL,
@@ -32,6 +57,113 @@ CREATE TABLE IF NOT EXISTS responses (
     ip_address TEXT NOT NULL,
     user_id VARCHAR(255) NOT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    deleted INTEGER NOT NULL DEFAULT 0,
+    -- unix timestamp (seconds) of the last deletion or restoration, 0 if never
+    edited_at INTEGER NOT NULL DEFAULT 0,
+    -- caps.id of a post made with a cap, 0 otherwise
+    cap_id INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (id)
+);
+
+-- Posts rejected because the same user or IP address had just posted the same content
+CREATE TABLE IF NOT EXISTS duplicate_posts (
+    id VARCHAR(255) NOT NULL,
+    board_id INTEGER NOT NULL,
+    thread_key INTEGER,
+    body TEXT NOT NULL,
+    original_response_id VARCHAR(255) NOT NULL,
+    ip_address TEXT NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Patterns are matched against the stored (HTML-escaped) name, subject and body
+CREATE TABLE IF NOT EXISTS ng_words (
+    id INTEGER NOT NULL AUTO_INCREMENT,
+    board_id INTEGER NOT NULL DEFAULT 0,
+    pattern TEXT NOT NULL,
+    is_regex INTEGER NOT NULL DEFAULT 0,
+    action VARCHAR(16) NOT NULL DEFAULT 'reject',
+    replacement VARCHAR(255) NOT NULL DEFAULT '',
+    PRIMARY KEY (id)
+);
+
+-- Posts held back by an NG word, waiting for a moderator
+CREATE TABLE IF NOT EXISTS held_posts (
+    id VARCHAR(255) NOT NULL,
+    board_id INTEGER NOT NULL,
+    thread_key INTEGER,
+    title TEXT,
+    name TEXT NOT NULL,
+    mail TEXT NOT NULL,
+    body TEXT NOT NULL,
+    author_id TEXT NOT NULL,
+    date_text TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    ng_word_id INTEGER NOT NULL,
+    cap_id INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS ip_bans (
+    id VARCHAR(255) NOT NULL,
+    -- An address or a CIDR range, IPv4 or IPv6
+    cidr VARCHAR(64) NOT NULL,
+    -- 0 bans the range on every board
+    board_id INTEGER NOT NULL DEFAULT 0,
+    reason VARCHAR(255) NOT NULL DEFAULT '',
+    -- unix timestamp (seconds) when the ban ends, 0 for a permanent ban
+    expires_at INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Caps (キャップ): the mail field "#<id>#<password>" posts with the cap's name and role
+CREATE TABLE IF NOT EXISTS caps (
+    id INTEGER NOT NULL AUTO_INCREMENT,
+    -- Shown as "<name> ★"
+    name VARCHAR(255) NOT NULL,
+    -- bcrypt hash of the password, e.g. the output of
+    -- htpasswd -nbBC 10 '' '<password>' | tr -d ':\n'
+    password_hash VARCHAR(255) NOT NULL,
+    -- board_owner, moderator or admin
+    role VARCHAR(32) NOT NULL,
+    -- 0 lets the cap post on every board
+    board_id INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Reports (通報) of responses by readers, waiting in the moderation queue until resolved
+CREATE TABLE IF NOT EXISTS reports (
+    id VARCHAR(255) NOT NULL,
+    response_id VARCHAR(255) NOT NULL,
+    -- 1-based number of the response in its thread
+    response_number INTEGER NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    reason VARCHAR(255) NOT NULL,
+    resolved INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+-- Append-only record of admin actions; never updated or deleted by the application
+CREATE TABLE IF NOT EXISTS audit_logs (
+    id VARCHAR(255) NOT NULL,
+    -- Opaque id derived from the admin's token, never the Google account
+    actor VARCHAR(64) NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    board_key VARCHAR(255) NOT NULL DEFAULT '',
+    thread_key BIGINT NOT NULL DEFAULT 0,
+    response_number INTEGER NOT NULL DEFAULT 0,
+    -- Digest of the target's user hash (16 hex digits), never the user hash itself
+    user_hash VARCHAR(255) NOT NULL DEFAULT '',
+    reason VARCHAR(255) NOT NULL DEFAULT '',
+    detail TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     PRIMARY KEY (id)
 );
 
@@ -41,6 +173,11 @@ CREATE TABLE IF NOT EXISTS users (
     user_hash VARCHAR(255) NOT NULL,
     created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
     disabled INTEGER NOT NULL DEFAULT 0,
+    disabled_reason VARCHAR(255) NOT NULL DEFAULT '',
+    -- unix timestamp (seconds) when the ban ends, 0 for a permanent ban
+    disabled_until INTEGER NOT NULL DEFAULT 0,
+    -- Digest of user_hash by which the admin API names the user (user_hash is its token)
+    derived_hash VARCHAR(16) NOT NULL DEFAULT '',
     PRIMARY KEY (id)
 );
 
@@ -59,11 +196,47 @@ ALTER TABLE
 ADD
     INDEX thread_id_index (thread_id);
 
+ALTER TABLE
+    responses
+ADD
+    INDEX user_id_created_at_index (user_id, created_at);
+
+-- ip_address is TEXT, so only a prefix long enough for any IPv6 address is indexed
+ALTER TABLE
+    responses
+ADD
+    INDEX ip_address_created_at_index (ip_address(64), created_at);
+
+ALTER TABLE
+    duplicate_posts
+ADD
+    INDEX created_at_index (created_at);
+
+ALTER TABLE
+    reports
+ADD
+    UNIQUE INDEX response_id_user_id_index (response_id, user_id);
+
+ALTER TABLE
+    reports
+ADD
+    INDEX resolved_index (resolved);
+
+ALTER TABLE
+    audit_logs
+ADD
+    INDEX created_at_index (created_at);
+
 ALTER TABLE
     users
 ADD
     INDEX user_hash_index (user_hash);
 
+ALTER TABLE
+    users
+ADD
+    INDEX derived_hash_index (derived_hash);
+
 -- Insert mock data into boards table
 INSERT INTO
     boards (name, board_key)
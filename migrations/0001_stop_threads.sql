@@ -0,0 +1,10 @@
+-- Threads stop at the board's maximum response count
+ALTER TABLE
+    boards
+ADD
+    COLUMN max_response_count INTEGER NOT NULL DEFAULT 1000;
+
+ALTER TABLE
+    threads
+ADD
+    COLUMN stopped INTEGER NOT NULL DEFAULT 0;
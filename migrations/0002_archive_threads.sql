@@ -0,0 +1,10 @@
+-- Threads beyond the board's thread count are archived as kako logs
+ALTER TABLE
+    boards
+ADD
+    COLUMN max_thread_count INTEGER NOT NULL DEFAULT 500;
+
+ALTER TABLE
+    threads
+ADD
+    COLUMN archived INTEGER NOT NULL DEFAULT 0;
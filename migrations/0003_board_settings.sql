@@ -0,0 +1,15 @@
+-- Per-board post limits served in SETTING.TXT
+ALTER TABLE
+    boards
+ADD
+    COLUMN subject_max_length INTEGER NOT NULL DEFAULT 96,
+ADD
+    COLUMN name_max_length INTEGER NOT NULL DEFAULT 64,
+ADD
+    COLUMN mail_max_length INTEGER NOT NULL DEFAULT 64,
+ADD
+    COLUMN message_max_length INTEGER NOT NULL DEFAULT 4096,
+ADD
+    COLUMN message_max_lines INTEGER NOT NULL DEFAULT 32,
+ADD
+    COLUMN allow_unicode INTEGER NOT NULL DEFAULT 1;
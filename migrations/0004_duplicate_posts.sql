@@ -0,0 +1,22 @@
+-- Rejection of recently repeated posts
+ALTER TABLE
+    boards
+ADD
+    COLUMN duplicate_post_window INTEGER NOT NULL DEFAULT 300;
+
+CREATE TABLE IF NOT EXISTS duplicate_posts (
+    id VARCHAR(255) NOT NULL,
+    board_id INTEGER NOT NULL,
+    thread_key INTEGER,
+    body TEXT NOT NULL,
+    original_response_id VARCHAR(255) NOT NULL,
+    ip_address TEXT NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE
+    responses
+ADD
+    INDEX user_id_created_at_index (user_id, created_at);
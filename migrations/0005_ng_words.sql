@@ -0,0 +1,27 @@
+-- NG words and the posts they hold back
+CREATE TABLE IF NOT EXISTS ng_words (
+    id INTEGER NOT NULL AUTO_INCREMENT,
+    board_id INTEGER NOT NULL DEFAULT 0,
+    pattern TEXT NOT NULL,
+    is_regex INTEGER NOT NULL DEFAULT 0,
+    action VARCHAR(16) NOT NULL DEFAULT 'reject',
+    replacement VARCHAR(255) NOT NULL DEFAULT '',
+    PRIMARY KEY (id)
+);
+
+CREATE TABLE IF NOT EXISTS held_posts (
+    id VARCHAR(255) NOT NULL,
+    board_id INTEGER NOT NULL,
+    thread_key INTEGER,
+    title TEXT,
+    name TEXT NOT NULL,
+    mail TEXT NOT NULL,
+    body TEXT NOT NULL,
+    author_id TEXT NOT NULL,
+    date_text TEXT NOT NULL,
+    ip_address TEXT NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    ng_word_id INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
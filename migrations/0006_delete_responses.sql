@@ -0,0 +1,7 @@
+-- Deletion (あぼーん) and restoration of responses
+ALTER TABLE
+    responses
+ADD
+    COLUMN deleted INTEGER NOT NULL DEFAULT 0,
+ADD
+    COLUMN edited_at INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,7 @@
+-- Bans of users with a reason and an expiry
+ALTER TABLE
+    users
+ADD
+    COLUMN disabled_reason VARCHAR(255) NOT NULL DEFAULT '',
+ADD
+    COLUMN disabled_until INTEGER NOT NULL DEFAULT 0;
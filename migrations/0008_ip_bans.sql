@@ -0,0 +1,10 @@
+-- Bans of IP addresses and CIDR ranges
+CREATE TABLE IF NOT EXISTS ip_bans (
+    id VARCHAR(255) NOT NULL,
+    cidr VARCHAR(64) NOT NULL,
+    board_id INTEGER NOT NULL DEFAULT 0,
+    reason VARCHAR(255) NOT NULL DEFAULT '',
+    expires_at INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
@@ -0,0 +1,13 @@
+-- Deletion of threads and redirects of moved ones
+ALTER TABLE
+    threads
+ADD
+    COLUMN deleted INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS thread_redirects (
+    board_id INTEGER NOT NULL,
+    thread_key INTEGER NOT NULL,
+    to_board_id INTEGER NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (board_id, thread_key)
+);
@@ -0,0 +1,19 @@
+-- Audit log of admin actions
+CREATE TABLE IF NOT EXISTS audit_logs (
+    id VARCHAR(255) NOT NULL,
+    actor VARCHAR(64) NOT NULL,
+    action VARCHAR(64) NOT NULL,
+    board_key VARCHAR(255) NOT NULL DEFAULT '',
+    thread_key BIGINT NOT NULL DEFAULT 0,
+    response_number INTEGER NOT NULL DEFAULT 0,
+    user_hash VARCHAR(255) NOT NULL DEFAULT '',
+    reason VARCHAR(255) NOT NULL DEFAULT '',
+    detail TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE
+    audit_logs
+ADD
+    INDEX created_at_index (created_at);
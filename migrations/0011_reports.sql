@@ -0,0 +1,21 @@
+-- Reports of responses by readers
+CREATE TABLE IF NOT EXISTS reports (
+    id VARCHAR(255) NOT NULL,
+    response_id VARCHAR(255) NOT NULL,
+    response_number INTEGER NOT NULL,
+    user_id VARCHAR(255) NOT NULL,
+    reason VARCHAR(255) NOT NULL,
+    resolved INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id)
+);
+
+ALTER TABLE
+    reports
+ADD
+    UNIQUE INDEX response_id_user_id_index (response_id, user_id);
+
+ALTER TABLE
+    reports
+ADD
+    INDEX resolved_index (resolved);
@@ -0,0 +1,21 @@
+-- Caps and the posts made with them
+CREATE TABLE IF NOT EXISTS caps (
+    id INTEGER NOT NULL AUTO_INCREMENT,
+    name VARCHAR(255) NOT NULL,
+    password_hash VARCHAR(255) NOT NULL,
+    role VARCHAR(32) NOT NULL,
+    board_id INTEGER NOT NULL DEFAULT 0,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (id),
+    UNIQUE KEY password_hash_index (password_hash)
+);
+
+ALTER TABLE
+    responses
+ADD
+    COLUMN cap_id INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE
+    held_posts
+ADD
+    COLUMN cap_id INTEGER NOT NULL DEFAULT 0;
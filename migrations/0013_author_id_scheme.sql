@@ -0,0 +1,5 @@
+-- Per-board author ID schemes
+ALTER TABLE
+    boards
+ADD
+    COLUMN author_id_scheme VARCHAR(32) NOT NULL DEFAULT 'ip_daily';
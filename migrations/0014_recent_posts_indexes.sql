@@ -0,0 +1,10 @@
+-- Indexes of the duplicate post check by IP address and of the list of duplicate posts
+ALTER TABLE
+    responses
+ADD
+    INDEX ip_address_created_at_index (ip_address(64), created_at);
+
+ALTER TABLE
+    duplicate_posts
+ADD
+    INDEX created_at_index (created_at);
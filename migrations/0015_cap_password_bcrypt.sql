@@ -0,0 +1,7 @@
+-- Cap passwords are stored as bcrypt hashes, which are salted and so never collide.
+-- Hashes of existing caps (SHA3-256) no longer match: replace each of them with a bcrypt
+-- hash generated as described in initial.sql.
+ALTER TABLE
+    caps
+DROP
+    INDEX password_hash_index;
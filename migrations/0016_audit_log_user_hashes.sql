@@ -0,0 +1,9 @@
+-- Audit logs name users by a digest of their user hash, which doubles as the login token.
+-- Entries written before hold the user hash itself (24 hex digits) and cannot be converted in
+-- SQL, so their target is cleared.
+UPDATE
+    audit_logs
+SET
+    user_hash = ''
+WHERE
+    CHAR_LENGTH(user_hash) <> 16;
@@ -0,0 +1,11 @@
+-- The admin API names users by a digest of their user hash, which doubles as the login token.
+-- SQL cannot compute it, so existing users get theirs when they next log in or post.
+ALTER TABLE
+    users
+ADD
+    COLUMN derived_hash VARCHAR(16) NOT NULL DEFAULT '';
+
+ALTER TABLE
+    users
+ADD
+    INDEX derived_hash_index (derived_hash);
@@ -0,0 +1,280 @@
+use std::str::FromStr;
+
+use chrono::NaiveDate;
+use sha3::Digest;
+
+use crate::{routes::bbs_cgi::calculate_trip, utils::to_hex};
+
+/// How a board derives the ID shown after the date of each post (`boards.author_id_scheme`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthorIdScheme {
+    /// Changes with the IP address and the day
+    IpDaily,
+    /// Changes with the user token and the day, whatever network the user posts from
+    UserDaily,
+    /// Changes with either of the IP address and the user token, and with the day
+    IpUserDaily,
+    /// No ID at all
+    None,
+    /// `IpDaily` followed by a letter telling the device, like 5ch's forced IDs
+    Forced,
+}
+
+impl FromStr for AuthorIdScheme {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "ip_daily" => Ok(AuthorIdScheme::IpDaily),
+            "user_daily" => Ok(AuthorIdScheme::UserDaily),
+            "ip_user_daily" => Ok(AuthorIdScheme::IpUserDaily),
+            "none" => Ok(AuthorIdScheme::None),
+            "forced" => Ok(AuthorIdScheme::Forced),
+            _ => Err(()),
+        }
+    }
+}
+
+impl AuthorIdScheme {
+    /// Every scheme showing an ID mixes in the `AUTHOR_ID_SALT` secret, otherwise anyone
+    /// knowing an IP address or a token could compute its IDs
+    pub fn needs_salt(self) -> bool {
+        self != AuthorIdScheme::None
+    }
+}
+
+/// The `AUTHOR_ID_SALT` secret: comma separated salts, each optionally prefixed with
+/// `YYYY-MM-DD:` to be in effect from that date (JST) on, so that a new salt can be rolled
+/// out without changing IDs in the middle of a day. Salts cannot contain commas.
+#[derive(Debug, Clone)]
+pub struct AuthorIdSalts {
+    /// Oldest first
+    salts: Vec<(NaiveDate, String)>,
+}
+
+impl FromStr for AuthorIdSalts {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let mut salts = s
+            .split(',')
+            .map(|entry| {
+                let entry = entry.trim();
+                // An entry without a date has been in effect since always
+                let (from, salt) = entry
+                    .split_once(':')
+                    .and_then(|(from, salt)| {
+                        let from = NaiveDate::parse_from_str(from.trim(), "%Y-%m-%d").ok()?;
+                        Some((from, salt.trim()))
+                    })
+                    .unwrap_or((NaiveDate::MIN, entry));
+                if salt.is_empty() {
+                    return Err(());
+                }
+                Ok((from, salt.to_string()))
+            })
+            .collect::<std::result::Result<Vec<_>, ()>>()?;
+        salts.sort_by_key(|(from, _)| *from);
+        Ok(Self { salts })
+    }
+}
+
+impl AuthorIdSalts {
+    /// The key of the day: the salt in effect on `date` hashed together with the date, so it
+    /// only changes at midnight JST. `None` if no salt is in effect yet.
+    pub fn daily_key(&self, date: NaiveDate) -> Option<String> {
+        let (_, salt) = self.salts.iter().rev().find(|(from, _)| *from <= date)?;
+        let hash = sha3::Sha3_256::digest(format!("{salt}:{date}").as_bytes());
+        Some(to_hex(&hash))
+    }
+}
+
+/// The kind of device a post is made from, shown as the last letter of a forced ID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Device {
+    Pc,
+    Android,
+    IPhone,
+    /// Other mobile phones
+    Mobile,
+}
+
+impl Device {
+    pub fn from_user_agent(user_agent: &str) -> Self {
+        if ["iPhone", "iPad", "iPod"]
+            .iter()
+            .any(|x| user_agent.contains(x))
+        {
+            Device::IPhone
+        } else if user_agent.contains("Android") {
+            Device::Android
+        } else if user_agent.contains("Mobile") {
+            Device::Mobile
+        } else {
+            Device::Pc
+        }
+    }
+
+    fn suffix(self) -> char {
+        match self {
+            Device::Pc => '0',
+            Device::Android => 'a',
+            Device::IPhone => 'i',
+            Device::Mobile => 'M',
+        }
+    }
+}
+
+/// Who a post is from, as far as IDs are concerned
+#[derive(Debug, Clone, Copy)]
+pub struct AuthorIdSource<'a> {
+    pub ip_addr: &'a str,
+    pub user_hash: &'a str,
+    pub device: Device,
+}
+
+/// The ID of a post made on `date` (in JST, so IDs change at midnight JST).
+///
+/// `key` is the [`AuthorIdSalts::daily_key`] of the date; an empty string is returned for
+/// `AuthorIdScheme::None`. IDs already stored are never recomputed, so those made before the
+/// salt keep showing as they were.
+pub fn calculate_author_id(
+    scheme: AuthorIdScheme,
+    source: AuthorIdSource,
+    key: &str,
+    date: NaiveDate,
+) -> String {
+    let AuthorIdSource {
+        ip_addr, user_hash, ..
+    } = source;
+    let seed = match scheme {
+        AuthorIdScheme::IpDaily | AuthorIdScheme::Forced => format!("{key}{ip_addr}{date}"),
+        AuthorIdScheme::UserDaily => format!("{key}{user_hash}{date}"),
+        AuthorIdScheme::IpUserDaily => format!("{key}{ip_addr}{user_hash}{date}"),
+        AuthorIdScheme::None => return String::new(),
+    };
+
+    let mut id = calculate_trip(&seed);
+    id.truncate(9);
+    if scheme == AuthorIdScheme::Forced {
+        id.push(source.device.suffix());
+    }
+    id
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+
+    use super::*;
+    use crate::utils::jst_date;
+
+    const SCHEMES: [AuthorIdScheme; 5] = [
+        AuthorIdScheme::IpDaily,
+        AuthorIdScheme::UserDaily,
+        AuthorIdScheme::IpUserDaily,
+        AuthorIdScheme::None,
+        AuthorIdScheme::Forced,
+    ];
+
+    const SOURCE: AuthorIdSource = AuthorIdSource {
+        ip_addr: "192.0.2.1",
+        user_hash: "0123456789abcdef01234567",
+        device: Device::Android,
+    };
+
+    /// The ID of a post at the UTC time, with the key of its JST date
+    fn author_id_at(
+        scheme: AuthorIdScheme,
+        source: AuthorIdSource,
+        utc: (u32, u32, u32),
+    ) -> String {
+        let (hour, minute, second) = utc;
+        let utc = Utc
+            .with_ymd_and_hms(2023, 11, 14, hour, minute, second)
+            .unwrap();
+        let date = jst_date(utc);
+        let salts = "salt".parse::<AuthorIdSalts>().unwrap();
+        calculate_author_id(scheme, source, &salts.daily_key(date).unwrap(), date)
+    }
+
+    #[test]
+    fn jst_dates_turn_at_15_utc() {
+        let date = |hour, minute, second| {
+            jst_date(
+                Utc.with_ymd_and_hms(2023, 11, 14, hour, minute, second)
+                    .unwrap(),
+            )
+        };
+        assert_eq!(
+            date(0, 0, 0),
+            NaiveDate::from_ymd_opt(2023, 11, 14).unwrap()
+        );
+        assert_eq!(
+            date(14, 59, 59),
+            NaiveDate::from_ymd_opt(2023, 11, 14).unwrap()
+        );
+        assert_eq!(
+            date(15, 0, 0),
+            NaiveDate::from_ymd_opt(2023, 11, 15).unwrap()
+        );
+    }
+
+    #[test]
+    fn ids_change_at_midnight_jst_only() {
+        for scheme in SCHEMES {
+            let morning = author_id_at(scheme, SOURCE, (0, 0, 0));
+            let before = author_id_at(scheme, SOURCE, (14, 59, 59));
+            let after = author_id_at(scheme, SOURCE, (15, 0, 0));
+            if scheme == AuthorIdScheme::None {
+                assert_eq!([&morning, &before, &after], [""; 3]);
+                continue;
+            }
+            assert_eq!(morning, before, "{scheme:?}");
+            assert_ne!(before, after, "{scheme:?}");
+        }
+    }
+
+    #[test]
+    fn forced_ids_end_with_the_device_on_both_sides_of_midnight() {
+        for utc in [(14, 59, 59), (15, 0, 0)] {
+            let ip_daily = author_id_at(AuthorIdScheme::IpDaily, SOURCE, utc);
+            assert_eq!(ip_daily.len(), 9);
+            for (device, suffix) in [
+                (Device::Pc, '0'),
+                (Device::Android, 'a'),
+                (Device::IPhone, 'i'),
+                (Device::Mobile, 'M'),
+            ] {
+                let source = AuthorIdSource { device, ..SOURCE };
+                let forced = author_id_at(AuthorIdScheme::Forced, source, utc);
+                assert_eq!(
+                    forced,
+                    format!("{ip_daily}{suffix}"),
+                    "{device:?} at {utc:?}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn ids_follow_the_source_of_their_scheme() {
+        let other_ip = AuthorIdSource {
+            ip_addr: "198.51.100.1",
+            ..SOURCE
+        };
+        let other_user = AuthorIdSource {
+            user_hash: "fedcba9876543210fedcba98",
+            ..SOURCE
+        };
+        let changes = |scheme, source| {
+            author_id_at(scheme, SOURCE, (12, 0, 0)) != author_id_at(scheme, source, (12, 0, 0))
+        };
+        assert!(changes(AuthorIdScheme::IpDaily, other_ip));
+        assert!(!changes(AuthorIdScheme::IpDaily, other_user));
+        assert!(!changes(AuthorIdScheme::UserDaily, other_ip));
+        assert!(changes(AuthorIdScheme::UserDaily, other_user));
+        assert!(changes(AuthorIdScheme::IpUserDaily, other_ip));
+        assert!(changes(AuthorIdScheme::IpUserDaily, other_user));
+    }
+}
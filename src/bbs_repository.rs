@@ -1,11 +1,28 @@
-use planetscale_driver::{query, PSConnection};
-use worker::Date;
+use async_trait::async_trait;
 
-use crate::dtos::{Board, Res, Thread, User};
+use crate::dtos::{
+    AuditLog, Board, Cap, DuplicatePost, HeldPost, IpBan, NgWord, Report, Res, Thread, User,
+    UserPost,
+};
+
+mod error;
+#[cfg(test)]
+pub(crate) mod fixtures;
+mod in_memory;
+mod planetscale;
+mod sql;
+
+pub use error::{RepositoryError, RepositoryResult};
+#[cfg(test)]
+pub(crate) use in_memory::FailPoint;
+pub use in_memory::InMemoryBbsRepository;
+pub use planetscale::PlanetScaleBbsRepository;
 
 #[derive(Debug, Clone)]
 pub struct CreatingThread {
     pub board_id: i32,
+    /// Live threads beyond this count are archived, least recently updated first
+    pub max_thread_count: i32,
     pub title: String,
     pub name: String,
     pub mail: String,
@@ -14,12 +31,16 @@ pub struct CreatingThread {
     pub author_id: String,
     pub ip_addr: String,
     pub user_hash: String,
+    /// The cap the thread is created with, 0 if none
+    pub cap_id: i32,
 }
 
 #[derive(Debug, Clone)]
 pub struct CreatingResponse {
     pub board_id: i32,
     pub thread_key: i64,
+    /// The thread is stopped once it reaches this many responses
+    pub max_response_count: i32,
     pub name: String,
     pub mail: String,
     pub body: String,
@@ -27,235 +48,225 @@ pub struct CreatingResponse {
     pub author_id: String,
     pub ip_addr: String,
     pub user_hash: String,
+    /// The cap the response is posted with, 0 if none
+    pub cap_id: i32,
+    /// Lets the response into a thread stopped by a moderator, though not into a full or
+    /// archived one
+    pub allow_stopped: bool,
 }
 
-#[derive(Clone)]
-pub struct BbsRepository {
-    conn: PSConnection,
+impl CreatingResponse {
+    /// Whether the response must not raise the thread in subject.txt
+    pub fn is_sage(&self) -> bool {
+        self.mail.contains("sage")
+    }
 }
 
-impl BbsRepository {
-    pub fn new(conn: PSConnection) -> Self {
-        Self { conn }
-    }
+/// A new thread or a response, before it is stored
+#[derive(Debug, Clone)]
+pub enum CreatingPost {
+    Thread(CreatingThread),
+    Response(CreatingResponse),
+}
 
-    pub async fn get_boards(&self) -> anyhow::Result<Vec<Board>> {
-        let results = query("SELECT * FROM boards;")
-            .fetch_all::<Board>(&self.conn)
-            .await;
-
-        match results {
-            Ok(boards) => Ok(boards),
-            Err(e) => {
-                if e.to_string().contains("No results found") {
-                    Ok(Vec::new())
-                } else {
-                    Err(anyhow::anyhow!("Error: unknown DB error in get boards"))
-                }
-            }
-        }
-    }
+/// A post rejected as a duplicate, kept for moderators
+#[derive(Debug, Clone)]
+pub struct CreatingDuplicatePost {
+    pub board_id: i32,
+    /// `None` when the post was a new thread
+    pub thread_key: Option<i64>,
+    pub body: String,
+    /// The earlier response with the same content
+    pub original_response_id: String,
+    pub ip_addr: String,
+    pub user_hash: String,
+}
 
-    pub async fn get_board(&self, board_key: &str) -> anyhow::Result<Option<Board>> {
-        let result = query("SELECT * FROM boards WHERE board_key = '$0' LIMIT 1;")
-            .bind(board_key)
-            .fetch_one::<Board>(&self.conn)
-            .await;
-
-        match result {
-            Ok(board) => Ok(Some(board)),
-            Err(e) => {
-                if e.to_string().contains("No results found") {
-                    Ok(None)
-                } else {
-                    Err(anyhow::anyhow!("Error: unknown DB error in get board"))
-                }
-            }
-        }
-    }
+#[derive(Debug, Clone)]
+pub struct CreatingIpBan {
+    /// A normalized address or CIDR range
+    pub cidr: String,
+    /// 0 bans the range on every board
+    pub board_id: i32,
+    pub reason: String,
+    /// unix timestamp (seconds) when the ban ends, 0 for a permanent ban
+    pub expires_at: i64,
+}
 
-    pub async fn get_threads(&self, board_key: &str) -> anyhow::Result<Vec<Thread>> {
-        let result = query(
-            "SELECT * FROM threads WHERE board_id IN 
-            (SELECT id FROM boards WHERE board_key = '$0') 
-            ORDER BY update_unix_timestamp DESC;",
-        )
-        .bind(board_key)
-        .fetch_all::<Thread>(&self.conn)
-        .await;
-
-        match result {
-            Ok(threads) => Ok(threads),
-            Err(e) => {
-                if e.to_string().contains("No results found") {
-                    Ok(Vec::new())
-                } else {
-                    Err(anyhow::anyhow!("Error: unknown DB error in get threads"))
-                }
-            }
-        }
-    }
+#[derive(Debug, Clone)]
+pub struct CreatingReport {
+    pub response_id: String,
+    pub response_number: i32,
+    pub user_hash: String,
+    pub reason: String,
+}
 
-    pub async fn get_thread(&self, board_id: i32, thread_key: i64) -> anyhow::Result<Thread> {
-        query("SELECT * FROM threads WHERE thread_key = $0 AND board_id = $1")
-            .bind(thread_key)
-            .bind(board_id)
-            .fetch_one::<Thread>(&self.conn)
-            .await
-            .map_err(|e| {
-                if e.to_string().contains("No results found") {
-                    anyhow::anyhow!("Error: No results found in get thread")
-                } else {
-                    anyhow::anyhow!("Error: unknown DB error in get thread")
-                }
-            })
-    }
+/// An admin action to record; targets the action does not have are left empty or 0
+#[derive(Debug, Clone, Default)]
+pub struct CreatingAuditLog {
+    pub actor: String,
+    pub action: &'static str,
+    pub board_key: String,
+    pub thread_key: i64,
+    pub response_number: i32,
+    /// [`crate::utils::derive_user_hash`] of the target user, never the user hash itself
+    pub user_hash: String,
+    pub reason: String,
+    pub detail: String,
+}
+
+/// Conditions of [`BbsRepository::get_audit_logs`]; empty or 0 fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct AuditLogFilter {
+    pub actor: String,
+    pub action: String,
+    pub board_key: String,
+    pub thread_key: i64,
+    pub user_hash: String,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+/// Conditions of [`BbsRepository::get_duplicate_posts`]; empty fields match everything
+#[derive(Debug, Clone, Default)]
+pub struct DuplicatePostFilter {
+    pub board_key: String,
+    pub user_hash: String,
+    pub limit: u64,
+    pub offset: u64,
+}
+
+/// Storage backend used by the route handlers
+#[async_trait(?Send)]
+pub trait BbsRepository {
+    async fn get_boards(&self) -> RepositoryResult<Vec<Board>>;
+
+    async fn get_board(&self, board_key: &str) -> RepositoryResult<Option<Board>>;
+
+    /// Live (not archived) threads of the board, most recently updated first
+    async fn get_threads(&self, board_key: &str) -> RepositoryResult<Vec<Thread>>;
+
+    /// Archived threads of the board, newest first
+    async fn get_archived_threads(&self, board_key: &str) -> RepositoryResult<Vec<Thread>>;
+
+    async fn get_thread(&self, board_key: &str, thread_key: i64) -> RepositoryResult<Thread>;
+
+    /// Makes the thread read-only, or writable again. Bumps `update_unix_timestamp`, as the
+    /// DAT changes with it.
+    async fn set_thread_stopped(&self, thread_id: &str, stopped: bool) -> RepositoryResult<()>;
 
-    pub async fn get_thread_with_responses(
+    /// Bumps `update_unix_timestamp`, as the DAT changes with the title
+    async fn rename_thread(&self, thread_id: &str, title: &str) -> RepositoryResult<()>;
+
+    /// Moves the thread with its key and leaves a redirect on the board it leaves.
+    /// Fails with `Conflict` when the destination already has a thread with the same key.
+    async fn move_thread(&self, thread_id: &str, to_board_id: i32) -> RepositoryResult<()>;
+
+    /// Hides the thread from subject.txt and the DAT, keeping its rows for moderators
+    async fn delete_thread(&self, thread_id: &str) -> RepositoryResult<()>;
+
+    /// The board which the thread moved to from `board_id`, if it did
+    async fn get_thread_redirect(
+        &self,
+        board_id: i32,
+        thread_key: i64,
+    ) -> RepositoryResult<Option<i32>>;
+
+    async fn get_thread_with_responses(
         &self,
         board_key: &str,
         thread_key: i64,
-    ) -> anyhow::Result<(Thread, Vec<Res>)> {
-        let thread = query(
-            "SELECT * FROM threads WHERE thread_key = $0 AND board_id IN
-        (SELECT id FROM boards WHERE board_key = '$1');",
-        )
-        .bind(thread_key)
-        .bind(board_key)
-        .fetch_one::<Thread>(&self.conn)
-        .await
-        .map_err(|e| {
-            if e.to_string().contains("No results found") {
-                anyhow::anyhow!("No results found")
-            } else {
-                anyhow::anyhow!("Error: unknown DB error in get thread with responses")
-            }
-        })?;
-
-        let responses = query("SELECT * FROM responses WHERE thread_id = '$0';")
-            .bind(&thread.id)
-            .fetch_all::<Res>(&self.conn)
-            .await;
-
-        let responses = match responses {
-            Ok(responses) => responses,
-            Err(e) => {
-                if e.to_string().contains("No results found") {
-                    Vec::new()
-                } else {
-                    return Err(anyhow::anyhow!(
-                        "Error: unknown DB error in get thread with responses"
-                    ));
-                }
-            }
-        };
-
-        Ok((thread, responses))
-    }
+    ) -> RepositoryResult<(Thread, Vec<Res>)>;
 
-    pub async fn get_user(&self, user_hash: &str) -> anyhow::Result<Option<User>> {
-        let result = query("SELECT * FROM users WHERE user_hash = '$0' LIMIT 1;")
-            .bind(user_hash)
-            .fetch_one::<User>(&self.conn)
-            .await;
-
-        match result {
-            Ok(user) => Ok(Some(user)),
-            Err(e) => {
-                if e.to_string().contains("No results found") {
-                    Ok(None)
-                } else {
-                    Err(anyhow::anyhow!("Error: unknown DB error in get user"))
-                }
-            }
-        }
-    }
+    /// Responses posted with the user hash or from the IP address at or after `since`
+    /// (unix timestamp in seconds) on any board, newest first
+    async fn get_recent_responses_by_author(
+        &self,
+        user_hash: &str,
+        ip_addr: &str,
+        since: i64,
+    ) -> RepositoryResult<Vec<Res>>;
 
-    pub async fn create_thread(&self, thread: CreatingThread) -> anyhow::Result<()> {
-        let thread_key = Date::now().as_millis() / 1000;
-        let thread_id = uuid::Uuid::new_v7(uuid::Timestamp::from_unix(
-            uuid::NoContext,
-            Date::now().as_millis() / 1000,
-            ((Date::now().as_millis() % 1000) * 1000) as u32,
-        ));
-
-        query(
-            "INSERT INTO threads 
-            (thread_key, board_id, title, ip_address, user_id, update_unix_timestamp, id, author_id)
-        VALUES ($0, $1, '$2', '$3', '$4', $5, '$6', '$7')",
-        )
-        .bind(thread_key)
-        .bind(thread.board_id)
-        .bind(thread.title)
-        .bind(&thread.ip_addr)
-        .bind(&thread.user_hash)
-        .bind(thread_key)
-        .bind(thread_id)
-        .bind(&thread.author_id)
-        .execute(&self.conn)
-        .await
-        .map_err(|_| anyhow::anyhow!("Error: failed to insert thread"))?;
-
-        let response_id = uuid::Uuid::new_v7(uuid::Timestamp::from_unix(
-            uuid::NoContext,
-            Date::now().as_millis() / 1000,
-            (((Date::now().as_millis() + 1) % 1000) * 1000) as u32,
-        ));
-        query(
-            "INSERT INTO responses 
-            (thread_id, name, mail, body, author_id, date_text, ip_address, user_id, id)
-        VALUES ('$0', '$1', '$2', '$3', '$4', '$5', '$6', '$7', '$8');",
-        )
-        .bind(thread_id)
-        .bind(thread.name)
-        .bind(thread.mail)
-        .bind(thread.body)
-        .bind(thread.author_id)
-        .bind(thread.date)
-        .bind(thread.ip_addr)
-        .bind(thread.user_hash)
-        .bind(response_id)
-        .execute(&self.conn)
-        .await
-        .map_err(|_| anyhow::anyhow!("Error: failed to insert response"))?;
-
-        Ok(())
-    }
+    async fn create_duplicate_post(&self, post: CreatingDuplicatePost) -> RepositoryResult<()>;
 
-    pub async fn create_response(&self, response: CreatingResponse) -> anyhow::Result<()> {
-        let thread = self
-            .get_thread(response.board_id, response.thread_key)
-            .await?;
-
-        query("UPDATE threads SET response_count = response_count + 1 WHERE id = '$0';")
-            .bind(&thread.id)
-            .execute(&self.conn)
-            .await
-            .map_err(|_| anyhow::anyhow!("Error: failed to update response count of thread"))?;
-
-        let response_id = uuid::Uuid::new_v7(uuid::Timestamp::from_unix(
-            uuid::NoContext,
-            Date::now().as_millis() / 1000,
-            ((Date::now().as_millis() % 1000) * 1000) as u32,
-        ));
-        query(
-            "INSERT INTO responses 
-            (thread_id, name, mail, body, author_id, date_text, ip_address, user_id, id)
-        VALUES ('$0', '$1', '$2', '$3', '$4', '$5', '$6', '$7', '$8');",
-        )
-        .bind(&thread.id)
-        .bind(response.name)
-        .bind(response.mail)
-        .bind(response.body)
-        .bind(response.author_id)
-        .bind(response.date)
-        .bind(response.ip_addr)
-        .bind(response.user_hash)
-        .bind(response_id)
-        .execute(&self.conn)
-        .await
-        .map_err(|_| anyhow::anyhow!("Error: failed to insert response"))?;
-
-        Ok(())
-    }
+    /// Posts rejected as duplicates, newest first
+    async fn get_duplicate_posts(
+        &self,
+        filter: DuplicatePostFilter,
+    ) -> RepositoryResult<Vec<DuplicatePost>>;
+
+    /// Marks the response as deleted, or restores it, keeping its number in the thread
+    async fn set_response_deleted(&self, response_id: &str, deleted: bool) -> RepositoryResult<()>;
+
+    /// NG words of every board, including the global ones
+    async fn get_ng_words(&self) -> RepositoryResult<Vec<NgWord>>;
+
+    /// Stores the post for review instead of publishing it
+    async fn create_held_post(&self, post: CreatingPost, ng_word_id: i32) -> RepositoryResult<()>;
+
+    /// Posts waiting for review, oldest first
+    async fn get_held_posts(&self, limit: u64, offset: u64) -> RepositoryResult<Vec<HeldPost>>;
+
+    async fn get_held_post(&self, id: &str) -> RepositoryResult<HeldPost>;
+
+    /// Drops the post from the review queue, once it is released or discarded
+    async fn delete_held_post(&self, id: &str) -> RepositoryResult<()>;
+
+    /// Caps of every board, including the global ones
+    async fn get_caps(&self) -> RepositoryResult<Vec<Cap>>;
+
+    /// IP bans which have not expired yet, newest first
+    async fn get_ip_bans(&self) -> RepositoryResult<Vec<IpBan>>;
+
+    /// Returns the id of the new ban
+    async fn create_ip_ban(&self, ban: CreatingIpBan) -> RepositoryResult<String>;
+
+    async fn delete_ip_ban(&self, id: &str) -> RepositoryResult<()>;
+
+    /// A user reporting the same response again is ignored
+    async fn create_report(&self, report: CreatingReport) -> RepositoryResult<()>;
+
+    /// Reports not resolved yet, newest first
+    async fn get_unresolved_reports(&self) -> RepositoryResult<Vec<Report>>;
+
+    async fn resolve_reports(&self, response_id: &str) -> RepositoryResult<()>;
+
+    async fn create_audit_log(&self, log: CreatingAuditLog) -> RepositoryResult<()>;
+
+    /// Audit logs matching the filter, newest first
+    async fn get_audit_logs(&self, filter: AuditLogFilter) -> RepositoryResult<Vec<AuditLog>>;
+
+    async fn get_user(&self, user_hash: &str) -> RepositoryResult<Option<User>>;
+
+    /// The user whose user hash derives `derived_hash`, as the admin API names users
+    async fn get_user_by_derived_hash(&self, derived_hash: &str) -> RepositoryResult<Option<User>>;
+
+    /// Stores the user with the derived hash of `user_hash`
+    async fn create_user(&self, user_hash: &str, ip_addr: &str) -> RepositoryResult<()>;
+
+    /// Stores the derived hash of a user created before it was stored
+    async fn set_derived_user_hash(&self, user_hash: &str) -> RepositoryResult<()>;
+
+    /// Bans the user until `until` (unix timestamp in seconds, 0 for good)
+    async fn disable_user(&self, user_hash: &str, reason: &str, until: i64)
+        -> RepositoryResult<()>;
+
+    async fn enable_user(&self, user_hash: &str) -> RepositoryResult<()>;
+
+    /// Responses posted with the user hash on any board, newest first
+    async fn get_posts_by_user(
+        &self,
+        user_hash: &str,
+        limit: u64,
+        offset: u64,
+    ) -> RepositoryResult<Vec<UserPost>>;
+
+    /// Inserts the thread and its first response; neither is stored if either insert fails.
+    /// Archives the oldest threads of the board when it has more than `max_thread_count`.
+    async fn create_thread(&self, thread: CreatingThread) -> RepositoryResult<()>;
+
+    /// Inserts the response and increments `response_count` of its thread as one unit.
+    /// Unless the response is sage, `update_unix_timestamp` of the thread is also bumped.
+    /// Fails with `ThreadStopped` when the thread is stopped, archived or already full.
+    async fn create_response(&self, response: CreatingResponse) -> RepositoryResult<()>;
 }
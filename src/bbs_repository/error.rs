@@ -0,0 +1,77 @@
+use std::fmt;
+
+#[derive(Debug)]
+pub enum RepositoryError {
+    /// The requested row does not exist
+    NotFound,
+    /// The thread does not accept responses any more (stopped, archived or full)
+    ThreadStopped,
+    /// The write conflicts with an existing row
+    Conflict(anyhow::Error),
+    /// The database could not be reached or timed out
+    Unavailable(anyhow::Error),
+    /// Any other failure reported by the driver
+    Driver(anyhow::Error),
+}
+
+pub type RepositoryResult<T> = Result<T, RepositoryError>;
+
+impl RepositoryError {
+    /// Classifies an error of `planetscale_driver`, which only reports failures as `anyhow`
+    /// messages (an empty result is "No results found", Vitess errors carry their code).
+    pub fn from_driver(e: anyhow::Error) -> Self {
+        if e.chain()
+            .any(|x| x.downcast_ref::<reqwest::Error>().is_some())
+        {
+            return RepositoryError::Unavailable(e);
+        }
+        let message = e.to_string();
+        if message.contains("No results found") {
+            RepositoryError::NotFound
+        } else if message.contains("Duplicate entry") || message.contains("ALREADY_EXISTS") {
+            RepositoryError::Conflict(e)
+        } else if message.contains("UNAVAILABLE") || message.contains("DEADLINE_EXCEEDED") {
+            RepositoryError::Unavailable(e)
+        } else {
+            RepositoryError::Driver(e)
+        }
+    }
+}
+
+impl fmt::Display for RepositoryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RepositoryError::NotFound => write!(f, "not found"),
+            RepositoryError::ThreadStopped => write!(f, "thread is stopped"),
+            RepositoryError::Conflict(e) => write!(f, "conflict: {e}"),
+            RepositoryError::Unavailable(e) => write!(f, "database unavailable: {e}"),
+            RepositoryError::Driver(e) => write!(f, "database error: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for RepositoryError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RepositoryError::Conflict(e)
+            | RepositoryError::Unavailable(e)
+            | RepositoryError::Driver(e) => Some(e.as_ref()),
+            RepositoryError::NotFound | RepositoryError::ThreadStopped => None,
+        }
+    }
+}
+
+pub trait OptionalExt<T> {
+    /// Turns `NotFound` into `Ok(None)`
+    fn optional(self) -> RepositoryResult<Option<T>>;
+}
+
+impl<T> OptionalExt<T> for RepositoryResult<T> {
+    fn optional(self) -> RepositoryResult<Option<T>> {
+        match self {
+            Ok(x) => Ok(Some(x)),
+            Err(RepositoryError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! Data shared by tests running against `InMemoryBbsRepository`
+
+use super::{CreatingResponse, CreatingThread};
+use crate::dtos::Board;
+
+pub const BOARD_ID: i32 = 1;
+pub const BOARD_KEY: &str = "test";
+
+/// 2023-11-14 22:13:20 UTC
+pub const NOW_MILLIS: i64 = 1_700_000_000_000;
+
+/// Hostile values for round trips: quotes, backslashes, placeholders and NUL
+pub const TRICKY_TEXTS: [&str; 6] = [
+    "it's \"quoted\"",
+    "C:\\path\\",
+    "\\'; DROP TABLE responses; --",
+    "? and $1 and ?",
+    "a\0b\0",
+    "ソ表\r\n\x1a",
+];
+
+pub fn fixed_clock() -> i64 {
+    NOW_MILLIS
+}
+
+pub fn board() -> Board {
+    Board {
+        id: BOARD_ID,
+        name: "テスト板".to_string(),
+        board_key: BOARD_KEY.to_string(),
+        default_name: "名無しさん".to_string(),
+        max_response_count: 1000,
+        max_thread_count: 10,
+        subject_max_length: 96,
+        name_max_length: 64,
+        mail_max_length: 64,
+        message_max_length: 4096,
+        message_max_lines: 32,
+        allow_unicode: 1,
+        duplicate_post_window: 0,
+        author_id_scheme: "ip_daily".to_string(),
+    }
+}
+
+pub fn creating_thread(title: &str) -> CreatingThread {
+    CreatingThread {
+        board_id: BOARD_ID,
+        max_thread_count: 10,
+        title: title.to_string(),
+        name: String::new(),
+        mail: String::new(),
+        body: "本文".to_string(),
+        date: "2023/11/15(水) 07:13:20.000".to_string(),
+        author_id: "abcdefghi".to_string(),
+        ip_addr: "192.0.2.1".to_string(),
+        user_hash: "user".to_string(),
+        cap_id: 0,
+    }
+}
+
+pub fn creating_response(thread_key: i64, body: &str) -> CreatingResponse {
+    CreatingResponse {
+        board_id: BOARD_ID,
+        thread_key,
+        max_response_count: 1000,
+        name: String::new(),
+        mail: String::new(),
+        body: body.to_string(),
+        date: "2023/11/15(水) 07:13:20.000".to_string(),
+        author_id: "abcdefghi".to_string(),
+        ip_addr: "192.0.2.1".to_string(),
+        user_hash: "user".to_string(),
+        cap_id: 0,
+        allow_stopped: false,
+    }
+}
@@ -0,0 +1,1060 @@
+use std::{cmp::Reverse, sync::Mutex};
+
+use async_trait::async_trait;
+
+use super::{
+    error::{RepositoryError, RepositoryResult},
+    AuditLogFilter, BbsRepository, CreatingAuditLog, CreatingDuplicatePost, CreatingIpBan,
+    CreatingPost, CreatingReport, CreatingResponse, CreatingThread, DuplicatePostFilter,
+};
+use crate::{
+    dtos::{
+        AuditLog, Board, Cap, DuplicatePost, HeldPost, IpBan, NgWord, Report, Res, Thread, User,
+        UserPost,
+    },
+    utils::{derive_user_hash, system_clock, Clock},
+};
+
+#[derive(Debug, Clone, Default)]
+struct InMemoryState {
+    boards: Vec<Board>,
+    threads: Vec<Thread>,
+    responses: Vec<Res>,
+    users: Vec<User>,
+    /// (board_id, thread_key) -> to_board_id
+    thread_redirects: Vec<((i32, i64), i32)>,
+    /// (post, id, created_at)
+    duplicate_posts: Vec<(CreatingDuplicatePost, String, String)>,
+    ng_words: Vec<NgWord>,
+    held_posts: Vec<HeldPost>,
+    caps: Vec<Cap>,
+    ip_bans: Vec<IpBan>,
+    /// (report, id, created_at, resolved)
+    reports: Vec<(CreatingReport, String, String, bool)>,
+    audit_logs: Vec<AuditLog>,
+}
+
+/// Steps which tests can make fail: those of a multi-row write, to check that the write leaves
+/// nothing behind when any of them fails, and reads, to check how routes answer them
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FailPoint {
+    /// Reading boards, threads and responses
+    Read,
+    /// Inserting the thread and its first response, or the response
+    Insert,
+    /// Updating `response_count`, `stopped` and the archived threads
+    CountUpdate,
+    Commit,
+}
+
+/// Repository keeping everything in process memory, for local development and tests
+/// without a PlanetScale database.
+///
+/// Multi-row writes are applied to a copy of the state which replaces it on commit, like a
+/// transaction of the PlanetScale repository.
+#[derive(Debug)]
+pub struct InMemoryBbsRepository {
+    state: Mutex<InMemoryState>,
+    clock: Clock,
+    #[cfg(test)]
+    fail_point: Mutex<Option<FailPoint>>,
+}
+
+impl InMemoryBbsRepository {
+    pub fn new(boards: Vec<Board>) -> Self {
+        Self::with_clock(boards, system_clock)
+    }
+
+    pub fn with_clock(boards: Vec<Board>, clock: Clock) -> Self {
+        Self {
+            state: Mutex::new(InMemoryState {
+                boards,
+                ..Default::default()
+            }),
+            clock,
+            #[cfg(test)]
+            fail_point: Mutex::new(None),
+        }
+    }
+
+    /// Makes writes fail at `point` until it is cleared
+    #[cfg(test)]
+    pub fn fail_at(&self, point: Option<FailPoint>) {
+        *self.fail_point.lock().unwrap() = point;
+    }
+
+    fn pass(&self, point: FailPoint) -> RepositoryResult<()> {
+        #[cfg(test)]
+        if *self.fail_point.lock().unwrap() == Some(point) {
+            return Err(RepositoryError::Unavailable(anyhow::anyhow!(
+                "injected failure at {point:?}"
+            )));
+        }
+        let _ = point;
+        Ok(())
+    }
+
+    /// unix timestamp in seconds
+    fn now(&self) -> i64 {
+        (self.clock)() / 1000
+    }
+
+    fn now_text(&self) -> String {
+        chrono::DateTime::from_timestamp_millis((self.clock)())
+            .unwrap_or_default()
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string()
+    }
+}
+
+impl InMemoryState {
+    fn board_by_key(&self, board_key: &str) -> Option<&Board> {
+        self.boards.iter().find(|b| b.board_key == board_key)
+    }
+
+    fn thread_by_id_mut(&mut self, thread_id: &str) -> Option<&mut Thread> {
+        self.threads
+            .iter_mut()
+            .find(|t| t.id == thread_id && t.deleted == 0)
+    }
+
+    fn thread_position(&self, board_id: i32, thread_key: i64) -> Option<usize> {
+        self.threads
+            .iter()
+            .position(|t| t.board_id == board_id && t.thread_key == thread_key && t.deleted == 0)
+    }
+}
+
+#[async_trait(?Send)]
+impl BbsRepository for InMemoryBbsRepository {
+    async fn get_boards(&self) -> RepositoryResult<Vec<Board>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.boards.clone())
+    }
+
+    async fn get_board(&self, board_key: &str) -> RepositoryResult<Option<Board>> {
+        self.pass(FailPoint::Read)?;
+        let state = self.state.lock().unwrap();
+        Ok(state.board_by_key(board_key).cloned())
+    }
+
+    async fn get_threads(&self, board_key: &str) -> RepositoryResult<Vec<Thread>> {
+        self.pass(FailPoint::Read)?;
+        let state = self.state.lock().unwrap();
+        let Some(board) = state.board_by_key(board_key) else {
+            return Ok(Vec::new());
+        };
+
+        let mut threads = state
+            .threads
+            .iter()
+            .filter(|t| t.board_id == board.id && t.archived == 0 && t.deleted == 0)
+            .cloned()
+            .collect::<Vec<_>>();
+        threads.sort_by_key(|t| Reverse(t.update_unix_timestamp));
+        Ok(threads)
+    }
+
+    async fn get_archived_threads(&self, board_key: &str) -> RepositoryResult<Vec<Thread>> {
+        self.pass(FailPoint::Read)?;
+        let state = self.state.lock().unwrap();
+        let Some(board) = state.board_by_key(board_key) else {
+            return Ok(Vec::new());
+        };
+
+        let mut threads = state
+            .threads
+            .iter()
+            .filter(|t| t.board_id == board.id && t.archived == 1 && t.deleted == 0)
+            .cloned()
+            .collect::<Vec<_>>();
+        threads.sort_by_key(|t| Reverse(t.thread_key));
+        Ok(threads)
+    }
+
+    async fn get_thread(&self, board_key: &str, thread_key: i64) -> RepositoryResult<Thread> {
+        let state = self.state.lock().unwrap();
+        state
+            .board_by_key(board_key)
+            .and_then(|board| state.thread_position(board.id, thread_key))
+            .map(|idx| state.threads[idx].clone())
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn set_thread_stopped(&self, thread_id: &str, stopped: bool) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let thread = state
+            .thread_by_id_mut(thread_id)
+            .ok_or(RepositoryError::NotFound)?;
+        thread.stopped = stopped as i32;
+        thread.update_unix_timestamp = self.now();
+        Ok(())
+    }
+
+    async fn rename_thread(&self, thread_id: &str, title: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let thread = state
+            .thread_by_id_mut(thread_id)
+            .ok_or(RepositoryError::NotFound)?;
+        thread.title = title.to_string();
+        thread.update_unix_timestamp = self.now();
+        Ok(())
+    }
+
+    async fn move_thread(&self, thread_id: &str, to_board_id: i32) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let (from_board_id, thread_key) = state
+            .thread_by_id_mut(thread_id)
+            .map(|t| (t.board_id, t.thread_key))
+            .ok_or(RepositoryError::NotFound)?;
+        if from_board_id == to_board_id {
+            return Ok(());
+        }
+        if state.thread_position(to_board_id, thread_key).is_some() {
+            return Err(RepositoryError::Conflict(anyhow::anyhow!(
+                "thread key {thread_key} already exists on board {to_board_id}"
+            )));
+        }
+
+        if let Some(thread) = state.thread_by_id_mut(thread_id) {
+            thread.board_id = to_board_id;
+        }
+        let redirects = &mut state.thread_redirects;
+        redirects.retain(|(from, _)| *from != (to_board_id, thread_key));
+        for ((_, key), to) in redirects.iter_mut() {
+            if *key == thread_key && *to == from_board_id {
+                *to = to_board_id;
+            }
+        }
+        redirects.retain(|(from, _)| *from != (from_board_id, thread_key));
+        redirects.push(((from_board_id, thread_key), to_board_id));
+        Ok(())
+    }
+
+    async fn delete_thread(&self, thread_id: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let thread = state
+            .thread_by_id_mut(thread_id)
+            .ok_or(RepositoryError::NotFound)?;
+        thread.deleted = 1;
+        Ok(())
+    }
+
+    async fn get_thread_redirect(
+        &self,
+        board_id: i32,
+        thread_key: i64,
+    ) -> RepositoryResult<Option<i32>> {
+        self.pass(FailPoint::Read)?;
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .thread_redirects
+            .iter()
+            .find(|(from, _)| *from == (board_id, thread_key))
+            .map(|(_, to)| *to))
+    }
+
+    async fn get_thread_with_responses(
+        &self,
+        board_key: &str,
+        thread_key: i64,
+    ) -> RepositoryResult<(Thread, Vec<Res>)> {
+        self.pass(FailPoint::Read)?;
+        let state = self.state.lock().unwrap();
+        let thread = state
+            .board_by_key(board_key)
+            .and_then(|board| state.thread_position(board.id, thread_key))
+            .map(|idx| state.threads[idx].clone())
+            .ok_or(RepositoryError::NotFound)?;
+
+        let responses = state
+            .responses
+            .iter()
+            .filter(|r| r.thread_id == thread.id)
+            .cloned()
+            .collect();
+
+        Ok((thread, responses))
+    }
+
+    async fn get_recent_responses_by_author(
+        &self,
+        user_hash: &str,
+        ip_addr: &str,
+        since: i64,
+    ) -> RepositoryResult<Vec<Res>> {
+        let state = self.state.lock().unwrap();
+        let mut responses = state
+            .responses
+            .iter()
+            .filter(|r| r.user_id == user_hash || r.ip_address == ip_addr)
+            .filter(|r| {
+                chrono::NaiveDateTime::parse_from_str(&r.created_at, "%Y-%m-%d %H:%M:%S")
+                    .is_ok_and(|x| x.and_utc().timestamp() >= since)
+            })
+            .cloned()
+            .collect::<Vec<_>>();
+        responses.reverse();
+        Ok(responses)
+    }
+
+    async fn create_duplicate_post(&self, post: CreatingDuplicatePost) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let id = uuid::Uuid::new_v4().to_string();
+        state.duplicate_posts.push((post, id, self.now_text()));
+        Ok(())
+    }
+
+    async fn get_duplicate_posts(
+        &self,
+        filter: DuplicatePostFilter,
+    ) -> RepositoryResult<Vec<DuplicatePost>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .duplicate_posts
+            .iter()
+            .rev()
+            .filter_map(|(post, id, created_at)| {
+                let board = state.boards.iter().find(|b| b.id == post.board_id)?;
+                Some(DuplicatePost {
+                    id: id.clone(),
+                    board_key: board.board_key.clone(),
+                    thread_key: post.thread_key.unwrap_or(0),
+                    body: post.body.clone(),
+                    original_response_id: post.original_response_id.clone(),
+                    ip_address: post.ip_addr.clone(),
+                    user_id: post.user_hash.clone(),
+                    created_at: created_at.clone(),
+                })
+            })
+            .filter(|x| filter.board_key.is_empty() || x.board_key == filter.board_key)
+            .filter(|x| filter.user_hash.is_empty() || x.user_id == filter.user_hash)
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .collect())
+    }
+
+    async fn set_response_deleted(&self, response_id: &str, deleted: bool) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let response = state
+            .responses
+            .iter_mut()
+            .find(|r| r.id == response_id)
+            .ok_or(RepositoryError::NotFound)?;
+        response.deleted = deleted as i32;
+        response.edited_at = self.now();
+        Ok(())
+    }
+
+    async fn get_ng_words(&self) -> RepositoryResult<Vec<NgWord>> {
+        let state = self.state.lock().unwrap();
+        Ok(state.ng_words.clone())
+    }
+
+    async fn create_held_post(&self, post: CreatingPost, ng_word_id: i32) -> RepositoryResult<()> {
+        let id = uuid::Uuid::new_v4().to_string();
+        let created_at = self.now_text();
+        let post = match post {
+            CreatingPost::Thread(thread) => HeldPost {
+                id,
+                board_id: thread.board_id,
+                thread_key: 0,
+                title: thread.title,
+                name: thread.name,
+                mail: thread.mail,
+                body: thread.body,
+                author_id: thread.author_id,
+                date_text: thread.date,
+                ip_address: thread.ip_addr,
+                user_id: thread.user_hash,
+                ng_word_id,
+                cap_id: thread.cap_id,
+                created_at,
+            },
+            CreatingPost::Response(response) => HeldPost {
+                id,
+                board_id: response.board_id,
+                thread_key: response.thread_key,
+                title: String::new(),
+                name: response.name,
+                mail: response.mail,
+                body: response.body,
+                author_id: response.author_id,
+                date_text: response.date,
+                ip_address: response.ip_addr,
+                user_id: response.user_hash,
+                ng_word_id,
+                cap_id: response.cap_id,
+                created_at,
+            },
+        };
+        self.state.lock().unwrap().held_posts.push(post);
+        Ok(())
+    }
+
+    async fn get_held_posts(&self, limit: u64, offset: u64) -> RepositoryResult<Vec<HeldPost>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .held_posts
+            .iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_held_post(&self, id: &str) -> RepositoryResult<HeldPost> {
+        let state = self.state.lock().unwrap();
+        state
+            .held_posts
+            .iter()
+            .find(|x| x.id == id)
+            .cloned()
+            .ok_or(RepositoryError::NotFound)
+    }
+
+    async fn delete_held_post(&self, id: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.held_posts.retain(|x| x.id != id);
+        Ok(())
+    }
+
+    async fn get_caps(&self) -> RepositoryResult<Vec<Cap>> {
+        Ok(self.state.lock().unwrap().caps.clone())
+    }
+
+    async fn get_ip_bans(&self) -> RepositoryResult<Vec<IpBan>> {
+        let now = self.now();
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .ip_bans
+            .iter()
+            .rev()
+            .filter(|b| b.expires_at == 0 || b.expires_at > now)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_ip_ban(&self, ban: CreatingIpBan) -> RepositoryResult<String> {
+        let mut state = self.state.lock().unwrap();
+        let id = uuid::Uuid::new_v4().to_string();
+        state.ip_bans.push(IpBan {
+            id: id.clone(),
+            cidr: ban.cidr,
+            board_id: ban.board_id,
+            reason: ban.reason,
+            expires_at: ban.expires_at,
+            created_at: self.now_text(),
+        });
+        Ok(id)
+    }
+
+    async fn delete_ip_ban(&self, id: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.ip_bans.retain(|b| b.id != id);
+        Ok(())
+    }
+
+    async fn create_report(&self, report: CreatingReport) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state
+            .reports
+            .iter()
+            .any(|(x, ..)| x.response_id == report.response_id && x.user_hash == report.user_hash)
+        {
+            let id = uuid::Uuid::new_v4().to_string();
+            state.reports.push((report, id, self.now_text(), false));
+        }
+        Ok(())
+    }
+
+    async fn get_unresolved_reports(&self) -> RepositoryResult<Vec<Report>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .reports
+            .iter()
+            .rev()
+            .filter(|(.., resolved)| !resolved)
+            .filter_map(|(report, id, created_at, _)| {
+                let res = state
+                    .responses
+                    .iter()
+                    .find(|r| r.id == report.response_id)?;
+                let thread = state.threads.iter().find(|t| t.id == res.thread_id)?;
+                let board = state.boards.iter().find(|b| b.id == thread.board_id)?;
+                Some(Report {
+                    id: id.clone(),
+                    response_id: report.response_id.clone(),
+                    board_key: board.board_key.clone(),
+                    thread_key: thread.thread_key,
+                    response_number: report.response_number,
+                    reporter_hash: report.user_hash.clone(),
+                    reason: report.reason.clone(),
+                    created_at: created_at.clone(),
+                    body: res.body.clone(),
+                    author_hash: res.user_id.clone(),
+                    deleted: res.deleted,
+                })
+            })
+            .collect())
+    }
+
+    async fn resolve_reports(&self, response_id: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        for (report, .., resolved) in state.reports.iter_mut() {
+            if report.response_id == response_id {
+                *resolved = true;
+            }
+        }
+        Ok(())
+    }
+
+    async fn create_audit_log(&self, log: CreatingAuditLog) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.audit_logs.push(AuditLog {
+            id: uuid::Uuid::new_v4().to_string(),
+            actor: log.actor,
+            action: log.action.to_string(),
+            board_key: log.board_key,
+            thread_key: log.thread_key,
+            response_number: log.response_number,
+            user_hash: log.user_hash,
+            reason: log.reason,
+            detail: log.detail,
+            created_at: self.now_text(),
+        });
+        Ok(())
+    }
+
+    async fn get_audit_logs(&self, filter: AuditLogFilter) -> RepositoryResult<Vec<AuditLog>> {
+        let state = self.state.lock().unwrap();
+        let matches = |value: &str, cond: &str| cond.is_empty() || value == cond;
+        Ok(state
+            .audit_logs
+            .iter()
+            .rev()
+            .filter(|x| matches(&x.actor, &filter.actor))
+            .filter(|x| matches(&x.action, &filter.action))
+            .filter(|x| matches(&x.board_key, &filter.board_key))
+            .filter(|x| filter.thread_key == 0 || x.thread_key == filter.thread_key)
+            .filter(|x| matches(&x.user_hash, &filter.user_hash))
+            .skip(filter.offset as usize)
+            .take(filter.limit as usize)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_user(&self, user_hash: &str) -> RepositoryResult<Option<User>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .users
+            .iter()
+            .find(|u| u.user_hash == user_hash)
+            .cloned())
+    }
+
+    async fn get_user_by_derived_hash(&self, derived_hash: &str) -> RepositoryResult<Option<User>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .users
+            .iter()
+            .find(|u| !derived_hash.is_empty() && u.derived_hash == derived_hash)
+            .cloned())
+    }
+
+    async fn create_user(&self, user_hash: &str, ip_addr: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        state.users.push(User {
+            id: uuid::Uuid::new_v4().to_string(),
+            ip_address: ip_addr.to_string(),
+            created_at: self.now_text(),
+            disabled: 0,
+            user_hash: user_hash.to_string(),
+            disabled_reason: String::new(),
+            disabled_until: 0,
+            derived_hash: derive_user_hash(user_hash),
+        });
+        Ok(())
+    }
+
+    async fn set_derived_user_hash(&self, user_hash: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(user) = state.users.iter_mut().find(|u| u.user_hash == user_hash) {
+            user.derived_hash = derive_user_hash(user_hash);
+        }
+        Ok(())
+    }
+
+    async fn disable_user(
+        &self,
+        user_hash: &str,
+        reason: &str,
+        until: i64,
+    ) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(user) = state.users.iter_mut().find(|u| u.user_hash == user_hash) {
+            user.disabled = 1;
+            user.disabled_reason = reason.to_string();
+            user.disabled_until = until;
+        }
+        Ok(())
+    }
+
+    async fn enable_user(&self, user_hash: &str) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(user) = state.users.iter_mut().find(|u| u.user_hash == user_hash) {
+            user.disabled = 0;
+            user.disabled_reason = String::new();
+            user.disabled_until = 0;
+        }
+        Ok(())
+    }
+
+    async fn get_posts_by_user(
+        &self,
+        user_hash: &str,
+        limit: u64,
+        offset: u64,
+    ) -> RepositoryResult<Vec<UserPost>> {
+        let state = self.state.lock().unwrap();
+        Ok(state
+            .responses
+            .iter()
+            .rev()
+            .filter(|r| r.user_id == user_hash)
+            .filter_map(|r| {
+                let thread = state.threads.iter().find(|t| t.id == r.thread_id)?;
+                let board = state.boards.iter().find(|b| b.id == thread.board_id)?;
+                Some(UserPost {
+                    id: r.id.clone(),
+                    board_key: board.board_key.clone(),
+                    thread_key: thread.thread_key,
+                    thread_title: thread.title.clone(),
+                    name: r.name.clone(),
+                    mail: r.mail.clone(),
+                    body: r.body.clone(),
+                    author_id: r.author_id.clone(),
+                    date_text: r.date_text.clone(),
+                    ip_address: r.ip_address.clone(),
+                    created_at: r.created_at.clone(),
+                    deleted: r.deleted,
+                })
+            })
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect())
+    }
+
+    async fn create_thread(&self, thread: CreatingThread) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        if !state.boards.iter().any(|b| b.id == thread.board_id) {
+            return Err(RepositoryError::NotFound);
+        }
+
+        let thread_key = self.now();
+        let thread_id = uuid::Uuid::new_v4().to_string();
+        let created_at = self.now_text();
+
+        let mut staged = state.clone();
+        staged.threads.push(Thread {
+            id: thread_id.clone(),
+            thread_key,
+            board_id: thread.board_id,
+            title: thread.title,
+            response_count: 1,
+            ip_address: thread.ip_addr.clone(),
+            user_id: thread.user_hash.clone(),
+            created_at: created_at.clone(),
+            update_unix_timestamp: thread_key,
+            author_id: thread.author_id.clone(),
+            stopped: 0,
+            archived: 0,
+            deleted: 0,
+        });
+        staged.responses.push(Res {
+            id: uuid::Uuid::new_v4().to_string(),
+            thread_id,
+            name: thread.name,
+            mail: thread.mail,
+            body: thread.body,
+            author_id: thread.author_id,
+            date_text: thread.date,
+            ip_address: thread.ip_addr,
+            user_id: thread.user_hash,
+            created_at,
+            deleted: 0,
+            edited_at: 0,
+            cap_id: thread.cap_id,
+        });
+        self.pass(FailPoint::Insert)?;
+
+        let mut live = staged
+            .threads
+            .iter_mut()
+            .filter(|t| t.board_id == thread.board_id && t.archived == 0 && t.deleted == 0)
+            .collect::<Vec<_>>();
+        let overflow = live
+            .len()
+            .saturating_sub(thread.max_thread_count.max(0) as usize);
+        live.sort_by_key(|t| t.update_unix_timestamp);
+        for t in live.into_iter().take(overflow) {
+            t.archived = 1;
+        }
+        self.pass(FailPoint::CountUpdate)?;
+
+        self.pass(FailPoint::Commit)?;
+        *state = staged;
+        Ok(())
+    }
+
+    async fn create_response(&self, response: CreatingResponse) -> RepositoryResult<()> {
+        let mut state = self.state.lock().unwrap();
+        let idx = state
+            .thread_position(response.board_id, response.thread_key)
+            .ok_or(RepositoryError::NotFound)?;
+
+        let thread = &state.threads[idx];
+        if (thread.stopped == 1 && !response.allow_stopped)
+            || thread.archived == 1
+            || thread.response_count >= response.max_response_count
+        {
+            return Err(RepositoryError::ThreadStopped);
+        }
+
+        let mut staged = state.clone();
+        let is_sage = response.is_sage();
+        let max_response_count = response.max_response_count;
+        staged.responses.push(Res {
+            id: uuid::Uuid::new_v4().to_string(),
+            thread_id: thread.id.clone(),
+            name: response.name,
+            mail: response.mail,
+            body: response.body,
+            author_id: response.author_id,
+            date_text: response.date,
+            ip_address: response.ip_addr,
+            user_id: response.user_hash,
+            created_at: self.now_text(),
+            deleted: 0,
+            edited_at: 0,
+            cap_id: response.cap_id,
+        });
+        self.pass(FailPoint::Insert)?;
+
+        let thread = &mut staged.threads[idx];
+        thread.response_count += 1;
+        if thread.response_count >= max_response_count {
+            thread.stopped = 1;
+        }
+        if !is_sage {
+            thread.update_unix_timestamp = self.now();
+        }
+        self.pass(FailPoint::CountUpdate)?;
+
+        self.pass(FailPoint::Commit)?;
+        *state = staged;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::bbs_repository::fixtures::{
+        board, creating_response, creating_thread, fixed_clock, BOARD_KEY, NOW_MILLIS, TRICKY_TEXTS,
+    };
+
+    #[test]
+    fn timestamps_come_from_the_injected_clock() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+        let thread_key = NOW_MILLIS / 1000;
+        block_on(repo.create_response(creating_response(thread_key, "レス"))).unwrap();
+
+        let (thread, responses) =
+            block_on(repo.get_thread_with_responses(BOARD_KEY, thread_key)).unwrap();
+        assert_eq!(thread.thread_key, thread_key);
+        assert_eq!(thread.update_unix_timestamp, thread_key);
+        assert_eq!(thread.response_count, 2);
+        assert!(responses
+            .iter()
+            .all(|r| r.created_at == "2023-11-14 22:13:20"));
+    }
+
+    #[test]
+    fn stores_tricky_texts_as_given() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        let thread_key = NOW_MILLIS / 1000;
+        block_on(repo.create_thread(creating_thread(TRICKY_TEXTS[0]))).unwrap();
+        for text in TRICKY_TEXTS {
+            block_on(repo.create_response(CreatingResponse {
+                name: text.to_string(),
+                mail: text.to_string(),
+                ..creating_response(thread_key, text)
+            }))
+            .unwrap();
+        }
+
+        let (thread, responses) =
+            block_on(repo.get_thread_with_responses(BOARD_KEY, thread_key)).unwrap();
+        assert_eq!(thread.title, TRICKY_TEXTS[0]);
+        for (response, text) in responses[1..].iter().zip(TRICKY_TEXTS) {
+            assert_eq!(
+                (&*response.name, &*response.mail, &*response.body),
+                (text, text, text)
+            );
+        }
+        assert_eq!(responses.len(), TRICKY_TEXTS.len() + 1);
+    }
+
+    #[test]
+    fn stopping_and_renaming_bump_the_thread() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+        let thread_key = NOW_MILLIS / 1000;
+        let thread_id = block_on(repo.get_thread(BOARD_KEY, thread_key)).unwrap().id;
+        let updated_at = || {
+            block_on(repo.get_thread(BOARD_KEY, thread_key))
+                .unwrap()
+                .update_unix_timestamp
+        };
+        let backdate = || repo.state.lock().unwrap().threads[0].update_unix_timestamp = 0;
+
+        backdate();
+        block_on(repo.set_thread_stopped(&thread_id, true)).unwrap();
+        assert_eq!(updated_at(), thread_key);
+
+        backdate();
+        block_on(repo.rename_thread(&thread_id, "新スレタイ")).unwrap();
+        assert_eq!(updated_at(), thread_key);
+    }
+
+    #[test]
+    fn replies_bump_the_thread_unless_sage() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+        let thread_key = NOW_MILLIS / 1000;
+        let updated_at = || {
+            block_on(repo.get_thread(BOARD_KEY, thread_key))
+                .unwrap()
+                .update_unix_timestamp
+        };
+        let backdate = || repo.state.lock().unwrap().threads[0].update_unix_timestamp = 0;
+
+        for mail in ["sage", "SAGE!sage", "#token"] {
+            backdate();
+            let response = CreatingResponse {
+                mail: mail.to_string(),
+                ..creating_response(thread_key, "レス")
+            };
+            block_on(repo.create_response(response)).unwrap();
+            let expected = if mail.contains("sage") { 0 } else { thread_key };
+            assert_eq!(updated_at(), expected, "{mail}");
+        }
+    }
+
+    #[test]
+    fn full_threads_stop_and_refuse_responses() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+        let thread_key = NOW_MILLIS / 1000;
+        let response = |allow_stopped| CreatingResponse {
+            max_response_count: 3,
+            allow_stopped,
+            ..creating_response(thread_key, "レス")
+        };
+        let thread = || block_on(repo.get_thread(BOARD_KEY, thread_key)).unwrap();
+
+        block_on(repo.create_response(response(false))).unwrap();
+        assert_eq!(thread().stopped, 0);
+        // The response reaching the limit is stored and stops the thread
+        block_on(repo.create_response(response(false))).unwrap();
+        assert_eq!(thread().response_count, 3);
+        assert_eq!(thread().stopped, 1);
+
+        // Not even a cap allowed to post in stopped threads gets past the limit
+        for allow_stopped in [false, true] {
+            assert!(matches!(
+                block_on(repo.create_response(response(allow_stopped))),
+                Err(RepositoryError::ThreadStopped)
+            ));
+        }
+        assert_eq!(thread().response_count, 3);
+    }
+
+    /// Everything a failed write could have left behind
+    fn snapshot(repo: &InMemoryBbsRepository) -> String {
+        format!("{:?}", repo.state.lock().unwrap())
+    }
+
+    const WRITE_FAIL_POINTS: [FailPoint; 3] =
+        [FailPoint::Insert, FailPoint::CountUpdate, FailPoint::Commit];
+
+    #[test]
+    fn failed_thread_creation_leaves_nothing_behind() {
+        for point in WRITE_FAIL_POINTS {
+            let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+            let before = snapshot(&repo);
+
+            repo.fail_at(Some(point));
+            let result = block_on(repo.create_thread(creating_thread("スレタイ")));
+            assert!(
+                matches!(result, Err(RepositoryError::Unavailable(_))),
+                "{point:?}"
+            );
+            assert_eq!(snapshot(&repo), before, "{point:?}");
+
+            repo.fail_at(None);
+            block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+            assert_eq!(repo.state.lock().unwrap().threads.len(), 1, "{point:?}");
+        }
+    }
+
+    #[test]
+    fn failed_thread_creation_does_not_archive() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        block_on(repo.create_thread(creating_thread("古いスレ"))).unwrap();
+        let before = snapshot(&repo);
+
+        repo.fail_at(Some(FailPoint::Commit));
+        let thread = CreatingThread {
+            max_thread_count: 1,
+            ..creating_thread("新しいスレ")
+        };
+        assert!(block_on(repo.create_thread(thread)).is_err());
+        assert_eq!(snapshot(&repo), before);
+    }
+
+    #[test]
+    fn failed_response_leaves_nothing_behind() {
+        let thread_key = NOW_MILLIS / 1000;
+        for point in WRITE_FAIL_POINTS {
+            let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+            block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+            let before = snapshot(&repo);
+
+            repo.fail_at(Some(point));
+            // The response would fill the thread, so `stopped` would be updated too
+            let response = CreatingResponse {
+                max_response_count: 2,
+                ..creating_response(thread_key, "レス")
+            };
+            let result = block_on(repo.create_response(response));
+            assert!(
+                matches!(result, Err(RepositoryError::Unavailable(_))),
+                "{point:?}"
+            );
+            assert_eq!(snapshot(&repo), before, "{point:?}");
+        }
+    }
+
+    #[test]
+    fn queues_held_posts_oldest_first_until_deleted() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        let thread = CreatingPost::Thread(creating_thread("保留スレ"));
+        block_on(repo.create_held_post(thread, 1)).unwrap();
+        let response = CreatingPost::Response(creating_response(1_700_000_000, "保留レス"));
+        block_on(repo.create_held_post(response, 2)).unwrap();
+
+        let held = block_on(repo.get_held_posts(10, 0)).unwrap();
+        let summary = held
+            .iter()
+            .map(|x| {
+                (
+                    x.thread_key,
+                    x.title.as_str(),
+                    x.body.as_str(),
+                    x.ng_word_id,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            [
+                (0, "保留スレ", "本文", 1),
+                (1_700_000_000, "", "保留レス", 2)
+            ]
+        );
+        assert_eq!(block_on(repo.get_held_posts(10, 1)).unwrap().len(), 1);
+
+        let id = &held[0].id;
+        assert_eq!(block_on(repo.get_held_post(id)).unwrap().title, "保留スレ");
+        block_on(repo.delete_held_post(id)).unwrap();
+        assert!(matches!(
+            block_on(repo.get_held_post(id)),
+            Err(RepositoryError::NotFound)
+        ));
+        assert_eq!(block_on(repo.get_held_posts(10, 0)).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn lists_duplicate_posts_newest_first() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        for (body, user_hash) in [("1", "a"), ("2", "b"), ("3", "a")] {
+            let post = CreatingDuplicatePost {
+                board_id: board().id,
+                thread_key: None,
+                body: body.to_string(),
+                original_response_id: String::new(),
+                ip_addr: "192.0.2.1".to_string(),
+                user_hash: user_hash.to_string(),
+            };
+            block_on(repo.create_duplicate_post(post)).unwrap();
+        }
+
+        let bodies = |filter: DuplicatePostFilter| {
+            block_on(repo.get_duplicate_posts(filter))
+                .unwrap()
+                .into_iter()
+                .map(|x| x.body)
+                .collect::<Vec<_>>()
+        };
+        let all = DuplicatePostFilter {
+            limit: 10,
+            ..Default::default()
+        };
+        assert_eq!(bodies(all.clone()), ["3", "2", "1"]);
+        assert_eq!(
+            bodies(DuplicatePostFilter {
+                user_hash: "a".to_string(),
+                ..all.clone()
+            }),
+            ["3", "1"]
+        );
+        assert_eq!(
+            bodies(DuplicatePostFilter {
+                board_key: "other".to_string(),
+                ..all
+            }),
+            Vec::<String>::new()
+        );
+    }
+
+    #[test]
+    fn finds_users_by_derived_hash() {
+        let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+        let token = "0123456789abcdef01234567";
+        block_on(repo.create_user(token, "192.0.2.1")).unwrap();
+        let user = block_on(repo.get_user_by_derived_hash(&derive_user_hash(token)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(user.user_hash, token);
+        assert!(block_on(repo.get_user_by_derived_hash(token))
+            .unwrap()
+            .is_none());
+
+        // Users created before derived hashes were stored are found once it is set
+        repo.state.lock().unwrap().users[0].derived_hash.clear();
+        assert!(block_on(repo.get_user_by_derived_hash(""))
+            .unwrap()
+            .is_none());
+        block_on(repo.set_derived_user_hash(token)).unwrap();
+        assert!(
+            block_on(repo.get_user_by_derived_hash(&derive_user_hash(token)))
+                .unwrap()
+                .is_some()
+        );
+    }
+}
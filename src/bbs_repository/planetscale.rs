@@ -0,0 +1,824 @@
+use std::{future::Future, sync::Arc};
+
+use async_trait::async_trait;
+use planetscale_driver::PSConnection;
+use tokio::sync::Mutex;
+use worker::Date;
+
+use super::{
+    error::{OptionalExt, RepositoryError, RepositoryResult},
+    sql::SqlQuery,
+    AuditLogFilter, BbsRepository, CreatingAuditLog, CreatingDuplicatePost, CreatingIpBan,
+    CreatingPost, CreatingReport, CreatingResponse, CreatingThread, DuplicatePostFilter,
+};
+use crate::{
+    dtos::{
+        AuditLog, Board, Cap, DuplicatePost, HeldPost, IpBan, NgWord, Report, Res, Thread, User,
+        UserPost,
+    },
+    utils::derive_user_hash,
+};
+
+/// A UUIDv7 for a new row, so that ids sort in the order rows are created
+fn new_id() -> uuid::Uuid {
+    new_id_at(Date::now().as_millis())
+}
+
+/// A UUIDv7 carrying `millis` as its timestamp
+fn new_id_at(millis: u64) -> uuid::Uuid {
+    uuid::Uuid::new_v7(uuid::Timestamp::from_unix(
+        uuid::NoContext,
+        millis / 1000,
+        ((millis % 1000) * 1_000_000) as u32,
+    ))
+}
+
+#[derive(Clone)]
+pub struct PlanetScaleBbsRepository {
+    conn: PSConnection,
+}
+
+impl PlanetScaleBbsRepository {
+    pub fn new(conn: PSConnection) -> Self {
+        Self { conn }
+    }
+
+    /// Runs `f` between BEGIN and COMMIT, rolling back when it fails.
+    ///
+    /// The shared connection is reused by every request of this isolate, so the transaction
+    /// gets its own Vitess session to keep other requests' statements out of it.
+    async fn transaction<F, Fut>(&self, f: F) -> RepositoryResult<()>
+    where
+        F: FnOnce(PSConnection) -> Fut,
+        Fut: Future<Output = RepositoryResult<()>>,
+    {
+        let mut conn = self.conn.clone();
+        conn.session = Arc::new(Mutex::new(None));
+        conn.transaction(|conn| async move { f(conn).await.map_err(anyhow::Error::from) })
+            .await
+            .map_err(|e| match e.downcast::<RepositoryError>() {
+                Ok(e) => e,
+                Err(e) => RepositoryError::from_driver(e),
+            })
+    }
+}
+
+#[async_trait(?Send)]
+impl BbsRepository for PlanetScaleBbsRepository {
+    async fn get_boards(&self) -> RepositoryResult<Vec<Board>> {
+        SqlQuery::new(
+            "SELECT id, name, board_key, default_name, max_response_count, max_thread_count,
+                subject_max_length, name_max_length, mail_max_length, message_max_length,
+                message_max_lines, allow_unicode, duplicate_post_window, author_id_scheme
+            FROM boards;",
+        )
+        .fetch_all::<Board>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn get_board(&self, board_key: &str) -> RepositoryResult<Option<Board>> {
+        SqlQuery::new(
+            "SELECT id, name, board_key, default_name, max_response_count, max_thread_count,
+                subject_max_length, name_max_length, mail_max_length, message_max_length,
+                message_max_lines, allow_unicode, duplicate_post_window, author_id_scheme
+            FROM boards WHERE board_key = ? LIMIT 1;",
+        )
+        .bind(board_key)
+        .fetch_one::<Board>(&self.conn)
+        .await
+        .optional()
+    }
+
+    async fn get_threads(&self, board_key: &str) -> RepositoryResult<Vec<Thread>> {
+        SqlQuery::new(
+            "SELECT id, thread_key, board_id, title, response_count, ip_address, user_id,
+                created_at, update_unix_timestamp, author_id, stopped, archived,
+                deleted
+            FROM threads WHERE archived = 0 AND deleted = 0 AND board_id IN
+            (SELECT id FROM boards WHERE board_key = ?)
+            ORDER BY update_unix_timestamp DESC;",
+        )
+        .bind(board_key)
+        .fetch_all::<Thread>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn get_archived_threads(&self, board_key: &str) -> RepositoryResult<Vec<Thread>> {
+        SqlQuery::new(
+            "SELECT id, thread_key, board_id, title, response_count, ip_address, user_id,
+                created_at, update_unix_timestamp, author_id, stopped, archived,
+                deleted
+            FROM threads WHERE archived = 1 AND deleted = 0 AND board_id IN
+            (SELECT id FROM boards WHERE board_key = ?)
+            ORDER BY thread_key DESC;",
+        )
+        .bind(board_key)
+        .fetch_all::<Thread>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn get_thread(&self, board_key: &str, thread_key: i64) -> RepositoryResult<Thread> {
+        SqlQuery::new(
+            "SELECT id, thread_key, board_id, title, response_count, ip_address, user_id,
+                created_at, update_unix_timestamp, author_id, stopped, archived,
+                deleted
+            FROM threads WHERE thread_key = ? AND deleted = 0 AND board_id IN
+            (SELECT id FROM boards WHERE board_key = ?);",
+        )
+        .bind(thread_key)
+        .bind(board_key)
+        .fetch_one::<Thread>(&self.conn)
+        .await
+    }
+
+    async fn set_thread_stopped(&self, thread_id: &str, stopped: bool) -> RepositoryResult<()> {
+        SqlQuery::new("UPDATE threads SET stopped = ?, update_unix_timestamp = ? WHERE id = ?;")
+            .bind(stopped as i32)
+            .bind(Date::now().as_millis() / 1000)
+            .bind(thread_id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn rename_thread(&self, thread_id: &str, title: &str) -> RepositoryResult<()> {
+        SqlQuery::new("UPDATE threads SET title = ?, update_unix_timestamp = ? WHERE id = ?;")
+            .bind(title)
+            .bind(Date::now().as_millis() / 1000)
+            .bind(thread_id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn move_thread(&self, thread_id: &str, to_board_id: i32) -> RepositoryResult<()> {
+        let thread_id = thread_id.to_string();
+        self.transaction(move |conn| async move {
+            let thread = SqlQuery::new(
+                "SELECT id, thread_key, board_id, title, response_count, ip_address, user_id,
+                    created_at, update_unix_timestamp, author_id, stopped, archived,
+                    deleted
+                FROM threads WHERE id = ? AND deleted = 0 FOR UPDATE;",
+            )
+            .bind(&thread_id)
+            .fetch_one::<Thread>(&conn)
+            .await?;
+            if thread.board_id == to_board_id {
+                return Ok(());
+            }
+
+            let taken = SqlQuery::new(
+                "SELECT COUNT(*) FROM threads
+                WHERE board_id = ? AND thread_key = ? AND deleted = 0;",
+            )
+            .bind(to_board_id)
+            .bind(thread.thread_key)
+            .fetch_scalar::<i64>(&conn)
+            .await?;
+            if taken > 0 {
+                return Err(RepositoryError::Conflict(anyhow::anyhow!(
+                    "thread key {} already exists on board {to_board_id}",
+                    thread.thread_key
+                )));
+            }
+
+            SqlQuery::new("UPDATE threads SET board_id = ? WHERE id = ?;")
+                .bind(to_board_id)
+                .bind(&thread.id)
+                .execute(&conn)
+                .await?;
+            // The thread is back on a board it once left
+            SqlQuery::new("DELETE FROM thread_redirects WHERE board_id = ? AND thread_key = ?;")
+                .bind(to_board_id)
+                .bind(thread.thread_key)
+                .execute(&conn)
+                .await?;
+            // Older redirects skip the board the thread is leaving
+            SqlQuery::new(
+                "UPDATE thread_redirects SET to_board_id = ?
+                WHERE to_board_id = ? AND thread_key = ?;",
+            )
+            .bind(to_board_id)
+            .bind(thread.board_id)
+            .bind(thread.thread_key)
+            .execute(&conn)
+            .await?;
+            SqlQuery::new(
+                "INSERT INTO thread_redirects (board_id, thread_key, to_board_id)
+                VALUES (?, ?, ?)
+                ON DUPLICATE KEY UPDATE to_board_id = VALUES(to_board_id);",
+            )
+            .bind(thread.board_id)
+            .bind(thread.thread_key)
+            .bind(to_board_id)
+            .execute(&conn)
+            .await
+        })
+        .await
+    }
+
+    async fn delete_thread(&self, thread_id: &str) -> RepositoryResult<()> {
+        SqlQuery::new("UPDATE threads SET deleted = 1 WHERE id = ?;")
+            .bind(thread_id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn get_thread_redirect(
+        &self,
+        board_id: i32,
+        thread_key: i64,
+    ) -> RepositoryResult<Option<i32>> {
+        SqlQuery::new(
+            "SELECT to_board_id FROM thread_redirects WHERE board_id = ? AND thread_key = ?;",
+        )
+        .bind(board_id)
+        .bind(thread_key)
+        .fetch_scalar::<i32>(&self.conn)
+        .await
+        .optional()
+    }
+
+    async fn get_thread_with_responses(
+        &self,
+        board_key: &str,
+        thread_key: i64,
+    ) -> RepositoryResult<(Thread, Vec<Res>)> {
+        let thread = SqlQuery::new(
+            "SELECT id, thread_key, board_id, title, response_count, ip_address, user_id,
+                created_at, update_unix_timestamp, author_id, stopped, archived,
+                deleted
+            FROM threads WHERE thread_key = ? AND deleted = 0 AND board_id IN
+            (SELECT id FROM boards WHERE board_key = ?);",
+        )
+        .bind(thread_key)
+        .bind(board_key)
+        .fetch_one::<Thread>(&self.conn)
+        .await?;
+
+        let responses = SqlQuery::new(
+            "SELECT id, thread_id, name, mail, body, author_id, date_text, ip_address, user_id,
+                created_at, deleted, edited_at, cap_id
+            FROM responses WHERE thread_id = ? ORDER BY id;",
+        )
+        .bind(&thread.id)
+        .fetch_all::<Res>(&self.conn)
+        .await
+        .optional()?
+        .unwrap_or_default();
+
+        Ok((thread, responses))
+    }
+
+    async fn get_recent_responses_by_author(
+        &self,
+        user_hash: &str,
+        ip_addr: &str,
+        since: i64,
+    ) -> RepositoryResult<Vec<Res>> {
+        SqlQuery::new(
+            // Two lookups, each served by its own index, rather than one OR over both columns
+            "SELECT id, thread_id, name, mail, body, author_id, date_text, ip_address, user_id,
+                created_at, deleted, edited_at, cap_id
+            FROM (
+                (SELECT id, thread_id, name, mail, body, author_id, date_text, ip_address,
+                    user_id, created_at, deleted, edited_at, cap_id
+                FROM responses WHERE user_id = ? AND created_at >= FROM_UNIXTIME(?)
+                ORDER BY created_at DESC LIMIT 100)
+                UNION
+                (SELECT id, thread_id, name, mail, body, author_id, date_text, ip_address,
+                    user_id, created_at, deleted, edited_at, cap_id
+                FROM responses WHERE ip_address = ? AND created_at >= FROM_UNIXTIME(?)
+                ORDER BY created_at DESC LIMIT 100)
+            ) AS recent
+            ORDER BY created_at DESC LIMIT 100;",
+        )
+        .bind(user_hash)
+        .bind(since)
+        .bind(ip_addr)
+        .bind(since)
+        .fetch_all::<Res>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn create_duplicate_post(&self, post: CreatingDuplicatePost) -> RepositoryResult<()> {
+        let id = new_id();
+
+        // thread_key stays NULL for threads, which `?` cannot express
+        let query = match post.thread_key {
+            Some(thread_key) => SqlQuery::new(
+                "INSERT INTO duplicate_posts
+                (id, board_id, thread_key, body, original_response_id, ip_address, user_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(id)
+            .bind(post.board_id)
+            .bind(thread_key),
+            None => SqlQuery::new(
+                "INSERT INTO duplicate_posts
+                (id, board_id, thread_key, body, original_response_id, ip_address, user_id)
+            VALUES (?, ?, NULL, ?, ?, ?, ?);",
+            )
+            .bind(id)
+            .bind(post.board_id),
+        };
+        query
+            .bind(post.body)
+            .bind(post.original_response_id)
+            .bind(post.ip_addr)
+            .bind(post.user_hash)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn get_duplicate_posts(
+        &self,
+        filter: DuplicatePostFilter,
+    ) -> RepositoryResult<Vec<DuplicatePost>> {
+        SqlQuery::new(
+            "SELECT d.id, b.board_key, COALESCE(d.thread_key, 0), d.body, d.original_response_id,
+                d.ip_address, d.user_id, d.created_at
+            FROM duplicate_posts d JOIN boards b ON b.id = d.board_id
+            WHERE (? = '' OR b.board_key = ?) AND (? = '' OR d.user_id = ?)
+            ORDER BY d.created_at DESC, d.id DESC LIMIT ? OFFSET ?;",
+        )
+        .bind(&filter.board_key)
+        .bind(&filter.board_key)
+        .bind(&filter.user_hash)
+        .bind(&filter.user_hash)
+        .bind(filter.limit)
+        .bind(filter.offset)
+        .fetch_all::<DuplicatePost>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn set_response_deleted(&self, response_id: &str, deleted: bool) -> RepositoryResult<()> {
+        SqlQuery::new("UPDATE responses SET deleted = ?, edited_at = ? WHERE id = ?;")
+            .bind(deleted as i32)
+            .bind(Date::now().as_millis() / 1000)
+            .bind(response_id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn get_ng_words(&self) -> RepositoryResult<Vec<NgWord>> {
+        SqlQuery::new(
+            "SELECT id, board_id, pattern, is_regex, action, replacement
+            FROM ng_words ORDER BY id;",
+        )
+        .fetch_all::<NgWord>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn create_held_post(&self, post: CreatingPost, ng_word_id: i32) -> RepositoryResult<()> {
+        let id = new_id();
+
+        // A thread has a title but no key yet, a response the other way round
+        let query = match post {
+            CreatingPost::Thread(thread) => SqlQuery::new(
+                "INSERT INTO held_posts
+                (id, board_id, thread_key, title, name, mail, body, author_id, date_text,
+                    ip_address, user_id, cap_id, ng_word_id)
+            VALUES (?, ?, NULL, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(id)
+            .bind(thread.board_id)
+            .bind(thread.title)
+            .bind(thread.name)
+            .bind(thread.mail)
+            .bind(thread.body)
+            .bind(thread.author_id)
+            .bind(thread.date)
+            .bind(thread.ip_addr)
+            .bind(thread.user_hash)
+            .bind(thread.cap_id),
+            CreatingPost::Response(response) => SqlQuery::new(
+                "INSERT INTO held_posts
+                (id, board_id, thread_key, title, name, mail, body, author_id, date_text,
+                    ip_address, user_id, cap_id, ng_word_id)
+            VALUES (?, ?, ?, NULL, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(id)
+            .bind(response.board_id)
+            .bind(response.thread_key)
+            .bind(response.name)
+            .bind(response.mail)
+            .bind(response.body)
+            .bind(response.author_id)
+            .bind(response.date)
+            .bind(response.ip_addr)
+            .bind(response.user_hash)
+            .bind(response.cap_id),
+        };
+        query.bind(ng_word_id).execute(&self.conn).await
+    }
+
+    async fn get_held_posts(&self, limit: u64, offset: u64) -> RepositoryResult<Vec<HeldPost>> {
+        SqlQuery::new(
+            "SELECT id, board_id, COALESCE(thread_key, 0), COALESCE(title, ''), name, mail, body,
+                author_id, date_text, ip_address, user_id, ng_word_id, cap_id, created_at
+            FROM held_posts ORDER BY created_at, id LIMIT ? OFFSET ?;",
+        )
+        .bind(limit)
+        .bind(offset)
+        .fetch_all::<HeldPost>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn get_held_post(&self, id: &str) -> RepositoryResult<HeldPost> {
+        SqlQuery::new(
+            "SELECT id, board_id, COALESCE(thread_key, 0), COALESCE(title, ''), name, mail, body,
+                author_id, date_text, ip_address, user_id, ng_word_id, cap_id, created_at
+            FROM held_posts WHERE id = ?;",
+        )
+        .bind(id)
+        .fetch_one::<HeldPost>(&self.conn)
+        .await
+    }
+
+    async fn delete_held_post(&self, id: &str) -> RepositoryResult<()> {
+        SqlQuery::new("DELETE FROM held_posts WHERE id = ?;")
+            .bind(id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn get_caps(&self) -> RepositoryResult<Vec<Cap>> {
+        SqlQuery::new("SELECT id, name, password_hash, role, board_id FROM caps;")
+            .fetch_all::<Cap>(&self.conn)
+            .await
+            .optional()
+            .map(Option::unwrap_or_default)
+    }
+
+    async fn get_ip_bans(&self) -> RepositoryResult<Vec<IpBan>> {
+        SqlQuery::new(
+            "SELECT id, cidr, board_id, reason, expires_at, created_at
+            FROM ip_bans WHERE expires_at = 0 OR expires_at > ?
+            ORDER BY created_at DESC;",
+        )
+        .bind(Date::now().as_millis() / 1000)
+        .fetch_all::<IpBan>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn create_ip_ban(&self, ban: CreatingIpBan) -> RepositoryResult<String> {
+        let id = new_id().to_string();
+
+        SqlQuery::new(
+            "INSERT INTO ip_bans (id, cidr, board_id, reason, expires_at)
+            VALUES (?, ?, ?, ?, ?);",
+        )
+        .bind(&id)
+        .bind(ban.cidr)
+        .bind(ban.board_id)
+        .bind(ban.reason)
+        .bind(ban.expires_at)
+        .execute(&self.conn)
+        .await?;
+        Ok(id)
+    }
+
+    async fn delete_ip_ban(&self, id: &str) -> RepositoryResult<()> {
+        SqlQuery::new("DELETE FROM ip_bans WHERE id = ?;")
+            .bind(id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn create_report(&self, report: CreatingReport) -> RepositoryResult<()> {
+        let id = new_id();
+
+        // A duplicate hits the unique index on (response_id, user_id)
+        SqlQuery::new(
+            "INSERT IGNORE INTO reports (id, response_id, response_number, user_id, reason)
+            VALUES (?, ?, ?, ?, ?);",
+        )
+        .bind(id)
+        .bind(report.response_id)
+        .bind(report.response_number)
+        .bind(report.user_hash)
+        .bind(report.reason)
+        .execute(&self.conn)
+        .await
+    }
+
+    async fn get_unresolved_reports(&self) -> RepositoryResult<Vec<Report>> {
+        SqlQuery::new(
+            "SELECT p.id, p.response_id, b.board_key, t.thread_key, p.response_number, p.user_id,
+                p.reason, p.created_at, r.body, r.user_id, r.deleted
+            FROM reports p
+            JOIN responses r ON r.id = p.response_id
+            JOIN threads t ON t.id = r.thread_id
+            JOIN boards b ON b.id = t.board_id
+            WHERE p.resolved = 0
+            ORDER BY p.created_at DESC, p.id DESC;",
+        )
+        .fetch_all::<Report>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn resolve_reports(&self, response_id: &str) -> RepositoryResult<()> {
+        SqlQuery::new("UPDATE reports SET resolved = 1 WHERE response_id = ? AND resolved = 0;")
+            .bind(response_id)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn create_audit_log(&self, log: CreatingAuditLog) -> RepositoryResult<()> {
+        let id = new_id();
+
+        SqlQuery::new(
+            "INSERT INTO audit_logs
+                (id, actor, action, board_key, thread_key, response_number, user_hash, reason,
+                    detail)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?);",
+        )
+        .bind(id)
+        .bind(log.actor)
+        .bind(log.action)
+        .bind(log.board_key)
+        .bind(log.thread_key)
+        .bind(log.response_number)
+        .bind(log.user_hash)
+        .bind(log.reason)
+        .bind(log.detail)
+        .execute(&self.conn)
+        .await
+    }
+
+    async fn get_audit_logs(&self, filter: AuditLogFilter) -> RepositoryResult<Vec<AuditLog>> {
+        // Each condition is bound twice so that the statement stays static
+        SqlQuery::new(
+            "SELECT id, actor, action, board_key, thread_key, response_number, user_hash, reason,
+                detail, created_at
+            FROM audit_logs
+            WHERE (? = '' OR actor = ?) AND (? = '' OR action = ?)
+                AND (? = '' OR board_key = ?) AND (? = 0 OR thread_key = ?)
+                AND (? = '' OR user_hash = ?)
+            ORDER BY created_at DESC, id DESC LIMIT ? OFFSET ?;",
+        )
+        .bind(&filter.actor)
+        .bind(&filter.actor)
+        .bind(&filter.action)
+        .bind(&filter.action)
+        .bind(&filter.board_key)
+        .bind(&filter.board_key)
+        .bind(filter.thread_key)
+        .bind(filter.thread_key)
+        .bind(&filter.user_hash)
+        .bind(&filter.user_hash)
+        .bind(filter.limit)
+        .bind(filter.offset)
+        .fetch_all::<AuditLog>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn get_user(&self, user_hash: &str) -> RepositoryResult<Option<User>> {
+        SqlQuery::new(
+            "SELECT id, ip_address, created_at, disabled, user_hash, disabled_reason,
+                disabled_until, derived_hash
+            FROM users WHERE user_hash = ? LIMIT 1;",
+        )
+        .bind(user_hash)
+        .fetch_one::<User>(&self.conn)
+        .await
+        .optional()
+    }
+
+    async fn get_user_by_derived_hash(&self, derived_hash: &str) -> RepositoryResult<Option<User>> {
+        SqlQuery::new(
+            "SELECT id, ip_address, created_at, disabled, user_hash, disabled_reason,
+                disabled_until, derived_hash
+            FROM users WHERE derived_hash = ? LIMIT 1;",
+        )
+        .bind(derived_hash)
+        .fetch_one::<User>(&self.conn)
+        .await
+        .optional()
+    }
+
+    async fn create_user(&self, user_hash: &str, ip_addr: &str) -> RepositoryResult<()> {
+        let user_id = new_id();
+
+        SqlQuery::new(
+            "INSERT INTO users (user_hash, ip_address, id, derived_hash) VALUES (?, ?, ?, ?);",
+        )
+        .bind(user_hash)
+        .bind(ip_addr)
+        .bind(user_id)
+        .bind(derive_user_hash(user_hash))
+        .execute(&self.conn)
+        .await
+    }
+
+    async fn set_derived_user_hash(&self, user_hash: &str) -> RepositoryResult<()> {
+        SqlQuery::new("UPDATE users SET derived_hash = ? WHERE user_hash = ?;")
+            .bind(derive_user_hash(user_hash))
+            .bind(user_hash)
+            .execute(&self.conn)
+            .await
+    }
+
+    async fn disable_user(
+        &self,
+        user_hash: &str,
+        reason: &str,
+        until: i64,
+    ) -> RepositoryResult<()> {
+        SqlQuery::new(
+            "UPDATE users SET disabled = 1, disabled_reason = ?, disabled_until = ?
+            WHERE user_hash = ?;",
+        )
+        .bind(reason)
+        .bind(until)
+        .bind(user_hash)
+        .execute(&self.conn)
+        .await
+    }
+
+    async fn enable_user(&self, user_hash: &str) -> RepositoryResult<()> {
+        SqlQuery::new(
+            "UPDATE users SET disabled = 0, disabled_reason = '', disabled_until = 0
+            WHERE user_hash = ?;",
+        )
+        .bind(user_hash)
+        .execute(&self.conn)
+        .await
+    }
+
+    async fn get_posts_by_user(
+        &self,
+        user_hash: &str,
+        limit: u64,
+        offset: u64,
+    ) -> RepositoryResult<Vec<UserPost>> {
+        SqlQuery::new(
+            "SELECT r.id, b.board_key, t.thread_key, t.title, r.name, r.mail, r.body,
+                r.author_id, r.date_text, r.ip_address, r.created_at, r.deleted
+            FROM responses r
+            JOIN threads t ON t.id = r.thread_id
+            JOIN boards b ON b.id = t.board_id
+            WHERE r.user_id = ?
+            ORDER BY r.created_at DESC, r.id DESC LIMIT ? OFFSET ?;",
+        )
+        .bind(user_hash)
+        .bind(limit)
+        .bind(offset)
+        .fetch_all::<UserPost>(&self.conn)
+        .await
+        .optional()
+        .map(Option::unwrap_or_default)
+    }
+
+    async fn create_thread(&self, thread: CreatingThread) -> RepositoryResult<()> {
+        let thread_key = Date::now().as_millis() / 1000;
+        let thread_id = new_id();
+        let response_id = new_id_at(Date::now().as_millis() + 1);
+
+        self.transaction(move |conn| async move {
+            SqlQuery::new(
+                "INSERT INTO threads
+                (thread_key, board_id, title, ip_address, user_id, update_unix_timestamp, id, author_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(thread_key)
+            .bind(thread.board_id)
+            .bind(thread.title)
+            .bind(&thread.ip_addr)
+            .bind(&thread.user_hash)
+            .bind(thread_key)
+            .bind(thread_id)
+            .bind(&thread.author_id)
+            .execute(&conn)
+            .await?;
+
+            SqlQuery::new(
+                "INSERT INTO responses
+                (thread_id, name, mail, body, author_id, date_text, ip_address, user_id, id,
+                    cap_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(thread_id)
+            .bind(thread.name)
+            .bind(thread.mail)
+            .bind(thread.body)
+            .bind(thread.author_id)
+            .bind(thread.date)
+            .bind(thread.ip_addr)
+            .bind(thread.user_hash)
+            .bind(response_id)
+            .bind(thread.cap_id)
+            .execute(&conn)
+            .await?;
+
+            let live_count = SqlQuery::new(
+                "SELECT COUNT(*) FROM threads
+                WHERE board_id = ? AND archived = 0 AND deleted = 0;",
+            )
+            .bind(thread.board_id)
+            .fetch_scalar::<i64>(&conn)
+            .await?;
+            let overflow = live_count - thread.max_thread_count as i64;
+            if overflow > 0 {
+                SqlQuery::new(
+                    "UPDATE threads SET archived = 1 WHERE board_id = ? AND archived = 0 AND deleted = 0
+                    ORDER BY update_unix_timestamp ASC LIMIT ?;",
+                )
+                .bind(thread.board_id)
+                .bind(overflow)
+                .execute(&conn)
+                .await?;
+            }
+
+            Ok(())
+        })
+        .await
+    }
+
+    async fn create_response(&self, response: CreatingResponse) -> RepositoryResult<()> {
+        let is_sage = response.is_sage();
+        let updated_at = Date::now().as_millis() / 1000;
+        let response_id = new_id();
+
+        self.transaction(move |conn| async move {
+            // Locks the thread row so that concurrent posts are serialized on response_count
+            let thread = SqlQuery::new(
+                "SELECT id, thread_key, board_id, title, response_count, ip_address, user_id,
+                    created_at, update_unix_timestamp, author_id, stopped, archived,
+                    deleted
+                FROM threads WHERE thread_key = ? AND board_id = ? AND deleted = 0 FOR UPDATE;",
+            )
+            .bind(response.thread_key)
+            .bind(response.board_id)
+            .fetch_one::<Thread>(&conn)
+            .await?;
+            if (thread.stopped == 1 && !response.allow_stopped)
+                || thread.archived == 1
+                || thread.response_count >= response.max_response_count
+            {
+                return Err(RepositoryError::ThreadStopped);
+            }
+            // A thread stopped by a moderator stays stopped after a cap posts in it
+            let stopped = (thread.stopped == 1
+                || thread.response_count + 1 >= response.max_response_count)
+                as i32;
+
+            SqlQuery::new(
+                "INSERT INTO responses
+                (thread_id, name, mail, body, author_id, date_text, ip_address, user_id, id,
+                    cap_id)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            )
+            .bind(&thread.id)
+            .bind(response.name)
+            .bind(response.mail)
+            .bind(response.body)
+            .bind(response.author_id)
+            .bind(response.date)
+            .bind(response.ip_addr)
+            .bind(response.user_hash)
+            .bind(response_id)
+            .bind(response.cap_id)
+            .execute(&conn)
+            .await?;
+
+            let update = if is_sage {
+                SqlQuery::new(
+                    "UPDATE threads SET response_count = response_count + 1, stopped = ?
+                    WHERE id = ?;",
+                )
+                .bind(stopped)
+                .bind(&thread.id)
+            } else {
+                SqlQuery::new(
+                    "UPDATE threads SET response_count = response_count + 1, stopped = ?,
+                    update_unix_timestamp = ? WHERE id = ?;",
+                )
+                .bind(stopped)
+                .bind(updated_at)
+                .bind(&thread.id)
+            };
+            update.execute(&conn).await
+        })
+        .await
+    }
+}
@@ -0,0 +1,270 @@
+use planetscale_driver::{Deserializer, PSConnection, Parser};
+
+use super::error::{RepositoryError, RepositoryResult};
+
+/// A value bound to a `?` placeholder of [`SqlQuery`]
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Int(i64),
+    Text(String),
+}
+
+impl From<i64> for SqlValue {
+    fn from(value: i64) -> Self {
+        SqlValue::Int(value)
+    }
+}
+
+impl From<i32> for SqlValue {
+    fn from(value: i32) -> Self {
+        SqlValue::Int(value as i64)
+    }
+}
+
+impl From<u64> for SqlValue {
+    fn from(value: u64) -> Self {
+        SqlValue::Int(value as i64)
+    }
+}
+
+impl From<&str> for SqlValue {
+    fn from(value: &str) -> Self {
+        SqlValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for SqlValue {
+    fn from(value: String) -> Self {
+        SqlValue::Text(value)
+    }
+}
+
+impl From<&String> for SqlValue {
+    fn from(value: &String) -> Self {
+        SqlValue::Text(value.clone())
+    }
+}
+
+impl From<uuid::Uuid> for SqlValue {
+    fn from(value: uuid::Uuid) -> Self {
+        SqlValue::Text(value.to_string())
+    }
+}
+
+impl SqlValue {
+    fn write_literal(&self, out: &mut String) {
+        match self {
+            SqlValue::Int(n) => out.push_str(&n.to_string()),
+            SqlValue::Text(s) => {
+                out.push('\'');
+                for c in s.chars() {
+                    match c {
+                        '\0' => out.push_str("\\0"),
+                        '\'' => out.push_str("\\'"),
+                        '"' => out.push_str("\\\""),
+                        '\\' => out.push_str("\\\\"),
+                        '\n' => out.push_str("\\n"),
+                        '\r' => out.push_str("\\r"),
+                        '\x1a' => out.push_str("\\Z"),
+                        c => out.push(c),
+                    }
+                }
+                out.push('\'');
+            }
+        }
+    }
+}
+
+/// Query builder that substitutes `?` placeholders with escaped literals in a single pass.
+///
+/// The PlanetScale HTTP API has no server-side prepared statements, so this is the only place
+/// where values are turned into SQL. Unlike `planetscale_driver::query`, bound values are never
+/// rescanned, so `$1` or `?` inside a value is stored as is. Placeholders must not be quoted in
+/// the statement; text values get their quotes from [`SqlValue`].
+#[derive(Debug, Clone)]
+pub struct SqlQuery {
+    statement: &'static str,
+    values: Vec<SqlValue>,
+}
+
+impl SqlQuery {
+    pub fn new(statement: &'static str) -> Self {
+        Self {
+            statement,
+            values: Vec::new(),
+        }
+    }
+
+    pub fn bind<T: Into<SqlValue>>(mut self, value: T) -> Self {
+        self.values.push(value.into());
+        self
+    }
+
+    pub fn render(&self) -> anyhow::Result<String> {
+        let mut out = String::with_capacity(self.statement.len());
+        let mut values = self.values.iter();
+        for c in self.statement.chars() {
+            if c == '?' {
+                let value = values
+                    .next()
+                    .ok_or_else(|| anyhow::anyhow!("Error: too few values bound to query"))?;
+                value.write_literal(&mut out);
+            } else {
+                out.push(c);
+            }
+        }
+        if values.next().is_some() {
+            return Err(anyhow::anyhow!("Error: too many values bound to query"));
+        }
+        Ok(out)
+    }
+
+    pub async fn execute(self, conn: &PSConnection) -> RepositoryResult<()> {
+        let statement = self.render().map_err(RepositoryError::Driver)?;
+        conn.execute(&statement)
+            .await
+            .map_err(RepositoryError::from_driver)
+    }
+
+    pub async fn fetch_one<T: Deserializer>(self, conn: &PSConnection) -> RepositoryResult<T> {
+        let statement = self.render().map_err(RepositoryError::Driver)?;
+        conn.execute_raw(&statement)
+            .await
+            .and_then(|x| x.deserialize())
+            .map_err(RepositoryError::from_driver)
+    }
+
+    pub async fn fetch_all<T: Deserializer>(self, conn: &PSConnection) -> RepositoryResult<Vec<T>> {
+        let statement = self.render().map_err(RepositoryError::Driver)?;
+        conn.execute_raw(&statement)
+            .await
+            .and_then(|x| x.deserialize_multiple())
+            .map_err(RepositoryError::from_driver)
+    }
+
+    pub async fn fetch_scalar<T: Parser>(self, conn: &PSConnection) -> RepositoryResult<T> {
+        let statement = self.render().map_err(RepositoryError::Driver)?;
+        conn.execute_raw(&statement)
+            .await
+            .and_then(|x| x.deserialize_scalar())
+            .map_err(RepositoryError::from_driver)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bbs_repository::fixtures::TRICKY_TEXTS;
+
+    fn render_text(value: &str) -> String {
+        SqlQuery::new("SELECT ?;").bind(value).render().unwrap()
+    }
+
+    /// The string literals of `statement` as MySQL reads them back
+    fn read_text_literals(statement: &str) -> Vec<String> {
+        let mut literals = Vec::new();
+        let mut chars = statement.chars().peekable();
+        while let Some(c) = chars.next() {
+            if c != '\'' {
+                continue;
+            }
+            let mut literal = String::new();
+            loop {
+                match chars.next().expect("unterminated literal") {
+                    '\\' => literal.push(match chars.next().expect("dangling backslash") {
+                        '0' => '\0',
+                        'n' => '\n',
+                        'r' => '\r',
+                        'Z' => '\x1a',
+                        c => c,
+                    }),
+                    '\'' if chars.peek() == Some(&'\'') => {
+                        chars.next();
+                        literal.push('\'');
+                    }
+                    '\'' => break,
+                    c => literal.push(c),
+                }
+            }
+            literals.push(literal);
+        }
+        literals
+    }
+
+    #[test]
+    fn binds_values_in_order() {
+        let query = SqlQuery::new("SELECT * FROM t WHERE a = ? AND b = ? AND c = ?;")
+            .bind(1_i32)
+            .bind("x")
+            .bind(-2_i64);
+        assert_eq!(
+            query.render().unwrap(),
+            "SELECT * FROM t WHERE a = 1 AND b = 'x' AND c = -2;"
+        );
+    }
+
+    #[test]
+    fn escapes_quotes() {
+        assert_eq!(render_text("it's"), r"SELECT 'it\'s';");
+        assert_eq!(render_text(r#"say "hi""#), r#"SELECT 'say \"hi\"';"#);
+        assert_eq!(render_text("' OR '1'='1"), r"SELECT '\' OR \'1\'=\'1';");
+    }
+
+    #[test]
+    fn escapes_backslashes() {
+        assert_eq!(render_text(r"\"), r"SELECT '\\';");
+        // A trailing backslash must not escape the closing quote
+        assert_eq!(render_text(r"a\'"), r"SELECT 'a\\\'';");
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        assert_eq!(render_text("a\0b"), r"SELECT 'a\0b';");
+        assert_eq!(render_text("a\r\nb"), r"SELECT 'a\r\nb';");
+        assert_eq!(render_text("a\x1ab"), r"SELECT 'a\Zb';");
+    }
+
+    #[test]
+    fn keeps_like_wildcards() {
+        // Values are only compared with `=`, where `%` and `_` have no special meaning
+        assert_eq!(render_text("100%_done"), "SELECT '100%_done';");
+    }
+
+    #[test]
+    fn keeps_multibyte_characters() {
+        assert_eq!(render_text("日本語🍣"), "SELECT '日本語🍣';");
+        // The second byte of Shift_JIS "ソ" is 0x5c, but values stay UTF-8 until the driver
+        assert_eq!(render_text("ソ表"), "SELECT 'ソ表';");
+    }
+
+    #[test]
+    fn does_not_rescan_bound_values() {
+        let query = SqlQuery::new("SELECT ?, ?;").bind("?").bind("$1");
+        assert_eq!(query.render().unwrap(), "SELECT '?', '$1';");
+    }
+
+    #[test]
+    fn fails_on_too_few_values() {
+        let query = SqlQuery::new("SELECT ?, ?;").bind(1_i32);
+        assert!(query.render().is_err());
+        assert!(SqlQuery::new("SELECT ?;").render().is_err());
+    }
+
+    #[test]
+    fn fails_on_too_many_values() {
+        let query = SqlQuery::new("SELECT ?;").bind(1_i32).bind(2_i32);
+        assert!(query.render().is_err());
+        assert!(SqlQuery::new("SELECT 1;").bind(1_i32).render().is_err());
+    }
+
+    #[test]
+    fn tricky_texts_read_back_unchanged() {
+        let query = TRICKY_TEXTS.iter().fold(
+            SqlQuery::new("INSERT INTO t (a, b, c, d, e, f, n) VALUES (?, ?, ?, ?, ?, ?, ?);"),
+            |query, text| query.bind(*text),
+        );
+        let statement = query.bind(1_i32).render().unwrap();
+        assert!(statement.ends_with(", 1);"), "{statement}");
+        assert_eq!(read_text_literals(&statement), TRICKY_TEXTS);
+    }
+}
@@ -0,0 +1,178 @@
+use std::{str::FromStr, sync::OnceLock};
+
+use pwhash::bcrypt;
+use regex::Regex;
+use worker::console_error;
+
+use crate::dtos;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CapRole {
+    /// Limited to the board of the cap
+    BoardOwner,
+    Moderator,
+    Admin,
+}
+
+impl FromStr for CapRole {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "board_owner" => Ok(CapRole::BoardOwner),
+            "moderator" => Ok(CapRole::Moderator),
+            "admin" => Ok(CapRole::Admin),
+            _ => Err(()),
+        }
+    }
+}
+
+impl CapRole {
+    pub fn bypasses_rate_limit(self) -> bool {
+        true
+    }
+
+    /// Only threads stopped by a moderator; full and archived threads stay closed
+    pub fn can_post_in_stopped_thread(self) -> bool {
+        true
+    }
+
+    pub fn bypasses_ng_words(self) -> bool {
+        self == CapRole::Admin
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Cap {
+    pub id: i32,
+    pub name: String,
+    pub role: CapRole,
+    /// 0 for every board
+    pub board_id: i32,
+}
+
+impl Cap {
+    pub fn is_usable_on(&self, board_id: i32) -> bool {
+        self.board_id == 0 || self.board_id == board_id
+    }
+
+    /// The name of a post made with the cap, without the ★ that the DAT adds
+    pub fn display_name(&self, name: &str) -> String {
+        if name.is_empty() {
+            self.name.clone()
+        } else {
+            format!("{name}＠{}", self.name)
+        }
+    }
+}
+
+/// Caps with the bcrypt hashes of their passwords
+pub struct CapsCtx {
+    caps: Vec<(String, Cap)>,
+}
+
+impl CapsCtx {
+    /// Skips entries with an unknown role
+    pub fn new(caps: Vec<dtos::Cap>) -> Self {
+        let caps = caps
+            .into_iter()
+            .filter_map(|cap| {
+                let Ok(role) = cap.role.parse::<CapRole>() else {
+                    console_error!("unknown role of cap {}: {}", cap.id, cap.role);
+                    return None;
+                };
+                Some((
+                    cap.password_hash,
+                    Cap {
+                        id: cap.id,
+                        name: cap.name,
+                        role,
+                        board_id: cap.board_id,
+                    },
+                ))
+            })
+            .collect();
+        Self { caps }
+    }
+
+    /// The cap with the id if it is usable on the board and the password is its own. Only the
+    /// hash of that cap is checked, so a post costs one bcrypt verification at most.
+    pub fn find(&self, id: i32, password: &str, board_id: i32) -> Option<&Cap> {
+        if password.is_empty() {
+            return None;
+        }
+        self.caps
+            .iter()
+            .find(|(_, cap)| cap.id == id)
+            .filter(|(_, cap)| cap.is_usable_on(board_id))
+            .filter(|(password_hash, _)| bcrypt::verify(password, password_hash))
+            .map(|(_, cap)| cap)
+    }
+}
+
+/// Whether a mail segment has the shape of a user token (24 lowercase hex digits), which is
+/// never a cap password
+pub fn looks_like_user_token(segment: &str) -> bool {
+    segment.len() == 24
+        && segment
+            .bytes()
+            .all(|x| x.is_ascii_digit() || (b'a'..=b'f').contains(&x))
+}
+
+/// Folds the marker of cap posts out of a name that was not posted with a cap, including
+/// numeric character references which a browser would show as ★
+pub fn mask_cap_marker(name: &str) -> String {
+    static STAR_REF: OnceLock<Regex> = OnceLock::new();
+    let star_ref = STAR_REF.get_or_init(|| Regex::new(r"&#(0*9733|[Xx]0*2605);?").unwrap());
+
+    star_ref
+        .replace_all(&name.replace('★', "☆"), "☆")
+        .into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn caps_ctx() -> CapsCtx {
+        // The lowest cost keeps the tests fast; real hashes use the default
+        let hash = |password: &str| {
+            let setup = bcrypt::BcryptSetup {
+                cost: Some(4),
+                ..Default::default()
+            };
+            bcrypt::hash_with(setup, password).unwrap()
+        };
+        let cap = |id: i32, password: &str, board_id: i32| dtos::Cap {
+            id,
+            name: format!("cap{id}"),
+            password_hash: hash(password),
+            role: "moderator".to_string(),
+            board_id,
+        };
+        CapsCtx::new(vec![cap(1, "global", 0), cap(2, "board", 1)])
+    }
+
+    #[test]
+    fn finds_caps_usable_on_the_board_by_id_and_password() {
+        let caps = caps_ctx();
+        assert_eq!(caps.find(1, "global", 1).map(|x| x.id), Some(1));
+        assert_eq!(caps.find(1, "global", 2).map(|x| x.id), Some(1));
+        assert_eq!(caps.find(2, "board", 1).map(|x| x.id), Some(2));
+        assert!(caps.find(2, "board", 2).is_none());
+        assert!(caps.find(1, "board", 1).is_none());
+        assert!(caps.find(2, "global", 1).is_none());
+        assert!(caps.find(3, "global", 1).is_none());
+        assert!(caps.find(1, "wrong", 1).is_none());
+        assert!(caps.find(1, "", 1).is_none());
+    }
+
+    #[test]
+    fn tells_user_tokens_from_passwords() {
+        assert!(looks_like_user_token("0123456789abcdef01234567"));
+        assert!(!looks_like_user_token("0123456789ABCDEF01234567"));
+        assert!(!looks_like_user_token("0123456789abcdef0123456"));
+        assert!(!looks_like_user_token("0123456789abcdefg1234567"));
+        assert!(!looks_like_user_token("password"));
+    }
+}
@@ -1,14 +1,28 @@
 use planetscale_driver::Database;
 
-#[derive(Debug, Database)]
+#[derive(Debug, Clone, Database)]
 pub struct Board {
     pub id: i32,
     pub name: String,
     pub board_key: String,
     pub default_name: String,
+    pub max_response_count: i32,
+    pub max_thread_count: i32,
+    /// Limits below are Shift_JIS byte lengths, as in SETTING.TXT
+    pub subject_max_length: i32,
+    pub name_max_length: i32,
+    pub mail_max_length: i32,
+    pub message_max_length: i32,
+    pub message_max_lines: i32,
+    /// 1 keeps numeric character references as they are, 0 replaces them with "？"
+    pub allow_unicode: i32,
+    /// Seconds in which the same content from the same user or IP address is rejected, 0 disables
+    pub duplicate_post_window: i32,
+    /// `ip_daily`, `user_daily`, `ip_user_daily`, `none` or `forced`, see `AuthorIdScheme`
+    pub author_id_scheme: String,
 }
 
-#[derive(Debug, Database)]
+#[derive(Debug, Clone, Database)]
 pub struct Thread {
     pub id: String,
     pub thread_key: i64,
@@ -20,9 +34,12 @@ pub struct Thread {
     pub created_at: String,
     pub update_unix_timestamp: i64,
     pub author_id: String,
+    pub stopped: i32,
+    pub archived: i32,
+    pub deleted: i32,
 }
 
-#[derive(Debug, Database)]
+#[derive(Debug, Clone, Database)]
 pub struct Res {
     pub id: String,
     pub thread_id: String,
@@ -34,13 +51,157 @@ pub struct Res {
     pub ip_address: String,
     pub user_id: String,
     pub created_at: String,
+    /// 1 shows the response as "あぼーん" while keeping its number
+    pub deleted: i32,
+    /// unix timestamp (seconds) of the last deletion or restoration, 0 if never
+    pub edited_at: i64,
+    /// The cap the response was posted with, 0 if none
+    pub cap_id: i32,
 }
 
-#[derive(Debug, Database)]
+#[derive(Debug, Clone, Database)]
+pub struct NgWord {
+    pub id: i32,
+    /// 0 applies the entry to every board
+    pub board_id: i32,
+    pub pattern: String,
+    /// 1 treats `pattern` as a regular expression, 0 as a literal
+    pub is_regex: i32,
+    /// "reject", "replace" or "hold"
+    pub action: String,
+    /// Text put in place of the match when `action` is "replace"
+    pub replacement: String,
+}
+
+#[derive(Debug, Clone, Database)]
+pub struct Cap {
+    pub id: i32,
+    pub name: String,
+    /// bcrypt hash of the password
+    pub password_hash: String,
+    /// `board_owner`, `moderator` or `admin`
+    pub role: String,
+    /// 0 lets the cap post on every board
+    pub board_id: i32,
+}
+
+#[derive(Debug, Clone, Database)]
+pub struct IpBan {
+    pub id: String,
+    /// An address or a CIDR range, IPv4 or IPv6
+    pub cidr: String,
+    /// 0 bans the range on every board
+    pub board_id: i32,
+    pub reason: String,
+    /// unix timestamp (seconds) when the ban ends, 0 for a permanent ban
+    pub expires_at: i64,
+    pub created_at: String,
+}
+
+/// A report of a response, with the response it points at
+#[derive(Debug, Clone, Database)]
+pub struct Report {
+    pub id: String,
+    pub response_id: String,
+    pub board_key: String,
+    pub thread_key: i64,
+    pub response_number: i32,
+    /// Hash of the user who reported
+    pub reporter_hash: String,
+    pub reason: String,
+    pub created_at: String,
+    pub body: String,
+    /// Hash of the user who posted the response
+    pub author_hash: String,
+    pub deleted: i32,
+}
+
+#[derive(Debug, Clone, Database)]
+pub struct AuditLog {
+    pub id: String,
+    pub actor: String,
+    pub action: String,
+    /// Fields below are empty or 0 when the action has no such target
+    pub board_key: String,
+    pub thread_key: i64,
+    pub response_number: i32,
+    pub user_hash: String,
+    pub reason: String,
+    pub detail: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Database)]
 pub struct User {
     pub id: String,
     pub ip_address: String,
     pub created_at: String,
     pub disabled: i32,
     pub user_hash: String,
+    pub disabled_reason: String,
+    /// unix timestamp (seconds) when the ban ends, 0 for a permanent ban
+    pub disabled_until: i64,
+    /// [`crate::utils::derive_user_hash`] of `user_hash`, empty for users created before it
+    /// was stored until they next log in or post
+    pub derived_hash: String,
+}
+
+impl User {
+    /// Whether the user is banned at `now` (unix timestamp in seconds); bans lift by themselves
+    /// once `disabled_until` has passed
+    pub fn is_banned_at(&self, now: i64) -> bool {
+        self.disabled == 1 && (self.disabled_until == 0 || now < self.disabled_until)
+    }
+}
+
+/// A post rejected as a duplicate, along with the board it was posted to
+#[derive(Debug, Clone, Database)]
+pub struct DuplicatePost {
+    pub id: String,
+    pub board_key: String,
+    /// 0 when the post was a new thread
+    pub thread_key: i64,
+    pub body: String,
+    pub original_response_id: String,
+    pub ip_address: String,
+    pub user_id: String,
+    pub created_at: String,
+}
+
+/// A post held for review by an NG word with the `hold` action
+#[derive(Debug, Clone, Database)]
+pub struct HeldPost {
+    pub id: String,
+    pub board_id: i32,
+    /// 0 when the post is a new thread
+    pub thread_key: i64,
+    /// Empty when the post is a response
+    pub title: String,
+    pub name: String,
+    pub mail: String,
+    pub body: String,
+    pub author_id: String,
+    pub date_text: String,
+    pub ip_address: String,
+    pub user_id: String,
+    pub ng_word_id: i32,
+    pub cap_id: i32,
+    pub created_at: String,
+}
+
+/// A response along with the thread and board it was posted to
+#[derive(Debug, Clone, Database)]
+pub struct UserPost {
+    pub id: String,
+    pub board_key: String,
+    pub thread_key: i64,
+    pub thread_title: String,
+    pub name: String,
+    pub mail: String,
+    pub body: String,
+    pub author_id: String,
+    pub date_text: String,
+    pub ip_address: String,
+    pub created_at: String,
+    pub deleted: i32,
 }
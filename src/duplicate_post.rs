@@ -0,0 +1,247 @@
+use std::sync::OnceLock;
+
+use regex::Regex;
+use worker::console_error;
+
+use crate::{
+    bbs_repository::{
+        BbsRepository, CreatingDuplicatePost, CreatingResponse, CreatingThread, RepositoryResult,
+    },
+    dtos::{Board, Res},
+};
+
+/// The parts of a post that the duplicate check looks at
+#[derive(Debug, Clone, Copy)]
+pub struct PostContent<'a> {
+    pub board_id: i32,
+    /// `None` for a new thread
+    pub thread_key: Option<i64>,
+    pub body: &'a str,
+    pub ip_addr: &'a str,
+    pub user_hash: &'a str,
+}
+
+impl<'a> From<&'a CreatingThread> for PostContent<'a> {
+    fn from(thread: &'a CreatingThread) -> Self {
+        Self {
+            board_id: thread.board_id,
+            thread_key: None,
+            body: &thread.body,
+            ip_addr: &thread.ip_addr,
+            user_hash: &thread.user_hash,
+        }
+    }
+}
+
+impl<'a> From<&'a CreatingResponse> for PostContent<'a> {
+    fn from(response: &'a CreatingResponse) -> Self {
+        Self {
+            board_id: response.board_id,
+            thread_key: Some(response.thread_key),
+            body: &response.body,
+            ip_addr: &response.ip_addr,
+            user_hash: &response.user_hash,
+        }
+    }
+}
+
+/// Folds the differences a flooder can add without changing what a (sanitized) body says:
+/// anchors, line breaks, whitespace, letter case and full-width alphanumerics.
+pub fn normalize_body(body: &str) -> String {
+    static ANCHOR: OnceLock<Regex> = OnceLock::new();
+    let anchor = ANCHOR.get_or_init(|| Regex::new(r"(&gt;){1,2}\d+(-\d+)?").unwrap());
+
+    anchor
+        .replace_all(&body.replace("<br>", ""), "")
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| match c {
+            // Full-width ASCII variants (！ to ～)
+            '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+            c => c,
+        })
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// Looks for a response with the same content that the same user or IP address posted on any
+/// board within the board's `duplicate_post_window` before `now` (unix timestamp in seconds).
+///
+/// A duplicate is recorded in `duplicate_posts` before being returned; failing to record it
+/// is only logged, so the post is rejected either way.
+pub async fn find_duplicate_post(
+    repo: &dyn BbsRepository,
+    board: &Board,
+    post: PostContent<'_>,
+    now: i64,
+) -> RepositoryResult<Option<Res>> {
+    if board.duplicate_post_window <= 0 {
+        return Ok(None);
+    }
+    let normalized = normalize_body(post.body);
+    if normalized.is_empty() {
+        return Ok(None);
+    }
+
+    let since = now - board.duplicate_post_window as i64;
+    let original = repo
+        .get_recent_responses_by_author(post.user_hash, post.ip_addr, since)
+        .await?
+        .into_iter()
+        .find(|x| normalize_body(&x.body) == normalized);
+
+    if let Some(original) = &original {
+        if let Err(e) = repo
+            .create_duplicate_post(CreatingDuplicatePost {
+                board_id: post.board_id,
+                thread_key: post.thread_key,
+                body: post.body.to_string(),
+                original_response_id: original.id.clone(),
+                ip_addr: post.ip_addr.to_string(),
+                user_hash: post.user_hash.to_string(),
+            })
+            .await
+        {
+            console_error!("failed to record duplicate post: {e:?}");
+        }
+    }
+
+    Ok(original)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::bbs_repository::{
+        fixtures::{board, creating_response, creating_thread, fixed_clock, NOW_MILLIS},
+        DuplicatePostFilter, InMemoryBbsRepository,
+    };
+
+    const WINDOW: i32 = 60;
+    const OTHER_BOARD_ID: i32 = 2;
+
+    fn board_with_window() -> Board {
+        Board {
+            duplicate_post_window: WINDOW,
+            ..board()
+        }
+    }
+
+    /// Two boards and a thread on the first, whose "本文" was posted by `user` from 192.0.2.1
+    /// at `NOW_MILLIS`
+    fn repo() -> InMemoryBbsRepository {
+        let other_board = Board {
+            id: OTHER_BOARD_ID,
+            board_key: "other".to_string(),
+            ..board()
+        };
+        let repo = InMemoryBbsRepository::with_clock(vec![board(), other_board], fixed_clock);
+        block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+        repo
+    }
+
+    fn find(repo: &InMemoryBbsRepository, response: &CreatingResponse, now: i64) -> Option<Res> {
+        block_on(find_duplicate_post(
+            repo,
+            &board_with_window(),
+            response.into(),
+            now,
+        ))
+        .unwrap()
+    }
+
+    fn recorded(repo: &InMemoryBbsRepository) -> usize {
+        block_on(repo.get_duplicate_posts(DuplicatePostFilter {
+            limit: 100,
+            ..Default::default()
+        }))
+        .unwrap()
+        .len()
+    }
+
+    #[test]
+    fn normalizes_whitespace_anchors_case_and_width() {
+        assert_eq!(normalize_body("本 文<br>　本文\t"), "本文本文");
+        assert_eq!(normalize_body("&gt;&gt;1 本文"), "本文");
+        assert_eq!(normalize_body("&gt;12-34本文"), "本文");
+        assert_eq!(normalize_body("ＡＢＣ１２３！～"), "abc123!~");
+        assert_eq!(normalize_body("ABC abc"), "abcabc");
+        // Half-width katakana are left alone
+        assert_eq!(normalize_body("ﾃｽﾄ"), "ﾃｽﾄ");
+        assert_eq!(normalize_body(" <br> "), "");
+    }
+
+    #[test]
+    fn finds_the_same_body_of_the_same_user_or_ip_address() {
+        let repo = repo();
+        let now = NOW_MILLIS / 1000;
+        let thread_key = now;
+        let same = creating_response(thread_key, " 本　文 ");
+        assert!(find(&repo, &same, now).is_some());
+
+        let same_user = CreatingResponse {
+            ip_addr: "192.0.2.2".to_string(),
+            ..same.clone()
+        };
+        assert!(find(&repo, &same_user, now).is_some());
+
+        let same_ip_addr = CreatingResponse {
+            user_hash: "other".to_string(),
+            ..same.clone()
+        };
+        assert!(find(&repo, &same_ip_addr, now).is_some());
+
+        let stranger = CreatingResponse {
+            ip_addr: "192.0.2.2".to_string(),
+            user_hash: "other".to_string(),
+            ..same.clone()
+        };
+        assert!(find(&repo, &stranger, now).is_none());
+
+        let other_body = creating_response(thread_key, "別の本文");
+        assert!(find(&repo, &other_body, now).is_none());
+
+        // Only the duplicates are recorded
+        assert_eq!(recorded(&repo), 3);
+    }
+
+    #[test]
+    fn looks_back_over_the_window_only() {
+        let repo = repo();
+        let posted_at = NOW_MILLIS / 1000;
+        let response = creating_response(posted_at, "本文");
+        assert!(find(&repo, &response, posted_at + WINDOW as i64).is_some());
+        assert!(find(&repo, &response, posted_at + WINDOW as i64 + 1).is_none());
+
+        let disabled = board();
+        assert!(block_on(find_duplicate_post(
+            &repo,
+            &disabled,
+            (&response).into(),
+            posted_at
+        ))
+        .unwrap()
+        .is_none());
+    }
+
+    #[test]
+    fn counts_posts_on_other_boards() {
+        let repo = repo();
+        let now = NOW_MILLIS / 1000;
+        let response = CreatingResponse {
+            board_id: OTHER_BOARD_ID,
+            ..creating_response(now, "本文")
+        };
+        assert!(find(&repo, &response, now).is_some());
+    }
+
+    #[test]
+    fn ignores_bodies_left_empty_by_normalization() {
+        let repo = repo();
+        let now = NOW_MILLIS / 1000;
+        block_on(repo.create_response(creating_response(now, "&gt;&gt;1"))).unwrap();
+        assert!(find(&repo, &creating_response(now, "&gt;&gt;1"), now).is_none());
+    }
+}
@@ -0,0 +1,244 @@
+use std::{net::IpAddr, str::FromStr};
+
+use worker::console_error;
+
+use crate::dtos::IpBan;
+
+/// An address or a CIDR range, e.g. `192.0.2.1`, `192.0.2.0/24` or `2001:db8::/32`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpRange {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl FromStr for IpRange {
+    type Err = ();
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (addr, prefix_len) = match s.trim().split_once('/') {
+            Some((addr, prefix_len)) => (addr, Some(prefix_len)),
+            None => (s.trim(), None),
+        };
+        let parsed = addr.parse::<IpAddr>().map_err(|_| ())?;
+        // `u8::from_str` would take a sign too
+        let prefix_len = match prefix_len {
+            Some(x) if !x.is_empty() && x.bytes().all(|x| x.is_ascii_digit()) => {
+                x.parse::<u8>().map_err(|_| ())?
+            }
+            Some(_) => return Err(()),
+            None if parsed.is_ipv6() => 128,
+            None => 32,
+        };
+        let addr = parsed.to_canonical();
+        let max_len = match addr {
+            IpAddr::V4(_) => 32,
+            IpAddr::V6(_) => 128,
+        };
+        // The prefix of an IPv4-mapped IPv6 range counts the 96 bits of the mapping
+        let prefix_len = if parsed.is_ipv6() && addr.is_ipv4() {
+            prefix_len.checked_sub(96).ok_or(())?
+        } else {
+            prefix_len
+        };
+        if prefix_len > max_len {
+            return Err(());
+        }
+
+        Ok(Self {
+            network: mask(addr, prefix_len),
+            prefix_len,
+        })
+    }
+}
+
+impl std::fmt::Display for IpRange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.network, self.prefix_len)
+    }
+}
+
+fn mask(addr: IpAddr, prefix_len: u8) -> IpAddr {
+    match addr {
+        IpAddr::V4(v4) => {
+            let bits = u32::from(v4);
+            let mask = u32::MAX.checked_shl(32 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V4((bits & mask).into())
+        }
+        IpAddr::V6(v6) => {
+            let bits = u128::from(v6);
+            let mask = u128::MAX.checked_shl(128 - prefix_len as u32).unwrap_or(0);
+            IpAddr::V6((bits & mask).into())
+        }
+    }
+}
+
+impl IpRange {
+    /// IPv4-mapped IPv6 addresses (`::ffff:192.0.2.1`) are matched as IPv4
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        let addr = addr.to_canonical();
+        match (self.network, addr) {
+            (IpAddr::V4(_), IpAddr::V4(_)) | (IpAddr::V6(_), IpAddr::V6(_)) => {
+                mask(addr, self.prefix_len) == self.network
+            }
+            _ => false,
+        }
+    }
+}
+
+/// IP bans with their ranges parsed
+pub struct IpBansCtx {
+    bans: Vec<(IpRange, IpBan)>,
+}
+
+impl IpBansCtx {
+    /// Skips entries whose range cannot be parsed
+    pub fn new(bans: Vec<IpBan>) -> Self {
+        let bans = bans
+            .into_iter()
+            .filter_map(|ban| match ban.cidr.parse::<IpRange>() {
+                Ok(range) => Some((range, ban)),
+                Err(()) => {
+                    console_error!("invalid range of ip ban {}: {}", ban.id, ban.cidr);
+                    None
+                }
+            })
+            .collect();
+        Self { bans }
+    }
+
+    /// The ban covering the address on the board at `now` (unix timestamp in seconds), if any.
+    /// An address that cannot be parsed is never banned.
+    pub fn find(&self, ip_addr: &str, board_id: i32, now: i64) -> Option<&IpBan> {
+        let addr = ip_addr.trim().parse::<IpAddr>().ok()?;
+        self.bans
+            .iter()
+            .filter(|(_, ban)| ban.board_id == 0 || ban.board_id == board_id)
+            .filter(|(_, ban)| ban.expires_at == 0 || now < ban.expires_at)
+            .find(|(range, _)| range.contains(addr))
+            .map(|(_, ban)| ban)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(cidr: &str) -> IpRange {
+        cidr.parse().unwrap()
+    }
+
+    fn addr(addr: &str) -> IpAddr {
+        addr.parse().unwrap()
+    }
+
+    #[test]
+    fn normalizes_ranges() {
+        assert_eq!(range("192.0.2.1").to_string(), "192.0.2.1/32");
+        assert_eq!(range(" 192.0.2.123/24 ").to_string(), "192.0.2.0/24");
+        assert_eq!(range("2001:db8::1").to_string(), "2001:db8::1/128");
+        assert_eq!(
+            range("2001:db8:1:2:3::/64").to_string(),
+            "2001:db8:1:2::/64"
+        );
+        assert_eq!(range("::ffff:192.0.2.1").to_string(), "192.0.2.1/32");
+        assert_eq!(range("::ffff:192.0.2.1/120").to_string(), "192.0.2.0/24");
+    }
+
+    #[test]
+    fn rejects_malformed_ranges() {
+        for cidr in [
+            "",
+            "/24",
+            "192.0.2.0/",
+            "192.0.2.0/33",
+            "192.0.2.0/-1",
+            "192.0.2.0/+8",
+            "192.0.2.0/24/24",
+            "192.0.2.0/ 24",
+            "192.0.2.256",
+            "192.0.2",
+            "2001:db8::/129",
+            "2001:db8:::/64",
+            "::ffff:192.0.2.0/95",
+            "example.com/24",
+        ] {
+            assert_eq!(cidr.parse::<IpRange>(), Err(()), "{cidr:?}");
+        }
+    }
+
+    #[test]
+    fn zero_prefix_covers_the_whole_family() {
+        let v4 = range("192.0.2.1/0");
+        assert_eq!(v4.to_string(), "0.0.0.0/0");
+        assert!(v4.contains(addr("0.0.0.0")));
+        assert!(v4.contains(addr("255.255.255.255")));
+        assert!(!v4.contains(addr("2001:db8::1")));
+
+        let v6 = range("2001:db8::1/0");
+        assert_eq!(v6.to_string(), "::/0");
+        assert!(v6.contains(addr("::")));
+        assert!(v6.contains(addr("ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff")));
+        assert!(!v6.contains(addr("192.0.2.1")));
+    }
+
+    #[test]
+    fn full_prefix_covers_one_address() {
+        let v4 = range("192.0.2.1/32");
+        assert!(v4.contains(addr("192.0.2.1")));
+        assert!(!v4.contains(addr("192.0.2.0")));
+        assert!(!v4.contains(addr("192.0.2.2")));
+
+        let v6 = range("2001:db8::1/128");
+        assert!(v6.contains(addr("2001:db8::1")));
+        assert!(!v6.contains(addr("2001:db8::")));
+        assert!(!v6.contains(addr("2001:db8::2")));
+    }
+
+    #[test]
+    fn slash_64_covers_its_boundaries_only() {
+        let v6 = range("2001:db8:0:1::/64");
+        assert!(v6.contains(addr("2001:db8:0:1::")));
+        assert!(v6.contains(addr("2001:db8:0:1:ffff:ffff:ffff:ffff")));
+        assert!(!v6.contains(addr("2001:db8:0:0:ffff:ffff:ffff:ffff")));
+        assert!(!v6.contains(addr("2001:db8:0:2::")));
+    }
+
+    #[test]
+    fn matches_ipv4_mapped_addresses_as_ipv4() {
+        let v4 = range("192.0.2.0/24");
+        assert!(v4.contains(addr("::ffff:192.0.2.1")));
+        assert!(!v4.contains(addr("::ffff:198.51.100.1")));
+
+        let mapped = range("::ffff:192.0.2.0/120");
+        assert!(mapped.contains(addr("192.0.2.1")));
+        assert!(mapped.contains(addr("::ffff:192.0.2.255")));
+        assert!(!mapped.contains(addr("192.0.3.0")));
+
+        // The IPv4-compatible form is not a mapping
+        assert!(!v4.contains(addr("::192.0.2.1")));
+    }
+
+    #[test]
+    fn finds_bans_of_the_board_which_have_not_expired() {
+        let ban = |id: &str, cidr: &str, board_id: i32, expires_at: i64| IpBan {
+            id: id.to_string(),
+            cidr: cidr.to_string(),
+            board_id,
+            reason: String::new(),
+            expires_at,
+            created_at: String::new(),
+        };
+        let bans = IpBansCtx::new(vec![
+            ban("board", "192.0.2.0/24", 1, 0),
+            ban("expiring", "198.51.100.0/24", 0, 100),
+        ]);
+        let find = |ip_addr: &str, board_id: i32, now: i64| {
+            bans.find(ip_addr, board_id, now).map(|x| x.id.as_str())
+        };
+        assert_eq!(find("192.0.2.1", 1, 0), Some("board"));
+        assert_eq!(find("192.0.2.1", 2, 0), None);
+        assert_eq!(find("198.51.100.1", 2, 99), Some("expiring"));
+        assert_eq!(find("198.51.100.1", 2, 100), None);
+        assert_eq!(find("not an address", 1, 0), None);
+    }
+}
@@ -2,32 +2,67 @@ use cookie::Cookie;
 use dtos::Board;
 use planetscale_driver::PSConnection;
 use routes::{
-    auth::route_auth, bbs_cgi::route_bbs_cgi, dat_routing::route_dat,
-    setting_txt::route_setting_txt, subject_txt::route_subject_txt,
+    admin::{
+        route_admin_audit_logs, route_admin_ban_user, route_admin_create_ip_ban,
+        route_admin_delete_ip_ban, route_admin_delete_response, route_admin_delete_thread,
+        route_admin_discard_held_post, route_admin_duplicate_posts, route_admin_held_posts,
+        route_admin_ip_bans, route_admin_move_thread, route_admin_release_held_post,
+        route_admin_rename_thread, route_admin_reports, route_admin_resolve_reports,
+        route_admin_responses, route_admin_restore_response, route_admin_stop_thread,
+        route_admin_thread, route_admin_unban_user, route_admin_unstop_thread, route_admin_user,
+    },
+    auth::route_auth,
+    bbs_cgi::route_bbs_cgi,
+    dat_routing::route_dat,
+    kako::{route_kako_dat, route_kako_subject_txt},
+    report::route_report,
+    setting_txt::route_setting_txt,
+    subject_txt::route_subject_txt,
 };
 use std::{
     collections::HashMap,
     str::FromStr,
     sync::{
         atomic::{AtomicU64, Ordering},
-        Arc, OnceLock,
+        Arc, Mutex, OnceLock,
     },
 };
 use utils::response_shift_jis_text_plain_with_cache;
 use worker::*;
 
-use bbs_repository::BbsRepository;
+use author_id::AuthorIdSalts;
+use bbs_repository::{
+    BbsRepository, InMemoryBbsRepository, PlanetScaleBbsRepository, RepositoryResult,
+};
+use cap::CapsCtx;
+use ip_ban::IpBansCtx;
+use ng_word::NgWordsCtx;
+use rate_limiter::{
+    InMemoryRateLimitStore, KvRateLimitStore, RateLimitConfig, RateLimitStore, RateLimiter,
+};
 
 mod utils;
 mod routes {
+    pub(crate) mod admin;
     pub(crate) mod auth;
     pub(crate) mod bbs_cgi;
     pub(crate) mod dat_routing;
+    #[cfg(test)]
+    pub(crate) mod fixtures;
+    pub(crate) mod kako;
+    pub(crate) mod report;
+    pub(crate) mod route_error;
     pub(crate) mod setting_txt;
     pub(crate) mod subject_txt;
 }
+mod author_id;
 mod bbs_repository;
+mod cap;
 mod dtos;
+mod duplicate_post;
+mod ip_ban;
+mod ng_word;
+mod rate_limiter;
 
 fn get_connection(host: &str, user: &str, password: &str) -> PSConnection {
     static PLANETSCALE_CONN: OnceLock<PSConnection> = OnceLock::new();
@@ -37,6 +72,39 @@ fn get_connection(host: &str, user: &str, password: &str) -> PSConnection {
         .to_owned()
 }
 
+fn get_in_memory_repository() -> Arc<InMemoryBbsRepository> {
+    static IN_MEMORY_REPOSITORY: OnceLock<Arc<InMemoryBbsRepository>> = OnceLock::new();
+
+    IN_MEMORY_REPOSITORY
+        .get_or_init(|| {
+            Arc::new(InMemoryBbsRepository::new(vec![Board {
+                id: 1,
+                name: "EDGE-EXP".to_string(),
+                board_key: "planetisodon".to_string(),
+                default_name: "デフォルトの名無し".to_string(),
+                max_response_count: 1000,
+                max_thread_count: 500,
+                subject_max_length: 96,
+                name_max_length: 64,
+                mail_max_length: 64,
+                message_max_length: 4096,
+                message_max_lines: 32,
+                allow_unicode: 1,
+                duplicate_post_window: 300,
+                author_id_scheme: "ip_daily".to_string(),
+            }]))
+        })
+        .to_owned()
+}
+
+fn get_in_memory_rate_limit_store() -> Arc<InMemoryRateLimitStore> {
+    static IN_MEMORY_RATE_LIMIT_STORE: OnceLock<Arc<InMemoryRateLimitStore>> = OnceLock::new();
+
+    IN_MEMORY_RATE_LIMIT_STORE
+        .get_or_init(|| Arc::new(InMemoryRateLimitStore::new()))
+        .to_owned()
+}
+
 fn get_user_token_cookie(req: &Request) -> Option<String> {
     let cookie_str = req.headers().get("Cookie").ok()??;
     for cookie in Cookie::split_parse(cookie_str).flatten() {
@@ -79,7 +147,7 @@ impl BoardsCtx {
     }
 }
 
-async fn get_boards(repo: BbsRepository) -> Arc<BoardsCtx> {
+async fn get_boards(repo: &dyn BbsRepository) -> Arc<BoardsCtx> {
     static BOARDS_CTX_CACHE: OnceLock<Arc<BoardsCtx>> = OnceLock::new();
     static LAST_MODIFIED: AtomicU64 = AtomicU64::new(0); // unix timestamp (seconds)
     const BOARDS_LIST_CACHE_TTL: u64 = 60 * 5;
@@ -99,6 +167,69 @@ async fn get_boards(repo: BbsRepository) -> Arc<BoardsCtx> {
     boards
 }
 
+/// A value loaded from the database and shared between the requests an isolate serves until
+/// it is `ttl` seconds old
+struct TtlCache<T> {
+    /// (fetched at in unix timestamp (seconds), value)
+    entry: Mutex<Option<(u64, Arc<T>)>>,
+    ttl: u64,
+}
+
+impl<T> TtlCache<T> {
+    const fn new(ttl: u64) -> Self {
+        Self {
+            entry: Mutex::new(None),
+            ttl,
+        }
+    }
+
+    /// The cached value, or the one `load` returns once the cached value is too old
+    async fn get_or_load<F>(&self, load: F) -> RepositoryResult<Arc<T>>
+    where
+        F: std::future::Future<Output = RepositoryResult<T>>,
+    {
+        let now = utils::system_clock() as u64 / 1000;
+        if let Some((fetched_at, value)) = self.entry.lock().unwrap().as_ref() {
+            if now.saturating_sub(*fetched_at) <= self.ttl {
+                return Ok(value.clone());
+            }
+        }
+
+        let value = Arc::new(load.await?);
+        *self.entry.lock().unwrap() = Some((now, value.clone()));
+        Ok(value)
+    }
+
+    /// Makes the next `get_or_load` load again. Other isolates keep their copy until it
+    /// expires.
+    fn invalidate(&self) {
+        *self.entry.lock().unwrap() = None;
+    }
+}
+
+// Shorter than the boards so that moderators' changes apply soon
+static NG_WORDS_CACHE: TtlCache<NgWordsCtx> = TtlCache::new(60);
+static CAPS_CACHE: TtlCache<CapsCtx> = TtlCache::new(60);
+static IP_BANS_CACHE: TtlCache<IpBansCtx> = TtlCache::new(60);
+
+async fn get_ng_words(repo: &dyn BbsRepository) -> RepositoryResult<Arc<NgWordsCtx>> {
+    NG_WORDS_CACHE
+        .get_or_load(async { Ok(NgWordsCtx::new(repo.get_ng_words().await?)) })
+        .await
+}
+
+async fn get_caps(repo: &dyn BbsRepository) -> RepositoryResult<Arc<CapsCtx>> {
+    CAPS_CACHE
+        .get_or_load(async { Ok(CapsCtx::new(repo.get_caps().await?)) })
+        .await
+}
+
+async fn get_ip_bans(repo: &dyn BbsRepository) -> RepositoryResult<Arc<IpBansCtx>> {
+    IP_BANS_CACHE
+        .get_or_load(async { Ok(IpBansCtx::new(repo.get_ip_bans().await?)) })
+        .await
+}
+
 #[derive(Debug, Clone)]
 struct GoogleOAuth2 {
     client_id: String,
@@ -106,31 +237,51 @@ struct GoogleOAuth2 {
 }
 
 struct Ctx {
-    db_conn: PSConnection,
     google_oauth2: GoogleOAuth2,
-    bbs_repository: BbsRepository,
+    /// Why the `AUTHOR_ID_SALT` secret is unusable, which only fails posts that show an ID
+    author_id_salts: std::result::Result<AuthorIdSalts, &'static str>,
+    bbs_repository: Arc<dyn BbsRepository>,
     boards: Arc<BoardsCtx>,
+    rate_limiter: RateLimiter,
 }
 
 #[event(fetch)]
 async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
-    let (host, username, password) = (
-        env.var("DATABASE_HOST")?.to_string(),
-        env.var("DATABASE_USERNAME")?.to_string(),
-        env.var("DATABASE_PASSWORD")?.to_string(),
-    );
-
-    let db_conn = get_connection(&host, &username, &password);
-    let repo = BbsRepository::new(db_conn.clone());
+    // STORAGE_BACKEND=memory runs the worker without PlanetScale (e.g. `wrangler dev --local`)
+    let repo: Arc<dyn BbsRepository> = match env.var("STORAGE_BACKEND") {
+        Ok(backend) if backend.to_string() == "memory" => get_in_memory_repository(),
+        _ => {
+            let (host, username, password) = (
+                env.var("DATABASE_HOST")?.to_string(),
+                env.var("DATABASE_USERNAME")?.to_string(),
+                env.var("DATABASE_PASSWORD")?.to_string(),
+            );
+            let db_conn = get_connection(&host, &username, &password);
+            Arc::new(PlanetScaleBbsRepository::new(db_conn))
+        }
+    };
+    let rate_limit_store: Box<dyn RateLimitStore> = match env.var("STORAGE_BACKEND") {
+        Ok(backend) if backend.to_string() == "memory" => {
+            Box::new(get_in_memory_rate_limit_store())
+        }
+        _ => Box::new(KvRateLimitStore::new(env.kv("planetisodon_rate_limit")?)),
+    };
 
     worker::Router::with_data(Ctx {
         bbs_repository: repo.clone(),
-        db_conn,
         google_oauth2: GoogleOAuth2 {
             client_id: env.secret("GOOGLE_CLIENT_ID")?.to_string(),
             client_secret: env.secret("GOOGLE_CLIENT_SECRET")?.to_string(),
         },
-        boards: get_boards(repo).await,
+        author_id_salts: match env.secret("AUTHOR_ID_SALT") {
+            Ok(salts) => salts
+                .to_string()
+                .parse::<AuthorIdSalts>()
+                .map_err(|()| "invalid AUTHOR_ID_SALT"),
+            Err(_) => Err("no AUTHOR_ID_SALT is set"),
+        },
+        boards: get_boards(repo.as_ref()).await,
+        rate_limiter: RateLimiter::new(rate_limit_store, RateLimitConfig::from_env(&env)),
     })
     .get("/", |_, _| {
         let html = include_str!("../planetisodon-client/dist/index.html");
@@ -154,9 +305,73 @@ async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
     })
     .get_async("/auth", route_auth)
     .post_async("/test/bbs.cgi", route_bbs_cgi)
+    .post_async("/api/report", route_report)
     .get_async("/:boardKey/subject.txt", route_subject_txt)
     .get_async("/:boardKey/SETTING.TXT", route_setting_txt)
     .get_async("/:boardKey/dat/:threadKey", route_dat)
+    .get_async("/:boardKey/kako/subject.txt", route_kako_subject_txt)
+    .get_async(
+        "/:boardKey/kako/:kakoDir1/:kakoDir2/:threadKey",
+        route_kako_dat,
+    )
+    .get_async("/admin/api/audit_logs", route_admin_audit_logs)
+    .get_async("/admin/api/duplicate_posts", route_admin_duplicate_posts)
+    .get_async("/admin/api/held_posts", route_admin_held_posts)
+    .post_async(
+        "/admin/api/held_posts/:heldPostId/release",
+        route_admin_release_held_post,
+    )
+    .post_async(
+        "/admin/api/held_posts/:heldPostId/discard",
+        route_admin_discard_held_post,
+    )
+    .get_async("/admin/api/ip_bans", route_admin_ip_bans)
+    .post_async("/admin/api/ip_bans", route_admin_create_ip_ban)
+    .post_async(
+        "/admin/api/ip_bans/:banId/delete",
+        route_admin_delete_ip_ban,
+    )
+    .get_async("/admin/api/reports", route_admin_reports)
+    .post_async(
+        "/admin/api/reports/:responseId/resolve",
+        route_admin_resolve_reports,
+    )
+    .get_async("/admin/api/users/:userHash", route_admin_user)
+    .post_async("/admin/api/users/:userHash/ban", route_admin_ban_user)
+    .post_async("/admin/api/users/:userHash/unban", route_admin_unban_user)
+    .get_async("/admin/api/:boardKey/:threadKey", route_admin_thread)
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/stop",
+        route_admin_stop_thread,
+    )
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/unstop",
+        route_admin_unstop_thread,
+    )
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/rename",
+        route_admin_rename_thread,
+    )
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/move",
+        route_admin_move_thread,
+    )
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/delete",
+        route_admin_delete_thread,
+    )
+    .get_async(
+        "/admin/api/:boardKey/:threadKey/responses",
+        route_admin_responses,
+    )
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/responses/:resNum/delete",
+        route_admin_delete_response,
+    )
+    .post_async(
+        "/admin/api/:boardKey/:threadKey/responses/:resNum/restore",
+        route_admin_restore_response,
+    )
     .get("/:boardKey/head.txt", |_, _| {
         response_shift_jis_text_plain_with_cache("<a href=\"/\">こちらへ</a>", 3600)
     })
@@ -181,3 +396,21 @@ async fn main(req: Request, env: Env, _ctx: Context) -> Result<Response> {
     .run(req, env)
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn ttl_cache_loads_again_once_invalidated() {
+        let cache = TtlCache::new(60);
+        let load = |value: i32| async move { RepositoryResult::Ok(value) };
+
+        assert_eq!(*block_on(cache.get_or_load(load(1))).unwrap(), 1);
+        assert_eq!(*block_on(cache.get_or_load(load(2))).unwrap(), 1);
+        cache.invalidate();
+        assert_eq!(*block_on(cache.get_or_load(load(3))).unwrap(), 3);
+    }
+}
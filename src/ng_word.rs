@@ -0,0 +1,245 @@
+use regex::{NoExpand, Regex};
+use worker::console_error;
+
+use crate::dtos::NgWord;
+
+enum Matcher {
+    Literal(String),
+    Regex(Regex),
+}
+
+impl Matcher {
+    fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Literal(pattern) => text.contains(pattern.as_str()),
+            Matcher::Regex(regex) => regex.is_match(text),
+        }
+    }
+
+    fn replace_all(&self, text: &str, replacement: &str) -> String {
+        match self {
+            Matcher::Literal(pattern) => text.replace(pattern.as_str(), replacement),
+            Matcher::Regex(regex) => regex.replace_all(text, NoExpand(replacement)).into_owned(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NgWordAction {
+    Reject,
+    Replace(String),
+    Hold,
+}
+
+struct CompiledNgWord {
+    id: i32,
+    board_id: i32,
+    matcher: Matcher,
+    action: NgWordAction,
+}
+
+impl CompiledNgWord {
+    /// `None` for an empty pattern, which matches nothing; the reason for an invalid entry
+    fn new(word: NgWord) -> Result<Option<Self>, String> {
+        if word.pattern.is_empty() {
+            return Ok(None);
+        }
+        let action = match word.action.as_str() {
+            "reject" => NgWordAction::Reject,
+            "replace" => NgWordAction::Replace(word.replacement),
+            "hold" => NgWordAction::Hold,
+            action => return Err(format!("unknown action of ng word {}: {action}", word.id)),
+        };
+        let matcher = if word.is_regex == 1 {
+            match Regex::new(&word.pattern) {
+                Ok(regex) => Matcher::Regex(regex),
+                Err(e) => return Err(format!("invalid regex of ng word {}: {e}", word.id)),
+            }
+        } else {
+            Matcher::Literal(word.pattern)
+        };
+        Ok(Some(Self {
+            id: word.id,
+            board_id: word.board_id,
+            matcher,
+            action,
+        }))
+    }
+}
+
+/// What to do with a post after the NG words have been applied
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NgWordVerdict {
+    Accept,
+    Reject,
+    /// Store the post for review while telling the poster it was written
+    Hold {
+        ng_word_id: i32,
+    },
+}
+
+/// NG words with their patterns compiled
+pub struct NgWordsCtx {
+    words: Vec<CompiledNgWord>,
+}
+
+impl NgWordsCtx {
+    /// Skips entries with an empty or invalid pattern or an unknown action
+    pub fn new(words: Vec<NgWord>) -> Self {
+        let words = words
+            .into_iter()
+            .filter_map(|word| {
+                CompiledNgWord::new(word).unwrap_or_else(|e| {
+                    console_error!("{e}");
+                    None
+                })
+            })
+            .collect();
+        Self { words }
+    }
+
+    /// Applies the global entries and those of the board to the fields.
+    ///
+    /// Replacements are made in place in the order of the entries. A reject wins over a hold,
+    /// so a post is never held when it would have been rejected.
+    pub fn apply(&self, board_id: i32, fields: &mut [&mut String]) -> NgWordVerdict {
+        let mut verdict = NgWordVerdict::Accept;
+        for word in self
+            .words
+            .iter()
+            .filter(|x| x.board_id == 0 || x.board_id == board_id)
+        {
+            match &word.action {
+                NgWordAction::Replace(replacement) => {
+                    for field in fields.iter_mut() {
+                        if word.matcher.is_match(field) {
+                            **field = word.matcher.replace_all(field, replacement);
+                        }
+                    }
+                }
+                action => {
+                    if !fields.iter().any(|x| word.matcher.is_match(x)) {
+                        continue;
+                    }
+                    if *action == NgWordAction::Reject {
+                        return NgWordVerdict::Reject;
+                    }
+                    if verdict == NgWordVerdict::Accept {
+                        verdict = NgWordVerdict::Hold {
+                            ng_word_id: word.id,
+                        };
+                    }
+                }
+            }
+        }
+        verdict
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ng_word(id: i32, board_id: i32, pattern: &str, action: &str) -> NgWord {
+        NgWord {
+            id,
+            board_id,
+            pattern: pattern.to_string(),
+            is_regex: 0,
+            action: action.to_string(),
+            replacement: String::new(),
+        }
+    }
+
+    fn regex(word: NgWord) -> NgWord {
+        NgWord {
+            is_regex: 1,
+            ..word
+        }
+    }
+
+    fn replace(id: i32, pattern: &str, replacement: &str) -> NgWord {
+        NgWord {
+            replacement: replacement.to_string(),
+            ..ng_word(id, 0, pattern, "replace")
+        }
+    }
+
+    fn verdict(ng_words: &NgWordsCtx, board_id: i32, body: &str) -> NgWordVerdict {
+        ng_words.apply(board_id, &mut [&mut body.to_string()])
+    }
+
+    #[test]
+    fn reject_wins_over_hold() {
+        let ng_words = NgWordsCtx::new(vec![
+            ng_word(1, 0, "保留", "hold"),
+            ng_word(2, 0, "保留2", "hold"),
+            ng_word(3, 0, "拒否", "reject"),
+        ]);
+        assert_eq!(verdict(&ng_words, 1, "本文"), NgWordVerdict::Accept);
+        assert_eq!(
+            verdict(&ng_words, 1, "保留2"),
+            NgWordVerdict::Hold { ng_word_id: 1 }
+        );
+        assert_eq!(verdict(&ng_words, 1, "保留と拒否"), NgWordVerdict::Reject);
+    }
+
+    #[test]
+    fn matches_literals_as_they_are_and_regexes_as_patterns() {
+        let literal = NgWordsCtx::new(vec![ng_word(1, 0, "a.c", "reject")]);
+        assert_eq!(verdict(&literal, 1, "abc"), NgWordVerdict::Accept);
+        assert_eq!(verdict(&literal, 1, "xa.cx"), NgWordVerdict::Reject);
+
+        let pattern = NgWordsCtx::new(vec![regex(ng_word(1, 0, "^a.c$", "reject"))]);
+        assert_eq!(verdict(&pattern, 1, "abc"), NgWordVerdict::Reject);
+        assert_eq!(verdict(&pattern, 1, "xabc"), NgWordVerdict::Accept);
+    }
+
+    #[test]
+    fn applies_global_entries_and_those_of_the_board() {
+        let ng_words = NgWordsCtx::new(vec![
+            ng_word(1, 0, "全板", "reject"),
+            ng_word(2, 1, "板1", "reject"),
+        ]);
+        assert_eq!(verdict(&ng_words, 1, "全板"), NgWordVerdict::Reject);
+        assert_eq!(verdict(&ng_words, 2, "全板"), NgWordVerdict::Reject);
+        assert_eq!(verdict(&ng_words, 1, "板1"), NgWordVerdict::Reject);
+        assert_eq!(verdict(&ng_words, 2, "板1"), NgWordVerdict::Accept);
+    }
+
+    #[test]
+    fn skips_invalid_entries() {
+        // NgWordsCtx::new would log these, which only works on wasm
+        assert!(CompiledNgWord::new(regex(ng_word(1, 0, "(", "reject"))).is_err());
+        assert!(CompiledNgWord::new(ng_word(1, 0, "a", "ban")).is_err());
+        assert!(CompiledNgWord::new(ng_word(1, 0, "", "reject"))
+            .unwrap()
+            .is_none());
+        // The same pattern is fine as a literal
+        assert!(CompiledNgWord::new(ng_word(1, 0, "(", "reject"))
+            .unwrap()
+            .is_some());
+    }
+
+    #[test]
+    fn replaces_in_every_field_in_order() {
+        let ng_words = NgWordsCtx::new(vec![
+            replace(1, "悪口", "＊＊"),
+            regex(replace(2, r"(\d{3})-\d{4}", "$1-xxxx")),
+            replace(3, "＊＊", "[削除]"),
+        ]);
+        let mut name = "悪口さん".to_string();
+        let mut mail = "sage悪口".to_string();
+        let mut subject = "悪口スレ".to_string();
+        let mut body = "悪口 090-1234".to_string();
+        assert_eq!(
+            ng_words.apply(1, &mut [&mut name, &mut mail, &mut subject, &mut body]),
+            NgWordVerdict::Accept
+        );
+        assert_eq!(name, "[削除]さん");
+        assert_eq!(mail, "sage[削除]");
+        assert_eq!(subject, "[削除]スレ");
+        // Replacements are taken literally, not expanded
+        assert_eq!(body, "[削除] $1-xxxx");
+    }
+}
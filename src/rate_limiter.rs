@@ -0,0 +1,459 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use worker::{Env, Result};
+
+use crate::utils::{system_clock, Clock};
+
+mod in_memory;
+mod kv;
+
+pub use in_memory::InMemoryRateLimitStore;
+pub use kv::KvRateLimitStore;
+
+/// Storage of the post history used by [`RateLimiter`].
+///
+/// Implementations only need to keep recent timestamps per key; they are free to drop them
+/// once `ttl` seconds have passed.
+#[async_trait(?Send)]
+pub trait RateLimitStore {
+    /// Unix timestamps (seconds) recorded under the key, oldest first
+    async fn get(&self, key: &str) -> Result<Vec<u64>>;
+
+    async fn put(&self, key: &str, timestamps: &[u64], ttl: u64) -> Result<()>;
+}
+
+// Lets a store shared across requests (e.g. the in-memory one) back each request's limiter
+#[async_trait(?Send)]
+impl<T: RateLimitStore + ?Sized> RateLimitStore for Arc<T> {
+    async fn get(&self, key: &str) -> Result<Vec<u64>> {
+        self.as_ref().get(key).await
+    }
+
+    async fn put(&self, key: &str, timestamps: &[u64], ttl: u64) -> Result<()> {
+        self.as_ref().put(key, timestamps, ttl).await
+    }
+}
+
+/// Cooldowns in seconds; `0` disables the corresponding check
+#[derive(Debug, Clone)]
+pub struct RateLimitConfig {
+    /// Time between two posts of the same user
+    pub post_cooldown: u64,
+    /// Time between two thread creations of the same user or the same IP address
+    pub thread_cooldown: u64,
+    /// Posts a user may make within `burst_window`
+    pub burst_limit: u64,
+    pub burst_window: u64,
+    /// Reports a user may make within `report_window`
+    pub report_limit: u64,
+    pub report_window: u64,
+    /// Failed cap passwords an IP address may send within `cap_failure_window`
+    pub cap_failure_limit: u64,
+    pub cap_failure_window: u64,
+}
+
+impl Default for RateLimitConfig {
+    fn default() -> Self {
+        Self {
+            post_cooldown: 10,
+            thread_cooldown: 300,
+            burst_limit: 5,
+            burst_window: 60,
+            report_limit: 10,
+            report_window: 3600,
+            cap_failure_limit: 5,
+            cap_failure_window: 600,
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// Reads `RATE_LIMIT_*` vars, keeping the default of those which are missing or invalid
+    pub fn from_env(env: &Env) -> Self {
+        let var = |name: &str, default: u64| {
+            env.var(name)
+                .ok()
+                .and_then(|x| x.to_string().parse::<u64>().ok())
+                .unwrap_or(default)
+        };
+        let default = Self::default();
+        Self {
+            post_cooldown: var("RATE_LIMIT_POST_COOLDOWN", default.post_cooldown),
+            thread_cooldown: var("RATE_LIMIT_THREAD_COOLDOWN", default.thread_cooldown),
+            burst_limit: var("RATE_LIMIT_BURST_LIMIT", default.burst_limit),
+            burst_window: var("RATE_LIMIT_BURST_WINDOW", default.burst_window),
+            report_limit: var("RATE_LIMIT_REPORT_LIMIT", default.report_limit),
+            report_window: var("RATE_LIMIT_REPORT_WINDOW", default.report_window),
+            cap_failure_limit: var("RATE_LIMIT_CAP_FAILURE_LIMIT", default.cap_failure_limit),
+            cap_failure_window: var("RATE_LIMIT_CAP_FAILURE_WINDOW", default.cap_failure_window),
+        }
+    }
+
+    fn post_history_ttl(&self) -> u64 {
+        self.post_cooldown.max(self.burst_window)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateLimitViolation {
+    /// Seconds until the user may post again
+    PostCooldown(u64),
+    /// Seconds until the user or the IP address may create a thread again
+    ThreadCooldown(u64),
+    /// Seconds until the oldest post leaves the burst window
+    Burst(u64),
+    /// Seconds until the oldest report leaves the report window
+    Report(u64),
+    /// Seconds until the oldest failed cap password leaves the window
+    CapFailures(u64),
+}
+
+impl std::fmt::Display for RateLimitViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RateLimitViolation::PostCooldown(wait) => {
+                write!(f, "連続投稿ですか？？ あと{wait}秒待ってください。")
+            }
+            RateLimitViolation::ThreadCooldown(wait) => {
+                write!(f, "スレッド立てすぎです。あと{wait}秒待ってください。")
+            }
+            RateLimitViolation::Burst(wait) => {
+                write!(f, "短時間に書き込みすぎです。あと{wait}秒待ってください。")
+            }
+            RateLimitViolation::Report(wait) => {
+                write!(f, "通報が多すぎます。あと{wait}秒待ってください。")
+            }
+            RateLimitViolation::CapFailures(wait) => {
+                write!(
+                    f,
+                    "キャップの認証に失敗しすぎです。あと{wait}秒待ってください。"
+                )
+            }
+        }
+    }
+}
+
+/// Throttles posts and reports per user hash, thread creations per user hash and IP address,
+/// and failed cap passwords per IP address
+pub struct RateLimiter {
+    store: Box<dyn RateLimitStore>,
+    config: RateLimitConfig,
+    clock: Clock,
+}
+
+impl RateLimiter {
+    pub fn new(store: Box<dyn RateLimitStore>, config: RateLimitConfig) -> Self {
+        Self::with_clock(store, config, system_clock)
+    }
+
+    pub fn with_clock(
+        store: Box<dyn RateLimitStore>,
+        config: RateLimitConfig,
+        clock: Clock,
+    ) -> Self {
+        Self {
+            store,
+            config,
+            clock,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        ((self.clock)() / 1000) as u64
+    }
+
+    fn post_key(user_hash: &str) -> String {
+        format!("post:user:{user_hash}")
+    }
+
+    fn thread_keys(user_hash: &str, ip_addr: &str) -> [String; 2] {
+        [
+            format!("thread:user:{user_hash}"),
+            format!("thread:ip:{ip_addr}"),
+        ]
+    }
+
+    fn report_key(user_hash: &str) -> String {
+        format!("report:user:{user_hash}")
+    }
+
+    fn cap_failure_key(ip_addr: &str) -> String {
+        format!("cap_failure:ip:{ip_addr}")
+    }
+
+    /// Seconds until one of `timestamps` leaves the window if `limit` of them are in it
+    fn window_full(timestamps: &[u64], limit: u64, window: u64, now: u64) -> Option<u64> {
+        let window_start = now.saturating_sub(window);
+        let mut in_window = timestamps
+            .iter()
+            .filter(|x| **x > window_start)
+            .collect::<Vec<_>>();
+        if (in_window.len() as u64) < limit {
+            return None;
+        }
+        in_window.sort();
+        Some(*in_window[in_window.len() - limit as usize] + window - now)
+    }
+
+    /// Seconds left until `cooldown` has passed since the latest timestamp
+    fn remaining(timestamps: &[u64], cooldown: u64, now: u64) -> Option<u64> {
+        let last = timestamps.iter().max()?;
+        let until = last + cooldown;
+        (until > now).then(|| until - now)
+    }
+
+    /// Checks whether the post is allowed without recording it
+    pub async fn check(
+        &self,
+        user_hash: &str,
+        ip_addr: &str,
+        is_thread: bool,
+    ) -> Result<std::result::Result<(), RateLimitViolation>> {
+        let now = self.now();
+        let config = &self.config;
+
+        let posts = self.store.get(&Self::post_key(user_hash)).await?;
+        if config.post_cooldown > 0 {
+            if let Some(wait) = Self::remaining(&posts, config.post_cooldown, now) {
+                return Ok(Err(RateLimitViolation::PostCooldown(wait)));
+            }
+        }
+        if config.burst_limit > 0 && config.burst_window > 0 {
+            if let Some(wait) =
+                Self::window_full(&posts, config.burst_limit, config.burst_window, now)
+            {
+                return Ok(Err(RateLimitViolation::Burst(wait)));
+            }
+        }
+
+        if is_thread && config.thread_cooldown > 0 {
+            for key in Self::thread_keys(user_hash, ip_addr) {
+                let threads = self.store.get(&key).await?;
+                if let Some(wait) = Self::remaining(&threads, config.thread_cooldown, now) {
+                    return Ok(Err(RateLimitViolation::ThreadCooldown(wait)));
+                }
+            }
+        }
+
+        Ok(Ok(()))
+    }
+
+    /// Records a successful post
+    pub async fn record(&self, user_hash: &str, ip_addr: &str, is_thread: bool) -> Result<()> {
+        let now = self.now();
+        let config = &self.config;
+
+        let ttl = config.post_history_ttl();
+        if ttl > 0 {
+            self.append(&Self::post_key(user_hash), now, ttl).await?;
+        }
+        if is_thread && config.thread_cooldown > 0 {
+            for key in Self::thread_keys(user_hash, ip_addr) {
+                self.append(&key, now, config.thread_cooldown).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks whether the user may report without recording it
+    pub async fn check_report(
+        &self,
+        user_hash: &str,
+    ) -> Result<std::result::Result<(), RateLimitViolation>> {
+        let config = &self.config;
+        if config.report_limit == 0 || config.report_window == 0 {
+            return Ok(Ok(()));
+        }
+        let reports = self.store.get(&Self::report_key(user_hash)).await?;
+        match Self::window_full(
+            &reports,
+            config.report_limit,
+            config.report_window,
+            self.now(),
+        ) {
+            Some(wait) => Ok(Err(RateLimitViolation::Report(wait))),
+            None => Ok(Ok(())),
+        }
+    }
+
+    /// Records a successful report
+    pub async fn record_report(&self, user_hash: &str) -> Result<()> {
+        let config = &self.config;
+        if config.report_limit == 0 || config.report_window == 0 {
+            return Ok(());
+        }
+        self.append(
+            &Self::report_key(user_hash),
+            self.now(),
+            config.report_window,
+        )
+        .await
+    }
+
+    /// Checks whether the IP address may try a cap password, before it is verified
+    pub async fn check_cap_failures(
+        &self,
+        ip_addr: &str,
+    ) -> Result<std::result::Result<(), RateLimitViolation>> {
+        let config = &self.config;
+        if config.cap_failure_limit == 0 || config.cap_failure_window == 0 {
+            return Ok(Ok(()));
+        }
+        let failures = self.store.get(&Self::cap_failure_key(ip_addr)).await?;
+        match Self::window_full(
+            &failures,
+            config.cap_failure_limit,
+            config.cap_failure_window,
+            self.now(),
+        ) {
+            Some(wait) => Ok(Err(RateLimitViolation::CapFailures(wait))),
+            None => Ok(Ok(())),
+        }
+    }
+
+    /// Records a cap password that did not match
+    pub async fn record_cap_failure(&self, ip_addr: &str) -> Result<()> {
+        let config = &self.config;
+        if config.cap_failure_limit == 0 || config.cap_failure_window == 0 {
+            return Ok(());
+        }
+        self.append(
+            &Self::cap_failure_key(ip_addr),
+            self.now(),
+            config.cap_failure_window,
+        )
+        .await
+    }
+
+    async fn append(&self, key: &str, now: u64, ttl: u64) -> Result<()> {
+        let mut timestamps = self.store.get(key).await?;
+        timestamps.retain(|x| x + ttl > now);
+        timestamps.push(now);
+        self.store.put(key, &timestamps, ttl).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use futures::executor::block_on;
+
+    use super::*;
+
+    thread_local! {
+        // Each test runs on its own thread, so they do not see each other's time
+        static NOW_MILLIS: Cell<i64> = const { Cell::new(1_700_000_000_000) };
+    }
+
+    fn test_clock() -> i64 {
+        NOW_MILLIS.with(Cell::get)
+    }
+
+    fn advance(seconds: i64) {
+        NOW_MILLIS.with(|now| now.set(now.get() + seconds * 1000));
+    }
+
+    fn limiter(config: RateLimitConfig) -> RateLimiter {
+        RateLimiter::with_clock(
+            Box::new(InMemoryRateLimitStore::with_clock(test_clock)),
+            config,
+            test_clock,
+        )
+    }
+
+    #[test]
+    fn post_cooldown_expires() {
+        let limiter = limiter(RateLimitConfig::default());
+        block_on(limiter.record("user", "192.0.2.1", false)).unwrap();
+
+        advance(3);
+        assert_eq!(
+            block_on(limiter.check("user", "192.0.2.1", false)).unwrap(),
+            Err(RateLimitViolation::PostCooldown(7))
+        );
+        assert_eq!(
+            block_on(limiter.check("other", "192.0.2.1", false)).unwrap(),
+            Ok(())
+        );
+
+        advance(7);
+        assert_eq!(
+            block_on(limiter.check("user", "192.0.2.1", false)).unwrap(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn burst_counts_posts_in_window() {
+        let limiter = limiter(RateLimitConfig {
+            post_cooldown: 0,
+            burst_limit: 3,
+            burst_window: 60,
+            ..Default::default()
+        });
+        for _ in 0..3 {
+            block_on(limiter.record("user", "192.0.2.1", false)).unwrap();
+            advance(10);
+        }
+        // The first post leaves the window 60 seconds after it was made
+        assert_eq!(
+            block_on(limiter.check("user", "192.0.2.1", false)).unwrap(),
+            Err(RateLimitViolation::Burst(30))
+        );
+
+        advance(30);
+        assert_eq!(
+            block_on(limiter.check("user", "192.0.2.1", false)).unwrap(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn thread_cooldown_applies_per_ip_address() {
+        let limiter = limiter(RateLimitConfig::default());
+        block_on(limiter.record("user", "192.0.2.1", true)).unwrap();
+
+        advance(100);
+        assert_eq!(
+            block_on(limiter.check("other", "192.0.2.1", true)).unwrap(),
+            Err(RateLimitViolation::ThreadCooldown(200))
+        );
+        assert_eq!(
+            block_on(limiter.check("other", "192.0.2.2", true)).unwrap(),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn cap_failures_count_per_ip_address() {
+        let limiter = limiter(RateLimitConfig {
+            cap_failure_limit: 2,
+            cap_failure_window: 600,
+            ..Default::default()
+        });
+        for _ in 0..2 {
+            assert_eq!(
+                block_on(limiter.check_cap_failures("192.0.2.1")).unwrap(),
+                Ok(())
+            );
+            block_on(limiter.record_cap_failure("192.0.2.1")).unwrap();
+            advance(100);
+        }
+        assert_eq!(
+            block_on(limiter.check_cap_failures("192.0.2.1")).unwrap(),
+            Err(RateLimitViolation::CapFailures(400))
+        );
+        assert_eq!(
+            block_on(limiter.check_cap_failures("192.0.2.2")).unwrap(),
+            Ok(())
+        );
+
+        advance(400);
+        assert_eq!(
+            block_on(limiter.check_cap_failures("192.0.2.1")).unwrap(),
+            Ok(())
+        );
+    }
+}
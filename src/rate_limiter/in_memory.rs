@@ -0,0 +1,62 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use async_trait::async_trait;
+use worker::Result;
+
+use super::RateLimitStore;
+use crate::utils::{system_clock, Clock};
+
+/// Store keeping the history in process memory.
+///
+/// Every isolate has its own history, so this is only suitable for local development.
+#[derive(Debug)]
+pub struct InMemoryRateLimitStore {
+    /// key -> (timestamps, expires at)
+    entries: Mutex<HashMap<String, (Vec<u64>, u64)>>,
+    clock: Clock,
+}
+
+impl Default for InMemoryRateLimitStore {
+    fn default() -> Self {
+        Self::with_clock(system_clock)
+    }
+}
+
+impl InMemoryRateLimitStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_clock(clock: Clock) -> Self {
+        Self {
+            entries: Mutex::default(),
+            clock,
+        }
+    }
+
+    fn now(&self) -> u64 {
+        ((self.clock)() / 1000) as u64
+    }
+}
+
+#[async_trait(?Send)]
+impl RateLimitStore for InMemoryRateLimitStore {
+    async fn get(&self, key: &str) -> Result<Vec<u64>> {
+        let now = self.now();
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|_, (_, expires_at)| *expires_at > now);
+        Ok(entries
+            .get(key)
+            .map(|(timestamps, _)| timestamps.clone())
+            .unwrap_or_default())
+    }
+
+    async fn put(&self, key: &str, timestamps: &[u64], ttl: u64) -> Result<()> {
+        let now = self.now();
+        self.entries
+            .lock()
+            .unwrap()
+            .insert(key.to_string(), (timestamps.to_vec(), now + ttl));
+        Ok(())
+    }
+}
@@ -0,0 +1,48 @@
+use async_trait::async_trait;
+use worker::{kv::KvStore, Result};
+
+use super::RateLimitStore;
+
+// Workers KV rejects expirations shorter than a minute
+const MIN_KV_TTL: u64 = 60;
+
+/// Store backed by Workers KV.
+///
+/// KV is eventually consistent, so posts racing each other across locations may slip
+/// through; it is meant to stop floods, not to be exact.
+pub struct KvRateLimitStore {
+    kv: KvStore,
+}
+
+impl KvRateLimitStore {
+    pub fn new(kv: KvStore) -> Self {
+        Self { kv }
+    }
+}
+
+#[async_trait(?Send)]
+impl RateLimitStore for KvRateLimitStore {
+    async fn get(&self, key: &str) -> Result<Vec<u64>> {
+        let Some(value) = self.kv.get(key).text().await? else {
+            return Ok(Vec::new());
+        };
+        Ok(value
+            .split(',')
+            .filter_map(|x| x.parse::<u64>().ok())
+            .collect())
+    }
+
+    async fn put(&self, key: &str, timestamps: &[u64], ttl: u64) -> Result<()> {
+        let value = timestamps
+            .iter()
+            .map(|x| x.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        self.kv
+            .put(key, value)?
+            .expiration_ttl(ttl.max(MIN_KV_TTL))
+            .execute()
+            .await?;
+        Ok(())
+    }
+}
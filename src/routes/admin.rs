@@ -0,0 +1,246 @@
+use serde::{Deserialize, Serialize};
+use sha3::Digest;
+use worker::{console_error, Cache, Request, Response, RouteContext, Url};
+
+use crate::{
+    bbs_repository::{CreatingAuditLog, RepositoryError},
+    utils::to_hex,
+    Ctx,
+};
+
+mod audit_logs;
+mod duplicate_posts;
+mod held_posts;
+mod ip_bans;
+mod reports;
+mod responses;
+mod threads;
+mod users;
+
+pub(crate) use audit_logs::route_admin_audit_logs;
+pub(crate) use duplicate_posts::route_admin_duplicate_posts;
+pub(crate) use held_posts::{
+    route_admin_discard_held_post, route_admin_held_posts, route_admin_release_held_post,
+};
+pub(crate) use ip_bans::{
+    route_admin_create_ip_ban, route_admin_delete_ip_ban, route_admin_ip_bans,
+};
+pub(crate) use reports::{route_admin_reports, route_admin_resolve_reports};
+pub(crate) use responses::{
+    route_admin_delete_response, route_admin_responses, route_admin_restore_response,
+};
+pub(crate) use threads::{
+    route_admin_delete_thread, route_admin_move_thread, route_admin_rename_thread,
+    route_admin_stop_thread, route_admin_thread, route_admin_unstop_thread,
+};
+pub(crate) use users::{route_admin_ban_user, route_admin_unban_user, route_admin_user};
+
+/// Failures of the admin API, answered as JSON
+#[derive(Debug)]
+pub(crate) enum AdminError {
+    Unauthorized,
+    NotFound(&'static str),
+    BadRequest(&'static str),
+    Database(RepositoryError),
+    Worker(worker::Error),
+}
+
+impl From<worker::Error> for AdminError {
+    fn from(e: worker::Error) -> Self {
+        AdminError::Worker(e)
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+}
+
+impl AdminError {
+    /// Maps a repository error, treating missing rows as `NotFound(what)`
+    pub(crate) fn from_repository(e: RepositoryError, what: &'static str) -> Self {
+        match e {
+            RepositoryError::NotFound => AdminError::NotFound(what),
+            e => AdminError::Database(e),
+        }
+    }
+
+    pub(crate) fn into_response(self) -> worker::Result<Response> {
+        let (message, status) = match &self {
+            AdminError::Unauthorized => ("unauthorized", 401),
+            AdminError::NotFound(what) => (*what, 404),
+            AdminError::BadRequest(what) => (*what, 400),
+            AdminError::Database(RepositoryError::Unavailable(e)) => {
+                console_error!("database unavailable: {e:?}");
+                ("database unavailable", 503)
+            }
+            AdminError::Database(e) => {
+                console_error!("database error: {e:?}");
+                ("database error", 500)
+            }
+            AdminError::Worker(e) => {
+                console_error!("worker error: {e:?}");
+                ("internal server error", 500)
+            }
+        };
+        Ok(Response::from_json(&ErrorBody { error: message })?.with_status(status))
+    }
+}
+
+pub(crate) type AdminResult<T> = std::result::Result<T, AdminError>;
+
+/// The admin making an authenticated request
+pub(crate) struct Admin {
+    /// Names the admin in the audit log. It is derived from the token so that the log reveals
+    /// neither the token nor the Google account behind it.
+    pub(crate) actor: String,
+}
+
+impl Admin {
+    fn new(token: &str) -> Self {
+        let hash = sha3::Sha3_256::digest(format!("audit-log:{token}").as_bytes());
+        Self {
+            actor: to_hex(&hash[..8]),
+        }
+    }
+
+    /// Starts an audit log entry of `action` by this admin
+    pub(crate) fn audit_log(&self, action: &'static str) -> CreatingAuditLog {
+        CreatingAuditLog {
+            actor: self.actor.clone(),
+            action,
+            ..Default::default()
+        }
+    }
+}
+
+/// Authenticates the request by `Authorization: Bearer <user token>`.
+///
+/// Admins are the users whose token is listed in the comma separated `ADMIN_USER_HASHES`
+/// secret; without the secret nobody is an admin.
+pub(crate) fn authenticate(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Admin> {
+    let token = req
+        .headers()
+        .get("Authorization")
+        .ok()
+        .flatten()
+        .and_then(|x| x.strip_prefix("Bearer ").map(|x| x.trim().to_string()))
+        .filter(|x| !x.is_empty())
+        .ok_or(AdminError::Unauthorized)?;
+    let admins = ctx
+        .secret("ADMIN_USER_HASHES")
+        .map(|x| x.to_string())
+        .unwrap_or_default();
+
+    if admins.split(',').any(|x| x.trim() == token) {
+        Ok(Admin::new(&token))
+    } else {
+        Err(AdminError::Unauthorized)
+    }
+}
+
+pub(crate) fn parse_thread_key(ctx: &RouteContext<Ctx>) -> AdminResult<i64> {
+    ctx.param("threadKey")
+        .and_then(|x| x.parse::<i64>().ok())
+        .ok_or(AdminError::BadRequest("invalid thread key"))
+}
+
+/// Drops what `route_dat` and `route_subject_txt` cached for the thread and its board
+pub(crate) async fn invalidate_thread_caches(req: &Request, board_key: &str, thread_key: i64) {
+    let Ok(url) = req.url() else {
+        return;
+    };
+    let origin = url.origin().ascii_serialization();
+    let cache = Cache::default();
+    for path in [
+        format!("/{board_key}/dat/{thread_key}.dat"),
+        format!("/{board_key}/subject.txt"),
+        format!("/{board_key}/subject_mate.txt"),
+    ] {
+        if let Err(e) = cache.delete(format!("{origin}{path}"), false).await {
+            console_error!("failed to invalidate cache of {path}: {e:?}");
+        }
+    }
+}
+
+/// Appends an entry to the audit log. Failing to do so is only logged, as the action it
+/// describes has already been made.
+pub(crate) async fn record_audit_log(ctx: &RouteContext<Ctx>, log: CreatingAuditLog) {
+    let action = log.action;
+    if let Err(e) = ctx.data.bbs_repository.create_audit_log(log).await {
+        console_error!("failed to record audit log of {action}: {e:?}");
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct ReasonRequest {
+    #[serde(default)]
+    reason: String,
+}
+
+/// The `reason` of an action whose request body is optional, empty when it is not given
+pub(crate) async fn optional_reason(req: &mut Request) -> String {
+    req.json::<ReasonRequest>()
+        .await
+        .map(|x| x.reason)
+        .unwrap_or_default()
+}
+
+/// The value of the query parameter `key`, if given
+pub(crate) fn query_param(url: &Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.into_owned())
+}
+
+/// The unix time `duration_seconds` after `now`, or 0 (no expiry) when no duration is given
+pub(crate) fn expiry_after(now: i64, duration_seconds: Option<u64>) -> AdminResult<i64> {
+    match duration_seconds {
+        None => Ok(0),
+        Some(0) => Err(AdminError::BadRequest("duration_seconds must be positive")),
+        Some(x) => i64::try_from(x)
+            .ok()
+            .and_then(|x| now.checked_add(x))
+            .ok_or(AdminError::BadRequest("duration_seconds is too large")),
+    }
+}
+
+/// `limit` and `offset` from the query; `limit` defaults to `default` and is capped at `max`
+pub(crate) fn paging(url: &Url, default: u64, max: u64) -> (u64, u64) {
+    let number = |key: &str| query_param(url, key).and_then(|x| x.parse::<u64>().ok());
+    let limit = number("limit").unwrap_or(default).min(max);
+    let offset = number("offset").unwrap_or(0);
+    (limit, offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bans_expire_after_their_duration() {
+        assert_eq!(expiry_after(1_700_000_000, None).unwrap(), 0);
+        assert_eq!(
+            expiry_after(1_700_000_000, Some(60)).unwrap(),
+            1_700_000_060
+        );
+        assert!(matches!(
+            expiry_after(1_700_000_000, Some(0)),
+            Err(AdminError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn rejects_durations_past_the_end_of_time() {
+        for duration in [u64::MAX, i64::MAX as u64, i64::MAX as u64 - 1_699_999_999] {
+            assert!(matches!(
+                expiry_after(1_700_000_000, Some(duration)),
+                Err(AdminError::BadRequest(_))
+            ));
+        }
+        assert_eq!(
+            expiry_after(1_700_000_000, Some(i64::MAX as u64 - 1_700_000_000)).unwrap(),
+            i64::MAX
+        );
+    }
+}
@@ -0,0 +1,88 @@
+use serde::Serialize;
+use worker::{Request, Response, Result, RouteContext};
+
+use super::{authenticate, paging, query_param, AdminError, AdminResult};
+use crate::{bbs_repository::AuditLogFilter, dtos::AuditLog, Ctx};
+
+const DEFAULT_ENTRIES_LIMIT: u64 = 100;
+const MAX_ENTRIES_LIMIT: u64 = 500;
+
+#[derive(Debug, Serialize)]
+struct AdminAuditLog {
+    id: String,
+    actor: String,
+    action: String,
+    board_key: Option<String>,
+    thread_key: Option<i64>,
+    response_number: Option<i32>,
+    user_hash: Option<String>,
+    reason: String,
+    detail: String,
+    created_at: String,
+}
+
+impl From<AuditLog> for AdminAuditLog {
+    fn from(log: AuditLog) -> Self {
+        Self {
+            id: log.id,
+            actor: log.actor,
+            action: log.action,
+            board_key: Some(log.board_key).filter(|x| !x.is_empty()),
+            thread_key: Some(log.thread_key).filter(|x| *x != 0),
+            response_number: Some(log.response_number).filter(|x| *x != 0),
+            user_hash: Some(log.user_hash).filter(|x| !x.is_empty()),
+            reason: log.reason,
+            detail: log.detail,
+            created_at: log.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AuditLogPage {
+    entries: Vec<AdminAuditLog>,
+    /// `offset` of the next page, `None` on the last page
+    next_offset: Option<u64>,
+}
+
+pub async fn route_admin_audit_logs(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_audit_logs(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// Entries newest first, filtered by the `actor`, `action`, `board_key`, `thread_key` and
+/// `user_hash` (derived from the target's, see [`crate::utils::derive_user_hash`]) query
+/// parameters and paged by `limit` and `offset`
+async fn admin_audit_logs(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let url = req.url()?;
+    let (limit, offset) = paging(&url, DEFAULT_ENTRIES_LIMIT, MAX_ENTRIES_LIMIT);
+    let thread_key = match query_param(&url, "thread_key") {
+        Some(x) => x
+            .parse::<i64>()
+            .map_err(|_| AdminError::BadRequest("invalid thread key"))?,
+        None => 0,
+    };
+    let filter = AuditLogFilter {
+        actor: query_param(&url, "actor").unwrap_or_default(),
+        action: query_param(&url, "action").unwrap_or_default(),
+        board_key: query_param(&url, "board_key").unwrap_or_default(),
+        thread_key,
+        user_hash: query_param(&url, "user_hash").unwrap_or_default(),
+        limit,
+        offset,
+    };
+
+    let entries = ctx
+        .data
+        .bbs_repository
+        .get_audit_logs(filter)
+        .await
+        .map_err(AdminError::Database)?;
+    let next_offset = (entries.len() as u64 == limit && limit > 0).then_some(offset + limit);
+    Ok(Response::from_json(&AuditLogPage {
+        entries: entries.into_iter().map(AdminAuditLog::from).collect(),
+        next_offset,
+    })?)
+}
@@ -0,0 +1,90 @@
+use serde::Serialize;
+use worker::{Request, Response, Result, RouteContext};
+
+use super::{authenticate, paging, query_param, AdminError, AdminResult};
+use crate::{
+    bbs_repository::DuplicatePostFilter, dtos::DuplicatePost, utils::derive_user_hash, Ctx,
+};
+
+const DEFAULT_ENTRIES_LIMIT: u64 = 100;
+const MAX_ENTRIES_LIMIT: u64 = 500;
+
+#[derive(Debug, Serialize)]
+struct AdminDuplicatePost {
+    id: String,
+    board_key: String,
+    /// `None` when the post was a new thread
+    thread_key: Option<i64>,
+    body: String,
+    original_response_id: String,
+    ip_address: String,
+    /// [`derive_user_hash`] of the poster, as the user hash is the poster's login token
+    user_hash: String,
+    created_at: String,
+}
+
+impl From<DuplicatePost> for AdminDuplicatePost {
+    fn from(post: DuplicatePost) -> Self {
+        Self {
+            id: post.id,
+            board_key: post.board_key,
+            thread_key: Some(post.thread_key).filter(|x| *x != 0),
+            body: post.body,
+            original_response_id: post.original_response_id,
+            ip_address: post.ip_address,
+            user_hash: derive_user_hash(&post.user_id),
+            created_at: post.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct DuplicatePostPage {
+    entries: Vec<AdminDuplicatePost>,
+    /// `offset` of the next page, `None` on the last page
+    next_offset: Option<u64>,
+}
+
+pub async fn route_admin_duplicate_posts(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_duplicate_posts(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// Posts rejected as duplicates, newest first, filtered by the `board_key` and `user_hash`
+/// (the derived one) query parameters and paged by `limit` and `offset`
+async fn admin_duplicate_posts(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let url = req.url()?;
+    let (limit, offset) = paging(&url, DEFAULT_ENTRIES_LIMIT, MAX_ENTRIES_LIMIT);
+    let user_hash = match query_param(&url, "user_hash").filter(|x| !x.is_empty()) {
+        Some(derived_hash) => {
+            ctx.data
+                .bbs_repository
+                .get_user_by_derived_hash(&derived_hash)
+                .await
+                .map_err(AdminError::Database)?
+                .ok_or(AdminError::NotFound("user not found"))?
+                .user_hash
+        }
+        None => String::new(),
+    };
+    let filter = DuplicatePostFilter {
+        board_key: query_param(&url, "board_key").unwrap_or_default(),
+        user_hash,
+        limit,
+        offset,
+    };
+
+    let entries = ctx
+        .data
+        .bbs_repository
+        .get_duplicate_posts(filter)
+        .await
+        .map_err(AdminError::Database)?;
+    let next_offset = (entries.len() as u64 == limit && limit > 0).then_some(offset + limit);
+    Ok(Response::from_json(&DuplicatePostPage {
+        entries: entries.into_iter().map(AdminDuplicatePost::from).collect(),
+        next_offset,
+    })?)
+}
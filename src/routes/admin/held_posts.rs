@@ -0,0 +1,232 @@
+use serde::Serialize;
+use worker::{Request, Response, Result, RouteContext};
+
+use super::{
+    authenticate, invalidate_thread_caches, optional_reason, paging, record_audit_log, Admin,
+    AdminError, AdminResult,
+};
+use crate::{
+    bbs_repository::{CreatingAuditLog, CreatingResponse, CreatingThread, RepositoryError},
+    dtos::{Board, HeldPost},
+    utils::derive_user_hash,
+    Ctx,
+};
+
+const DEFAULT_QUEUE_LIMIT: u64 = 100;
+const MAX_QUEUE_LIMIT: u64 = 500;
+
+#[derive(Debug, Serialize)]
+struct AdminHeldPost {
+    id: String,
+    board_key: String,
+    /// `None` when the post is a new thread
+    thread_key: Option<i64>,
+    /// `None` when the post is a response
+    title: Option<String>,
+    name: String,
+    mail: String,
+    body: String,
+    author_id: String,
+    date: String,
+    ip_address: String,
+    /// [`derive_user_hash`] of the poster, as the user hash is the poster's login token
+    user_hash: String,
+    ng_word_id: i32,
+    cap_id: i32,
+    created_at: String,
+}
+
+impl AdminHeldPost {
+    fn new(post: HeldPost, ctx: &RouteContext<Ctx>) -> Self {
+        Self {
+            id: post.id,
+            board_key: ctx
+                .data
+                .boards
+                .get_board_by_id(post.board_id)
+                .map(|x| x.board_key.clone())
+                .unwrap_or_default(),
+            thread_key: Some(post.thread_key).filter(|x| *x != 0),
+            title: (post.thread_key == 0).then_some(post.title),
+            name: post.name,
+            mail: post.mail,
+            body: post.body,
+            author_id: post.author_id,
+            date: post.date_text,
+            ip_address: post.ip_address,
+            user_hash: derive_user_hash(&post.user_id),
+            ng_word_id: post.ng_word_id,
+            cap_id: post.cap_id,
+            created_at: post.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct HeldPostPage {
+    entries: Vec<AdminHeldPost>,
+    /// `offset` of the next page, `None` on the last page
+    next_offset: Option<u64>,
+}
+
+pub async fn route_admin_held_posts(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_held_posts(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// Posts held by NG words, oldest first, paged by the `limit` and `offset` query parameters
+async fn admin_held_posts(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let url = req.url()?;
+    let (limit, offset) = paging(&url, DEFAULT_QUEUE_LIMIT, MAX_QUEUE_LIMIT);
+
+    let entries = ctx
+        .data
+        .bbs_repository
+        .get_held_posts(limit, offset)
+        .await
+        .map_err(AdminError::Database)?;
+    let next_offset = (entries.len() as u64 == limit && limit > 0).then_some(offset + limit);
+    Ok(Response::from_json(&HeldPostPage {
+        entries: entries
+            .into_iter()
+            .map(|x| AdminHeldPost::new(x, ctx))
+            .collect(),
+        next_offset,
+    })?)
+}
+
+pub async fn route_admin_release_held_post(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    release_held_post(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// Publishes the held post as it was posted, with its original date and ID
+async fn release_held_post(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let (post, board) = find_held_post(ctx).await?;
+    let reason = optional_reason(req).await;
+
+    let repo = &ctx.data.bbs_repository;
+    let thread_key = post.thread_key;
+    let result = if thread_key == 0 {
+        repo.create_thread(CreatingThread {
+            board_id: board.id,
+            max_thread_count: board.max_thread_count,
+            title: post.title.clone(),
+            name: post.name.clone(),
+            mail: post.mail.clone(),
+            body: post.body.clone(),
+            date: post.date_text.clone(),
+            author_id: post.author_id.clone(),
+            ip_addr: post.ip_address.clone(),
+            user_hash: post.user_id.clone(),
+            cap_id: post.cap_id,
+        })
+        .await
+    } else {
+        repo.create_response(CreatingResponse {
+            board_id: board.id,
+            thread_key,
+            max_response_count: board.max_response_count,
+            name: post.name.clone(),
+            mail: post.mail.clone(),
+            body: post.body.clone(),
+            date: post.date_text.clone(),
+            author_id: post.author_id.clone(),
+            ip_addr: post.ip_address.clone(),
+            user_hash: post.user_id.clone(),
+            cap_id: post.cap_id,
+            allow_stopped: false,
+        })
+        .await
+    };
+    result.map_err(|e| match e {
+        RepositoryError::ThreadStopped => {
+            AdminError::BadRequest("thread does not accept responses")
+        }
+        e => AdminError::from_repository(e, "thread not found"),
+    })?;
+    // The post is published already; left in the queue, it would be released twice
+    repo.delete_held_post(&post.id)
+        .await
+        .map_err(AdminError::Database)?;
+    record_audit_log(
+        ctx,
+        held_post_audit_log(&admin, "release_held_post", &post, &board, reason),
+    )
+    .await;
+    invalidate_thread_caches(req, &board.board_key, thread_key).await;
+
+    Ok(Response::from_json(&AdminHeldPost::new(post, ctx))?)
+}
+
+pub async fn route_admin_discard_held_post(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    discard_held_post(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn discard_held_post(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let (post, board) = find_held_post(ctx).await?;
+    let reason = optional_reason(req).await;
+
+    ctx.data
+        .bbs_repository
+        .delete_held_post(&post.id)
+        .await
+        .map_err(AdminError::Database)?;
+    record_audit_log(
+        ctx,
+        held_post_audit_log(&admin, "discard_held_post", &post, &board, reason),
+    )
+    .await;
+
+    Ok(Response::from_json(&AdminHeldPost::new(post, ctx))?)
+}
+
+/// The held post named by the `heldPostId` parameter, with its board
+async fn find_held_post(ctx: &RouteContext<Ctx>) -> AdminResult<(HeldPost, Board)> {
+    let id = ctx
+        .param("heldPostId")
+        .ok_or(AdminError::BadRequest("invalid held post id"))?;
+    let post = ctx
+        .data
+        .bbs_repository
+        .get_held_post(id)
+        .await
+        .map_err(|e| AdminError::from_repository(e, "held post not found"))?;
+    let board = ctx
+        .data
+        .boards
+        .get_board_by_id(post.board_id)
+        .ok_or(AdminError::NotFound("board not found"))?
+        .clone();
+    Ok((post, board))
+}
+
+fn held_post_audit_log(
+    admin: &Admin,
+    action: &'static str,
+    post: &HeldPost,
+    board: &Board,
+    reason: String,
+) -> CreatingAuditLog {
+    CreatingAuditLog {
+        board_key: board.board_key.clone(),
+        thread_key: post.thread_key,
+        user_hash: derive_user_hash(&post.user_id),
+        reason,
+        detail: format!("{} ng_word {}", post.id, post.ng_word_id),
+        ..admin.audit_log(action)
+    }
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+use worker::{Date, Request, Response, Result, RouteContext};
+
+use super::{
+    authenticate, expiry_after, optional_reason, record_audit_log, AdminError, AdminResult,
+};
+use crate::{
+    bbs_repository::{CreatingAuditLog, CreatingIpBan},
+    dtos::IpBan,
+    ip_ban::IpRange,
+    Ctx, IP_BANS_CACHE,
+};
+
+#[derive(Debug, Serialize)]
+struct AdminIpBan {
+    id: String,
+    cidr: String,
+    /// `None` for a ban on every board
+    board_key: Option<String>,
+    reason: String,
+    /// unix timestamp (seconds), `None` for a permanent ban
+    expires_at: Option<i64>,
+    created_at: String,
+}
+
+impl AdminIpBan {
+    fn new(ban: IpBan, ctx: &RouteContext<Ctx>) -> Self {
+        Self {
+            id: ban.id,
+            cidr: ban.cidr,
+            board_key: ctx
+                .data
+                .boards
+                .get_board_by_id(ban.board_id)
+                .map(|x| x.board_key.clone()),
+            reason: ban.reason,
+            expires_at: (ban.expires_at != 0).then_some(ban.expires_at),
+            created_at: ban.created_at,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct IpBanRequest {
+    cidr: String,
+    /// Omitted for a ban on every board
+    board_key: Option<String>,
+    #[serde(default)]
+    reason: String,
+    /// Omitted for a permanent ban
+    duration_seconds: Option<u64>,
+}
+
+pub async fn route_admin_ip_bans(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_ip_bans(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn admin_ip_bans(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let bans = ctx
+        .data
+        .bbs_repository
+        .get_ip_bans()
+        .await
+        .map_err(AdminError::Database)?
+        .into_iter()
+        .map(|x| AdminIpBan::new(x, ctx))
+        .collect::<Vec<_>>();
+    Ok(Response::from_json(&bans)?)
+}
+
+pub async fn route_admin_create_ip_ban(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    create_ip_ban(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn create_ip_ban(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let ban = req
+        .json::<IpBanRequest>()
+        .await
+        .map_err(|_| AdminError::BadRequest("invalid request body"))?;
+    let range = ban
+        .cidr
+        .parse::<IpRange>()
+        .map_err(|_| AdminError::BadRequest("invalid cidr"))?;
+    let board_id = match &ban.board_key {
+        Some(board_key) => {
+            ctx.data
+                .boards
+                .get_board_by_key(board_key)
+                .ok_or(AdminError::NotFound("board not found"))?
+                .id
+        }
+        None => 0,
+    };
+    let expires_at = expiry_after(
+        (Date::now().as_millis() / 1000) as i64,
+        ban.duration_seconds,
+    )?;
+
+    let repo = &ctx.data.bbs_repository;
+    let id = repo
+        .create_ip_ban(CreatingIpBan {
+            cidr: range.to_string(),
+            board_id,
+            reason: ban.reason.clone(),
+            expires_at,
+        })
+        .await
+        .map_err(AdminError::Database)?;
+    IP_BANS_CACHE.invalidate();
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            board_key: ban.board_key.unwrap_or_default(),
+            reason: ban.reason,
+            detail: format!("{id} {range}"),
+            ..admin.audit_log("create_ip_ban")
+        },
+    )
+    .await;
+
+    let created = repo
+        .get_ip_bans()
+        .await
+        .map_err(AdminError::Database)?
+        .into_iter()
+        .find(|x| x.id == id)
+        .ok_or(AdminError::NotFound("ip ban not found"))?;
+    Ok(Response::from_json(&AdminIpBan::new(created, ctx))?.with_status(201))
+}
+
+pub async fn route_admin_delete_ip_ban(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    delete_ip_ban(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn delete_ip_ban(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let id = ctx
+        .param("banId")
+        .ok_or(AdminError::BadRequest("invalid ban id"))?;
+    let reason = optional_reason(req).await;
+
+    let repo = &ctx.data.bbs_repository;
+    let ban = repo
+        .get_ip_bans()
+        .await
+        .map_err(AdminError::Database)?
+        .into_iter()
+        .find(|x| &x.id == id)
+        .ok_or(AdminError::NotFound("ip ban not found"))?;
+    repo.delete_ip_ban(id).await.map_err(AdminError::Database)?;
+    IP_BANS_CACHE.invalidate();
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            board_key: ctx
+                .data
+                .boards
+                .get_board_by_id(ban.board_id)
+                .map(|x| x.board_key.clone())
+                .unwrap_or_default(),
+            reason,
+            detail: format!("{} {}", ban.id, ban.cidr),
+            ..admin.audit_log("delete_ip_ban")
+        },
+    )
+    .await;
+
+    Ok(Response::from_json(&AdminIpBan::new(ban, ctx))?)
+}
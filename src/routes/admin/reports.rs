@@ -0,0 +1,155 @@
+use std::cmp::Reverse;
+
+use serde::Serialize;
+use worker::{Request, Response, Result, RouteContext};
+
+use super::{authenticate, optional_reason, paging, record_audit_log, AdminError, AdminResult};
+use crate::{bbs_repository::CreatingAuditLog, dtos::Report, utils::derive_user_hash, Ctx};
+
+const DEFAULT_QUEUE_LIMIT: u64 = 100;
+const MAX_QUEUE_LIMIT: u64 = 500;
+
+#[derive(Debug, Serialize)]
+struct ReportEntry {
+    id: String,
+    /// [`derive_user_hash`] of the reporter, as the user hash is the reporter's login token
+    reporter_hash: String,
+    reason: String,
+    created_at: String,
+}
+
+/// The unresolved reports of one response
+#[derive(Debug, Serialize)]
+struct ReportedResponse {
+    response_id: String,
+    board_key: String,
+    thread_key: i64,
+    response_number: i32,
+    body: String,
+    /// [`derive_user_hash`] of the poster
+    author_hash: String,
+    deleted: bool,
+    report_count: usize,
+    /// Newest first
+    reports: Vec<ReportEntry>,
+}
+
+impl ReportedResponse {
+    fn new(report: &Report) -> Self {
+        Self {
+            response_id: report.response_id.clone(),
+            board_key: report.board_key.clone(),
+            thread_key: report.thread_key,
+            response_number: report.response_number,
+            body: report.body.clone(),
+            author_hash: derive_user_hash(&report.author_hash),
+            deleted: report.deleted == 1,
+            report_count: 0,
+            reports: Vec::new(),
+        }
+    }
+}
+
+/// Groups reports (newest first) by response, most reported first and then most recently
+/// reported first
+fn group_reports(reports: Vec<Report>) -> Vec<ReportedResponse> {
+    let mut groups = Vec::<ReportedResponse>::new();
+    for report in reports {
+        let idx = match groups
+            .iter()
+            .position(|x| x.response_id == report.response_id)
+        {
+            Some(idx) => idx,
+            None => {
+                groups.push(ReportedResponse::new(&report));
+                groups.len() - 1
+            }
+        };
+        let group = &mut groups[idx];
+        group.report_count += 1;
+        group.reports.push(ReportEntry {
+            id: report.id,
+            reporter_hash: derive_user_hash(&report.reporter_hash),
+            reason: report.reason,
+            created_at: report.created_at,
+        });
+    }
+    // Stable, so ties keep the order of their latest report
+    groups.sort_by_key(|x| Reverse(x.report_count));
+    groups
+}
+
+pub async fn route_admin_reports(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_reports(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// The moderation queue, paged by `limit` and `offset` over reported responses
+async fn admin_reports(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let (limit, offset) = paging(&req.url()?, DEFAULT_QUEUE_LIMIT, MAX_QUEUE_LIMIT);
+    let reports = ctx
+        .data
+        .bbs_repository
+        .get_unresolved_reports()
+        .await
+        .map_err(AdminError::Database)?;
+
+    let queue = group_reports(reports)
+        .into_iter()
+        .skip(offset as usize)
+        .take(limit as usize)
+        .collect::<Vec<_>>();
+    Ok(Response::from_json(&queue)?)
+}
+
+pub async fn route_admin_resolve_reports(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    resolve_reports(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// Removes the response from the queue, responding with what it had been reported for
+async fn resolve_reports(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let response_id = ctx
+        .param("responseId")
+        .ok_or(AdminError::BadRequest("invalid response id"))?
+        .to_string();
+    let reason = optional_reason(req).await;
+
+    let repo = &ctx.data.bbs_repository;
+    let reports = repo
+        .get_unresolved_reports()
+        .await
+        .map_err(AdminError::Database)?
+        .into_iter()
+        .filter(|x| x.response_id == response_id)
+        .collect::<Vec<_>>();
+    let reported = group_reports(reports)
+        .pop()
+        .ok_or(AdminError::NotFound("no unresolved reports"))?;
+
+    repo.resolve_reports(&response_id)
+        .await
+        .map_err(AdminError::Database)?;
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            board_key: reported.board_key.clone(),
+            thread_key: reported.thread_key,
+            response_number: reported.response_number,
+            user_hash: reported.author_hash.clone(),
+            reason,
+            detail: format!("{} reports", reported.report_count),
+            ..admin.audit_log("resolve_reports")
+        },
+    )
+    .await;
+
+    Ok(Response::from_json(&reported)?)
+}
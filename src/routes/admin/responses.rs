@@ -0,0 +1,142 @@
+use serde::Serialize;
+use worker::{Request, Response, Result, RouteContext};
+
+use super::{
+    authenticate, invalidate_thread_caches, optional_reason, parse_thread_key, record_audit_log,
+    AdminError, AdminResult,
+};
+use crate::{bbs_repository::CreatingAuditLog, dtos::Res, utils::derive_user_hash, Ctx};
+
+/// A response as moderators see it, including who posted it
+#[derive(Debug, Serialize)]
+struct AdminResponse {
+    /// 1-based number in the thread
+    number: usize,
+    id: String,
+    name: String,
+    mail: String,
+    body: String,
+    date_text: String,
+    author_id: String,
+    ip_address: String,
+    /// [`derive_user_hash`] of the poster, as the user hash is the poster's login token
+    user_hash: String,
+    created_at: String,
+    deleted: bool,
+}
+
+impl AdminResponse {
+    fn new(number: usize, res: Res) -> Self {
+        Self {
+            number,
+            id: res.id,
+            name: res.name,
+            mail: res.mail,
+            body: res.body,
+            date_text: res.date_text,
+            author_id: res.author_id,
+            ip_address: res.ip_address,
+            user_hash: derive_user_hash(&res.user_id),
+            created_at: res.created_at,
+            deleted: res.deleted == 1,
+        }
+    }
+}
+
+pub async fn route_admin_responses(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_responses(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn admin_responses(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(AdminError::NotFound("board not found"))?;
+    let thread_key = parse_thread_key(ctx)?;
+
+    let (_, responses) = ctx
+        .data
+        .bbs_repository
+        .get_thread_with_responses(board_key, thread_key)
+        .await
+        .map_err(|e| AdminError::from_repository(e, "thread not found"))?;
+
+    let responses = responses
+        .into_iter()
+        .enumerate()
+        .map(|(idx, res)| AdminResponse::new(idx + 1, res))
+        .collect::<Vec<_>>();
+    Ok(Response::from_json(&responses)?)
+}
+
+pub async fn route_admin_delete_response(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    set_response_deleted(&mut req, &ctx, true)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+pub async fn route_admin_restore_response(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    set_response_deleted(&mut req, &ctx, false)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn set_response_deleted(
+    req: &mut Request,
+    ctx: &RouteContext<Ctx>,
+    deleted: bool,
+) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(AdminError::NotFound("board not found"))?;
+    let thread_key = parse_thread_key(ctx)?;
+    let number = ctx
+        .param("resNum")
+        .and_then(|x| x.parse::<usize>().ok())
+        .filter(|x| *x > 0)
+        .ok_or(AdminError::BadRequest("invalid response number"))?;
+    let reason = optional_reason(req).await;
+
+    let repo = &ctx.data.bbs_repository;
+    let (_, responses) = repo
+        .get_thread_with_responses(board_key, thread_key)
+        .await
+        .map_err(|e| AdminError::from_repository(e, "thread not found"))?;
+    let mut res = responses
+        .into_iter()
+        .nth(number - 1)
+        .ok_or(AdminError::NotFound("response not found"))?;
+
+    repo.set_response_deleted(&res.id, deleted)
+        .await
+        .map_err(|e| AdminError::from_repository(e, "response not found"))?;
+    invalidate_thread_caches(req, board_key, thread_key).await;
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            board_key: board_key.clone(),
+            thread_key,
+            response_number: number as i32,
+            user_hash: derive_user_hash(&res.user_id),
+            reason,
+            ..admin.audit_log(if deleted {
+                "delete_response"
+            } else {
+                "restore_response"
+            })
+        },
+    )
+    .await;
+
+    res.deleted = deleted as i32;
+    Ok(Response::from_json(&AdminResponse::new(number, res))?)
+}
@@ -0,0 +1,262 @@
+use serde::{Deserialize, Serialize};
+use worker::{Request, Response, Result, RouteContext};
+
+use super::{
+    authenticate, invalidate_thread_caches, optional_reason, parse_thread_key, record_audit_log,
+    AdminError, AdminResult,
+};
+use crate::{
+    bbs_repository::{CreatingAuditLog, RepositoryError},
+    dtos::Thread,
+    routes::bbs_cgi::{sanitize_thread_name, sjis_len},
+    utils::derive_user_hash,
+    Ctx,
+};
+
+#[derive(Debug, Serialize)]
+struct AdminThread {
+    id: String,
+    board_key: String,
+    thread_key: i64,
+    title: String,
+    response_count: i32,
+    author_id: String,
+    ip_address: String,
+    /// [`derive_user_hash`] of the creator, as the user hash is the creator's login token
+    user_hash: String,
+    created_at: String,
+    stopped: bool,
+    archived: bool,
+}
+
+impl AdminThread {
+    fn new(thread: Thread, board_key: &str) -> Self {
+        Self {
+            id: thread.id,
+            board_key: board_key.to_string(),
+            thread_key: thread.thread_key,
+            title: thread.title,
+            response_count: thread.response_count,
+            author_id: thread.author_id,
+            ip_address: thread.ip_address,
+            user_hash: derive_user_hash(&thread.user_id),
+            created_at: thread.created_at,
+            stopped: thread.stopped == 1,
+            archived: thread.archived == 1,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RenameRequest {
+    title: String,
+    #[serde(default)]
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MoveRequest {
+    board_key: String,
+    #[serde(default)]
+    reason: String,
+}
+
+enum ThreadAction {
+    Stop,
+    Unstop,
+    Rename,
+    Move,
+    Delete,
+}
+
+async fn get_thread(ctx: &RouteContext<Ctx>) -> AdminResult<(String, Thread)> {
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(AdminError::NotFound("board not found"))?
+        .to_string();
+    let thread_key = parse_thread_key(ctx)?;
+    let thread = ctx
+        .data
+        .bbs_repository
+        .get_thread(&board_key, thread_key)
+        .await
+        .map_err(|e| AdminError::from_repository(e, "thread not found"))?;
+    Ok((board_key, thread))
+}
+
+pub async fn route_admin_thread(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_thread(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn admin_thread(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let (board_key, thread) = get_thread(ctx).await?;
+    Ok(Response::from_json(&AdminThread::new(thread, &board_key))?)
+}
+
+pub async fn route_admin_stop_thread(mut req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    thread_action(&mut req, &ctx, ThreadAction::Stop)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+pub async fn route_admin_unstop_thread(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    thread_action(&mut req, &ctx, ThreadAction::Unstop)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+pub async fn route_admin_rename_thread(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    thread_action(&mut req, &ctx, ThreadAction::Rename)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+pub async fn route_admin_move_thread(mut req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    thread_action(&mut req, &ctx, ThreadAction::Move)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+pub async fn route_admin_delete_thread(
+    mut req: Request,
+    ctx: RouteContext<Ctx>,
+) -> Result<Response> {
+    thread_action(&mut req, &ctx, ThreadAction::Delete)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+/// Applies the action and responds with the thread as it is afterwards
+async fn thread_action(
+    req: &mut Request,
+    ctx: &RouteContext<Ctx>,
+    action: ThreadAction,
+) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let (board_key, mut thread) = get_thread(ctx).await?;
+    let repo = &ctx.data.bbs_repository;
+    let mut final_board_key = board_key.clone();
+
+    let log = match action {
+        ThreadAction::Stop => {
+            let reason = optional_reason(req).await;
+            repo.set_thread_stopped(&thread.id, true)
+                .await
+                .map_err(AdminError::Database)?;
+            thread.stopped = 1;
+            CreatingAuditLog {
+                reason,
+                ..admin.audit_log("stop_thread")
+            }
+        }
+        ThreadAction::Unstop => {
+            let reason = optional_reason(req).await;
+            let board = ctx
+                .data
+                .boards
+                .get_board_by_id(thread.board_id)
+                .ok_or(AdminError::NotFound("board not found"))?;
+            if thread.response_count >= board.max_response_count {
+                return Err(AdminError::BadRequest("thread is full"));
+            }
+            repo.set_thread_stopped(&thread.id, false)
+                .await
+                .map_err(AdminError::Database)?;
+            thread.stopped = 0;
+            CreatingAuditLog {
+                reason,
+                ..admin.audit_log("unstop_thread")
+            }
+        }
+        ThreadAction::Rename => {
+            let rename = req
+                .json::<RenameRequest>()
+                .await
+                .map_err(|_| AdminError::BadRequest("invalid request body"))?;
+            let title = sanitize_thread_name(rename.title.trim());
+            if title.is_empty() {
+                return Err(AdminError::BadRequest("title must not be empty"));
+            }
+            let board = ctx
+                .data
+                .boards
+                .get_board_by_id(thread.board_id)
+                .ok_or(AdminError::NotFound("board not found"))?;
+            if sjis_len(&title) > board.subject_max_length.max(0) as usize {
+                return Err(AdminError::BadRequest("title is too long"));
+            }
+            repo.rename_thread(&thread.id, &title)
+                .await
+                .map_err(AdminError::Database)?;
+            let detail = format!("{} -> {title}", thread.title);
+            thread.title = title;
+            CreatingAuditLog {
+                reason: rename.reason,
+                detail,
+                ..admin.audit_log("rename_thread")
+            }
+        }
+        ThreadAction::Move => {
+            let to = req
+                .json::<MoveRequest>()
+                .await
+                .map_err(|_| AdminError::BadRequest("invalid request body"))?;
+            let to_board = ctx
+                .data
+                .boards
+                .get_board_by_key(&to.board_key)
+                .ok_or(AdminError::NotFound("board not found"))?;
+            repo.move_thread(&thread.id, to_board.id)
+                .await
+                .map_err(|e| match e {
+                    RepositoryError::Conflict(_) => {
+                        AdminError::BadRequest("thread key already exists on the board")
+                    }
+                    e => AdminError::from_repository(e, "thread not found"),
+                })?;
+            thread.board_id = to_board.id;
+            final_board_key = to_board.board_key.clone();
+            invalidate_thread_caches(req, &final_board_key, thread.thread_key).await;
+            CreatingAuditLog {
+                reason: to.reason,
+                detail: format!("{board_key} -> {final_board_key}"),
+                ..admin.audit_log("move_thread")
+            }
+        }
+        ThreadAction::Delete => {
+            let reason = optional_reason(req).await;
+            repo.delete_thread(&thread.id)
+                .await
+                .map_err(AdminError::Database)?;
+            CreatingAuditLog {
+                reason,
+                ..admin.audit_log("delete_thread")
+            }
+        }
+    };
+    invalidate_thread_caches(req, &board_key, thread.thread_key).await;
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            board_key: board_key.clone(),
+            thread_key: thread.thread_key,
+            user_hash: derive_user_hash(&thread.user_id),
+            ..log
+        },
+    )
+    .await;
+
+    Ok(Response::from_json(&AdminThread::new(
+        thread,
+        &final_board_key,
+    ))?)
+}
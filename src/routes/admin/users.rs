@@ -0,0 +1,201 @@
+use serde::{Deserialize, Serialize};
+use worker::{Date, Request, Response, Result, RouteContext};
+
+use super::{
+    authenticate, expiry_after, optional_reason, paging, record_audit_log, AdminError, AdminResult,
+};
+use crate::bbs_repository::CreatingAuditLog;
+use crate::{
+    dtos::{User, UserPost},
+    utils::derive_user_hash,
+    Ctx,
+};
+
+const DEFAULT_POSTS_LIMIT: u64 = 100;
+const MAX_POSTS_LIMIT: u64 = 500;
+
+#[derive(Debug, Serialize)]
+struct AdminUserPost {
+    id: String,
+    board_key: String,
+    thread_key: i64,
+    thread_title: String,
+    name: String,
+    mail: String,
+    body: String,
+    author_id: String,
+    date_text: String,
+    ip_address: String,
+    created_at: String,
+    deleted: bool,
+}
+
+impl From<UserPost> for AdminUserPost {
+    fn from(post: UserPost) -> Self {
+        Self {
+            id: post.id,
+            board_key: post.board_key,
+            thread_key: post.thread_key,
+            thread_title: post.thread_title,
+            name: post.name,
+            mail: post.mail,
+            body: post.body,
+            author_id: post.author_id,
+            date_text: post.date_text,
+            ip_address: post.ip_address,
+            created_at: post.created_at,
+            deleted: post.deleted == 1,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct AdminUser {
+    /// [`derive_user_hash`] of the user hash, which is the user's login token
+    user_hash: String,
+    ip_address: String,
+    created_at: String,
+    banned: bool,
+    ban_reason: Option<String>,
+    /// unix timestamp (seconds), `None` for a permanent ban or no ban
+    banned_until: Option<i64>,
+    posts: Vec<AdminUserPost>,
+}
+
+impl AdminUser {
+    fn new(user: User, posts: Vec<UserPost>) -> Self {
+        let banned = user.is_banned_at((Date::now().as_millis() / 1000) as i64);
+        Self {
+            ban_reason: banned.then_some(user.disabled_reason),
+            banned_until: (banned && user.disabled_until != 0).then_some(user.disabled_until),
+            banned,
+            user_hash: derive_user_hash(&user.user_hash),
+            ip_address: user.ip_address,
+            created_at: user.created_at,
+            posts: posts.into_iter().map(AdminUserPost::from).collect(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BanRequest {
+    #[serde(default)]
+    reason: String,
+    /// Omitted for a permanent ban
+    duration_seconds: Option<u64>,
+}
+
+/// The `userHash` parameter, which is the derived hash the admin API shows for users
+fn user_hash_param(ctx: &RouteContext<Ctx>) -> AdminResult<String> {
+    ctx.param("userHash")
+        .filter(|x| !x.is_empty())
+        .cloned()
+        .ok_or(AdminError::BadRequest("invalid user hash"))
+}
+
+async fn get_existing_user(ctx: &RouteContext<Ctx>, derived_hash: &str) -> AdminResult<User> {
+    ctx.data
+        .bbs_repository
+        .get_user_by_derived_hash(derived_hash)
+        .await
+        .map_err(AdminError::Database)?
+        .ok_or(AdminError::NotFound("user not found"))
+}
+
+/// Responds with the user and its posts, newest first, paged by `limit` and `offset`
+async fn respond_user(req: &Request, ctx: &RouteContext<Ctx>, user: User) -> AdminResult<Response> {
+    let (limit, offset) = paging(&req.url()?, DEFAULT_POSTS_LIMIT, MAX_POSTS_LIMIT);
+
+    let posts = ctx
+        .data
+        .bbs_repository
+        .get_posts_by_user(&user.user_hash, limit, offset)
+        .await
+        .map_err(AdminError::Database)?;
+    Ok(Response::from_json(&AdminUser::new(user, posts))?)
+}
+
+pub async fn route_admin_user(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    admin_user(&req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn admin_user(req: &Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    authenticate(req, ctx)?;
+    let user = get_existing_user(ctx, &user_hash_param(ctx)?).await?;
+    respond_user(req, ctx, user).await
+}
+
+pub async fn route_admin_ban_user(mut req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    ban_user(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn ban_user(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let derived_hash = user_hash_param(ctx)?;
+    let ban = req
+        .json::<BanRequest>()
+        .await
+        .map_err(|_| AdminError::BadRequest("invalid request body"))?;
+    let until = expiry_after(
+        (Date::now().as_millis() / 1000) as i64,
+        ban.duration_seconds,
+    )?;
+    let user_hash = get_existing_user(ctx, &derived_hash).await?.user_hash;
+
+    ctx.data
+        .bbs_repository
+        .disable_user(&user_hash, &ban.reason, until)
+        .await
+        .map_err(AdminError::Database)?;
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            user_hash: derived_hash.clone(),
+            reason: ban.reason,
+            detail: match until {
+                0 => "permanent".to_string(),
+                until => format!("until {until}"),
+            },
+            ..admin.audit_log("ban_user")
+        },
+    )
+    .await;
+
+    let user = get_existing_user(ctx, &derived_hash).await?;
+    respond_user(req, ctx, user).await
+}
+
+pub async fn route_admin_unban_user(mut req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    unban_user(&mut req, &ctx)
+        .await
+        .or_else(AdminError::into_response)
+}
+
+async fn unban_user(req: &mut Request, ctx: &RouteContext<Ctx>) -> AdminResult<Response> {
+    let admin = authenticate(req, ctx)?;
+    let derived_hash = user_hash_param(ctx)?;
+    let reason = optional_reason(req).await;
+    let user_hash = get_existing_user(ctx, &derived_hash).await?.user_hash;
+
+    ctx.data
+        .bbs_repository
+        .enable_user(&user_hash)
+        .await
+        .map_err(AdminError::Database)?;
+    record_audit_log(
+        ctx,
+        CreatingAuditLog {
+            user_hash: derived_hash.clone(),
+            reason,
+            ..admin.audit_log("unban_user")
+        },
+    )
+    .await;
+
+    let user = get_existing_user(ctx, &derived_hash).await?;
+    respond_user(req, ctx, user).await
+}
@@ -4,13 +4,17 @@ use oauth2::{
     PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, RevocationUrl, Scope, TokenResponse,
     TokenUrl,
 };
-use planetscale_driver::query;
 use serde::Deserialize;
 use sha3::Digest;
-use uuid::{NoContext, Timestamp};
-use worker::{Date, Request, Response, Result, RouteContext};
-
-use crate::{dtos::User, get_user_token_cookie, utils::response_shift_jis_text_html, Ctx};
+use worker::{Request, Response, Result, RouteContext};
+
+use crate::{
+    bbs_repository::RepositoryError,
+    get_user_token_cookie,
+    routes::route_error::RouteError,
+    utils::{response_shift_jis_text_html, to_hex},
+    Ctx,
+};
 
 #[derive(Debug, Clone, Deserialize)]
 struct GoogleUserInfo {
@@ -35,12 +39,8 @@ fn get_pkce_checker_cookie(req: &Request) -> Option<String> {
 pub async fn route_auth(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
     let cookie = get_user_token_cookie(&req);
     if let Some(cookie) = cookie {
-        let conn = ctx.data.db_conn.clone();
-        let user = query("SELECT * FROM users WHERE user_hash = '$0' LIMIT 1")
-            .bind(&cookie)
-            .fetch_one::<User>(&conn)
-            .await;
-        if user.is_ok() {
+        let user = ctx.data.bbs_repository.get_user(&cookie).await;
+        if matches!(user, Ok(Some(_))) {
             return Response::ok(format!(
                 "Your account is already logged in. \nToken: #{cookie}"
             ));
@@ -182,38 +182,31 @@ pub async fn route_auth(req: Request, ctx: RouteContext<Ctx>) -> Result<Response
             let mut sub_hash = user_info.sub;
             for _ in 0..10 {
                 let hash = sha3::Sha3_256::digest(format!("{sub_hash}{salt}").as_bytes());
-                sub_hash = hash.iter().fold(String::new(), |mut acc, x| {
-                    acc.push_str(&format!("{:02x}", x));
-                    acc
-                });
+                sub_hash = to_hex(&hash);
             }
             sub_hash.truncate(24);
             sub_hash
         };
 
-        let conn = ctx.data.db_conn.clone();
+        let repo = &ctx.data.bbs_repository;
 
         let ip_addr = req.headers().get("cf-connecting-ip").unwrap().unwrap();
-        let user_id = uuid::Uuid::new_v7(Timestamp::from_unix(
-            NoContext,
-            Date::now().as_millis() / 1000,
-            ((Date::now().as_millis() % 1000) * 1000) as u32,
-        ));
-
-        if query("SELECT * FROM users WHERE user_hash = '$0' LIMIT 1")
-            .bind(&sub_hash)
-            .fetch_one::<User>(&conn)
-            .await
-            .is_err()
-        {
-            query("INSERT INTO users (user_hash, ip_address, id) VALUES ('$0', '$1', '$2')")
-                .bind(&sub_hash)
-                .bind(ip_addr)
-                .bind(user_id.to_string())
-                .execute(&conn)
-                .await
-                .unwrap();
+
+        let created = match repo.get_user(&sub_hash).await {
+            Ok(Some(user)) if user.derived_hash.is_empty() => {
+                repo.set_derived_user_hash(&sub_hash).await
+            }
+            Ok(Some(_)) => Ok(()),
+            // A concurrent login of the same account may have created the user meanwhile
+            Ok(None) => match repo.create_user(&sub_hash, &ip_addr).await {
+                Err(RepositoryError::Conflict(_)) => Ok(()),
+                x => x,
+            },
+            Err(e) => Err(e),
         };
+        if let Err(e) = created {
+            return RouteError::Database(e).into_response();
+        }
 
         Response::ok(format!("token: #{sub_hash}")).map(|mut x| {
             x.headers_mut()
@@ -1,14 +1,22 @@
+use std::collections::HashMap;
+
 use base64::{engine::general_purpose, Engine};
 use pwhash::unix;
 use regex::Regex;
 use sha1::{Digest, Sha1};
-use worker::{Request, Response, Result, RouteContext};
+use worker::{console_error, Date, Request, Response, Result, RouteContext};
 
 use crate::{
-    bbs_repository::{CreatingResponse, CreatingThread},
-    get_user_token_cookie,
+    author_id::{calculate_author_id, AuthorIdScheme, AuthorIdSource, Device},
+    bbs_repository::{CreatingPost, CreatingResponse, CreatingThread, RepositoryError},
+    cap::{looks_like_user_token, mask_cap_marker, Cap},
+    dtos::{Board, IpBan, User},
+    duplicate_post::{find_duplicate_post, PostContent},
+    get_caps, get_ip_bans, get_ng_words, get_user_token_cookie,
+    ng_word::{NgWordVerdict, NgWordsCtx},
     utils::{
-        self, get_current_date_time, get_current_date_time_string, response_shift_jis_text_html,
+        self, format_unix_timestamp_jst, get_current_date_time_string, jst_date,
+        response_shift_jis_text_html, FormCharset, UrlDecodeError,
     },
     Ctx,
 };
@@ -23,7 +31,7 @@ fn sanitize(input: &str) -> String {
         .replace("&#10;", "")
 }
 
-fn sanitize_thread_name(input: &str) -> String {
+pub(crate) fn sanitize_thread_name(input: &str) -> String {
     let sanitized = sanitize(input);
     // Delete all of semicolon closing \n character references
     let re = Regex::new(r"&#([Xx]0*[aA]|0*10);").unwrap();
@@ -92,7 +100,7 @@ fn sanitize_non_semi_closing_num_char_refs(target: &str) -> String {
             sanitized.push(c);
             if c == '&' {
                 ampersand_used = i as isize;
-            } else if ampersand_used == (i as isize - 1) && c == '#' {
+            } else if i > 0 && ampersand_used == (i as isize - 1) && c == '#' {
                 in_num_ref = Some(NumRefKind::Undef);
             }
         }
@@ -113,8 +121,58 @@ struct BbsCgiForm {
     body: String,
     board_key: String,
     is_thread: bool,
-    thread_id: Option<String>,
-    cap: Option<String>,
+    thread_key: Option<i64>,
+    /// Segments of the mail field after `#`, unsanitized: the user token, a cap id and
+    /// password, or the token followed by a cap id and password
+    mail_secrets: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum FormError {
+    /// The body is not valid percent-encoded Shift_JIS or UTF-8
+    Malformed(UrlDecodeError),
+    MissingField(&'static str),
+    DuplicateField(String),
+    InvalidField(&'static str),
+}
+
+impl std::fmt::Display for FormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::Malformed(_) => write!(f, "フォームの内容が不正です。"),
+            FormError::MissingField(field) => write!(f, "{field}が指定されていません。"),
+            FormError::DuplicateField(field) => write!(f, "{field}が重複しています。"),
+            FormError::InvalidField(field) => write!(f, "{field}の値が不正です。"),
+        }
+    }
+}
+
+/// Decoded fields of a bbs.cgi form, each key appearing at most once
+struct FormFields<'a> {
+    fields: HashMap<&'a str, String>,
+}
+
+impl<'a> FormFields<'a> {
+    fn parse(data: &'a str, charset: Option<FormCharset>) -> std::result::Result<Self, FormError> {
+        let pairs = utils::shift_jis_url_encodeded_body_to_vec(data, charset)
+            .map_err(FormError::Malformed)?;
+
+        let mut fields = HashMap::new();
+        for (key, value) in pairs {
+            if fields.insert(key, value).is_some() {
+                return Err(FormError::DuplicateField(key.to_string()));
+            }
+        }
+        Ok(Self { fields })
+    }
+
+    fn get(&self, key: &str) -> Option<&str> {
+        self.fields.get(key).map(|x| x.as_str())
+    }
+
+    fn require(&self, key: &'static str) -> std::result::Result<&str, FormError> {
+        self.get(key).ok_or(FormError::MissingField(key))
+    }
 }
 
 pub struct TokenRemover {
@@ -137,81 +195,93 @@ impl TokenRemover {
     }
 }
 
-fn calculate_author_id(ip_addr: &str) -> String {
-    let date = get_current_date_time().date().to_string();
-    let mut id = calculate_trip(&format!("{ip_addr}{date}"));
-    id.truncate(9);
-    id
+/// Whether the form creates a new thread rather than replying to one.
+///
+/// `key` means a reply and `subject` a new thread. The submit label differs between browsers
+/// ("書き込む", "書き込み", "上記全てを承諾して書き込む", "新規スレッド作成", or none at all), so
+/// it is only a hint for forms carrying neither.
+fn is_thread_form(fields: &FormFields) -> bool {
+    if fields.get("key").is_some_and(|key| !key.is_empty()) {
+        return false;
+    }
+    if fields.get("subject").is_some() {
+        return true;
+    }
+    fields
+        .get("submit")
+        .is_some_and(|submit| submit.contains("スレッド"))
 }
 
-fn extract_forms(bytes: Vec<u8>) -> Option<BbsCgiForm> {
+/// Parses the form, whose values are in `charset` if the request declares one
+fn extract_forms(
+    bytes: Vec<u8>,
+    charset: Option<FormCharset>,
+) -> std::result::Result<BbsCgiForm, FormError> {
     let data = encoding_rs::SHIFT_JIS.decode(&bytes).0.to_string();
 
-    let Ok(result) = utils::shift_jis_url_encodeded_body_to_vec(&data) else {
-        return None;
-    };
-    let is_thread = {
-        let submit = &result["submit"];
-        match submit as &str {
-            "書き込む" => false,
-            "新規スレッド作成" => true,
-            // TODO: above comment
-            _ => return None,
-        }
-    };
+    let result = FormFields::parse(&data, charset)?;
+    let is_thread = is_thread_form(&result);
 
-    let mail_segments = result["mail"].split('#').collect::<Vec<_>>();
+    let mail_segments = result
+        .get("mail")
+        .unwrap_or_default()
+        .split('#')
+        .collect::<Vec<_>>();
     let mail = mail_segments[0];
-    let cap = if mail_segments.len() == 1 {
-        None
-    } else {
-        Some(sanitize(&mail_segments[1..].concat()))
-    };
+    let mail_secrets = mail_segments[1..]
+        .iter()
+        .map(|x| x.to_string())
+        .collect::<Vec<_>>();
 
     let subject = if is_thread {
-        Some(sanitize_thread_name(&result["subject"]).clone())
+        Some(sanitize_thread_name(result.require("subject")?))
     } else {
         None
     };
 
-    let name_segments = result["FROM"].split('#').collect::<Vec<_>>();
+    let name_segments = result
+        .get("FROM")
+        .unwrap_or_default()
+        .split('#')
+        .collect::<Vec<_>>();
     let name = name_segments[0];
     let name = if name_segments.len() == 1 {
         let token_remover = TokenRemover::new();
         let name = token_remover.remove(name.to_string());
-        sanitize(&name)
-            .replace('◆', "◇")
-            .replace("&#9670;", "◇")
-            .replace('★', "☆")
-            .replace("&#9733;", "☆")
+        mask_cap_marker(&sanitize(&name).replace('◆', "◇").replace("&#9670;", "◇"))
     } else {
         // TODO: smell
         let trip = sanitize(&name_segments[1..].concat())
             .replace('◆', "◇")
             .replace("&#9670;", "◇");
         let trip = calculate_trip(&trip);
-        format!("{name}◆{trip}")
+        let name = sanitize(name).replace('◆', "◇").replace("&#9670;", "◇");
+        format!("{}◆{trip}", mask_cap_marker(&name))
     };
 
     let mail = sanitize(mail).to_string();
-    let body = sanitize(&result["MESSAGE"]).clone();
-    let board_key = result["bbs"].clone();
+    let body = sanitize(result.require("MESSAGE")?);
+    let board_key = result.require("bbs")?.to_string();
 
-    let thread_id = if is_thread {
+    let thread_key = if is_thread {
         None
     } else {
-        Some(result["key"].clone())
+        let key = result.require("key")?;
+        match key.parse::<i64>() {
+            Ok(key) if key > 0 => Some(key),
+            _ => return Err(FormError::InvalidField("key")),
+        }
     };
 
-    Some(BbsCgiForm {
+    Ok(BbsCgiForm {
         subject,
         name,
         mail,
         body,
         board_key,
         is_thread,
-        thread_id,
-        cap,
+        thread_key,
+        mail_secrets,
     })
 }
 
@@ -246,40 +316,312 @@ pub fn calculate_trip(target: &str) -> String {
     }
 }
 
+pub(crate) fn sjis_len(input: &str) -> usize {
+    encoding_rs::SHIFT_JIS.encode(input).0.len()
+}
+
+fn is_blank(value: &str) -> bool {
+    value.replace("<br>", "").trim().is_empty()
+}
+
+fn validate_form(form: &BbsCgiForm, board: &Board) -> std::result::Result<(), &'static str> {
+    let exceeds = |value: &str, max: i32| sjis_len(value) > max.max(0) as usize;
+
+    if matches!(&form.subject, Some(subject) if is_blank(subject)) {
+        return Err("サブジェクトが存在しません！");
+    }
+    if is_blank(&form.body) {
+        return Err("本文がありません！");
+    }
+
+    if matches!(&form.subject, Some(subject) if exceeds(subject, board.subject_max_length)) {
+        return Err("サブジェクトが長すぎます！");
+    }
+    if exceeds(&form.name, board.name_max_length) {
+        return Err("名前が長すぎます！");
+    }
+    if exceeds(&form.mail, board.mail_max_length) {
+        return Err("メール欄が長すぎます！");
+    }
+    if exceeds(&form.body, board.message_max_length) {
+        return Err("本文が長すぎます！");
+    }
+    if form.body.matches("<br>").count() + 1 > board.message_max_lines.max(0) as usize {
+        return Err("改行が多すぎます！");
+    }
+
+    Ok(())
+}
+
+/// Applies the NG words to the form, then validates what their replacements leave of it.
+/// Returns the NG word holding the post for review, if any.
+fn screen_form(
+    form: &mut BbsCgiForm,
+    board: &Board,
+    ng_words: &NgWordsCtx,
+    bypasses_ng_words: bool,
+) -> std::result::Result<Option<i32>, &'static str> {
+    let mut fields = vec![&mut form.name, &mut form.body];
+    fields.extend(form.subject.as_mut());
+    let verdict = if bypasses_ng_words {
+        NgWordVerdict::Accept
+    } else {
+        ng_words.apply(board.id, &mut fields)
+    };
+    let held_by = match verdict {
+        NgWordVerdict::Accept => None,
+        NgWordVerdict::Reject => return Err("NGワードが含まれています。"),
+        NgWordVerdict::Hold { ng_word_id } => Some(ng_word_id),
+    };
+    validate_form(form, board)?;
+    Ok(held_by)
+}
+
+// Replaces numeric character references with "？" on boards which do not allow unicode
+fn apply_unicode_policy(form: BbsCgiForm, board: &Board) -> BbsCgiForm {
+    if board.allow_unicode == 1 {
+        return form;
+    }
+
+    let re = Regex::new(r"&#([Xx][0-9A-Fa-f]+|[0-9]+);").unwrap();
+    let replace = |input: &str| re.replace_all(input, "？").to_string();
+    BbsCgiForm {
+        subject: form.subject.as_deref().map(replace),
+        name: replace(&form.name),
+        mail: replace(&form.mail),
+        body: replace(&form.body),
+        ..form
+    }
+}
+
+// Dedicated browsers only show the message of a 200 response marked as 2ch_X:error
+fn response_bbs_cgi_error(message: &str) -> Result<Response> {
+    response_shift_jis_text_html(format!(
+        r#"<html><!-- 2ch_X:error -->
+
+<head>
+    <meta http-equiv="Content-Type" content="text/html; charset=x-sjis">
+    <title>ＥＲＲＯＲ！</title>
+</head>
+
+<body>ＥＲＲＯＲ：{message}</body>
+
+</html>"#
+    ))
+}
+
+fn ban_period(until: i64) -> String {
+    if until == 0 {
+        "無期限".to_string()
+    } else {
+        format!("{} まで", format_unix_timestamp_jst(until))
+    }
+}
+
+fn ban_reason(reason: &str) -> String {
+    if reason.is_empty() {
+        "(なし)".to_string()
+    } else {
+        sanitize(reason)
+    }
+}
+
+fn ip_ban_message(ban: &IpBan) -> String {
+    format!(
+        "このIPアドレスからの書き込みは規制されています。<br>理由: {}<br>期間: {}",
+        ban_reason(&ban.reason),
+        ban_period(ban.expires_at)
+    )
+}
+
+// The token itself stays valid, so the cookie is kept for when the ban ends
+fn ban_message(user: &User) -> String {
+    format!(
+        "このトークンは書き込みを停止されています。<br>理由: {}<br>期間: {}",
+        ban_reason(&user.disabled_reason),
+        ban_period(user.disabled_until)
+    )
+}
+
+/// The ID of the post under the board's scheme, or the error message to respond with
+fn author_id_of(
+    req: &Request,
+    ctx: &RouteContext<Ctx>,
+    board: &Board,
+    ip_addr: &str,
+    user_hash: &str,
+) -> std::result::Result<String, &'static str> {
+    let scheme = board
+        .author_id_scheme
+        .parse::<AuthorIdScheme>()
+        .unwrap_or_else(|()| {
+            console_error!(
+                "unknown author id scheme of board {}: {}",
+                board.id,
+                board.author_id_scheme
+            );
+            AuthorIdScheme::IpDaily
+        });
+    let now = chrono::DateTime::from_timestamp_millis(Date::now().as_millis() as i64);
+    let date = jst_date(now.unwrap_or_default());
+    let key = if scheme.needs_salt() {
+        let salts = ctx.data.author_id_salts.as_ref().map_err(|e| {
+            console_error!("{e}");
+            "書き込みに失敗しました。"
+        })?;
+        match salts.daily_key(date) {
+            Some(key) => key,
+            None => {
+                console_error!("no AUTHOR_ID_SALT is in effect on {date}");
+                return Err("書き込みに失敗しました。");
+            }
+        }
+    } else {
+        String::new()
+    };
+    let user_agent = req
+        .headers()
+        .get("User-Agent")
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+
+    Ok(calculate_author_id(
+        scheme,
+        AuthorIdSource {
+            ip_addr,
+            user_hash,
+            device: Device::from_user_agent(&user_agent),
+        },
+        &key,
+        date,
+    ))
+}
+
+/// Takes a cap id and password (`#<cap id>#<password>`) out of the end of the mail secrets.
+/// The remaining segments make up the user token, as they did before caps. A password shaped
+/// like a user token is left alone, so `#<cap id>#<user token>` never reads as a cap.
+fn take_cap_attempt(mail_secrets: &mut Vec<String>) -> Option<(i32, String)> {
+    let [.., id, password] = mail_secrets.as_slice() else {
+        return None;
+    };
+    let id = id.parse::<i32>().ok().filter(|x| *x > 0)?;
+    if looks_like_user_token(password) {
+        return None;
+    }
+    let password = mail_secrets.pop()?;
+    mail_secrets.pop();
+    Some((id, password))
+}
+
+/// The cap of the attempt, checked only while the IP address has not failed too often. Each
+/// failure counts against the IP address.
+async fn verify_cap(
+    ctx: &RouteContext<Ctx>,
+    board: &Board,
+    ip_addr: &str,
+    (id, password): (i32, String),
+) -> std::result::Result<Cap, String> {
+    let rate_limiter = &ctx.data.rate_limiter;
+    match rate_limiter.check_cap_failures(ip_addr).await {
+        Ok(Ok(())) => {}
+        Ok(Err(violation)) => return Err(violation.to_string()),
+        Err(e) => {
+            console_error!("failed to check cap failures: {e:?}");
+            return Err("書き込みに失敗しました。".to_string());
+        }
+    }
+    let caps = match get_caps(ctx.data.bbs_repository.as_ref()).await {
+        Ok(caps) => caps,
+        Err(e) => {
+            console_error!("failed to get caps: {e:?}");
+            return Err("書き込みに失敗しました。".to_string());
+        }
+    };
+    if let Some(cap) = caps.find(id, &password, board.id) {
+        return Ok(cap.clone());
+    }
+    if let Err(e) = rate_limiter.record_cap_failure(ip_addr).await {
+        console_error!("failed to record cap failure: {e:?}");
+    }
+    Err("キャップのIDかパスワードが違います。".to_string())
+}
+
+/// The error page to return when the post repeats a recent one of the same author
+async fn reject_duplicate_post(
+    ctx: &RouteContext<Ctx>,
+    board: &Board,
+    post: PostContent<'_>,
+) -> Option<Result<Response>> {
+    let now = (Date::now().as_millis() / 1000) as i64;
+    match find_duplicate_post(ctx.data.bbs_repository.as_ref(), board, post, now).await {
+        Ok(None) => None,
+        Ok(Some(_)) => Some(response_bbs_cgi_error(
+            "同じ内容の書き込みが続いています。しばらく待ってください。",
+        )),
+        Err(e) => {
+            console_error!("failed to check duplicate post: {e:?}");
+            Some(response_bbs_cgi_error("書き込みに失敗しました。"))
+        }
+    }
+}
+
 pub async fn route_bbs_cgi(mut req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
     let Ok(Some(ip_addr)) = req.headers().get("CF-Connecting-IP") else {
         return Response::error("internal server error - cf-connecting-ip", 500);
     };
     let Ok(req_bytes) = req.bytes().await else {
-        return Response::error("Bad request - read bytes", 400);
+        return response_bbs_cgi_error("リクエストを読み込めませんでした。");
     };
-    let form = match extract_forms(req_bytes) {
-        Some(form) => form,
-        None => return Response::error("Bad request - extract forms", 400),
+    let charset = req
+        .headers()
+        .get("Content-Type")
+        .ok()
+        .flatten()
+        .and_then(|x| FormCharset::from_content_type(&x));
+    let form = match extract_forms(req_bytes, charset) {
+        Ok(form) => form,
+        Err(e) => return response_bbs_cgi_error(&e.to_string()),
     };
 
     let Some(board) = ctx.data.boards.get_board_by_key(&form.board_key) else {
-        return Response::error("Not Found - board not found", 404);
+        return response_bbs_cgi_error("指定された板は存在しません。");
     };
+    // Checked before anything is written, whichever token the post carries
+    match get_ip_bans(ctx.data.bbs_repository.as_ref()).await {
+        Ok(ip_bans) => {
+            let now = (Date::now().as_millis() / 1000) as i64;
+            if let Some(ban) = ip_bans.find(&ip_addr, board.id, now) {
+                return response_bbs_cgi_error(&ip_ban_message(ban));
+            }
+        }
+        Err(e) => {
+            console_error!("failed to get ip bans: {e:?}");
+            return response_bbs_cgi_error("書き込みに失敗しました。");
+        }
+    }
+    let mut form = apply_unicode_policy(form, board);
 
-    let (user_token, cookie_token) = match (get_user_token_cookie(&req), form.cap) {
+    let mut mail_secrets = std::mem::take(&mut form.mail_secrets);
+    let cap_attempt = take_cap_attempt(&mut mail_secrets);
+    let mail_token = (!mail_secrets.is_empty()).then(|| sanitize(&mail_secrets.concat()));
+
+    let (user_token, cookie_token) = match (get_user_token_cookie(&req), mail_token) {
         (Some(user_token), _) => (Some(user_token), true),
         (_, Some(cap)) => (Some(cap), false),
         _ => (None, false),
     };
 
     let user_token = if let Some(user_token) = &user_token {
-        let user = ctx.data.bbs_repository.get_user(user_token).await.unwrap();
+        let user = match ctx.data.bbs_repository.get_user(user_token).await {
+            Ok(user) => user,
+            Err(e) => {
+                console_error!("failed to get user: {e:?}");
+                return response_bbs_cgi_error("ユーザー情報を取得できませんでした。");
+            }
+        };
         if user.is_none() {
-            return Response::error("Forbidden - given user token is invalid", 403).map(|mut x| {
-                x.headers_mut()
-                    .append("Set-Cookie", "user_token=; Max-Age=0; Path=/")
-                    .unwrap();
-                x
-            });
-        }
-        if matches!(user, Some(user) if user.disabled == 1) {
-            return Response::error("Forbidden - given user token is disabled", 403).map(
+            return response_bbs_cgi_error("トークンが無効です。再度認証してください。").map(
                 |mut x| {
                     x.headers_mut()
                         .append("Set-Cookie", "user_token=; Max-Age=0; Path=/")
@@ -288,6 +630,21 @@ pub async fn route_bbs_cgi(mut req: Request, ctx: RouteContext<Ctx>) -> Result<R
                 },
             );
         }
+        // Lets the admin API find users created before derived hashes were stored
+        if user.as_ref().is_some_and(|x| x.derived_hash.is_empty()) {
+            if let Err(e) = ctx
+                .data
+                .bbs_repository
+                .set_derived_user_hash(user_token)
+                .await
+            {
+                console_error!("failed to set derived user hash: {e:?}");
+            }
+        }
+        let now = (Date::now().as_millis() / 1000) as i64;
+        if let Some(user) = user.filter(|x| x.is_banned_at(now)) {
+            return response_bbs_cgi_error(&ban_message(&user));
+        }
         user_token
     } else {
         let host_url = ctx.env.var("GOOGLE_AUTH_REDIRECT_URI").unwrap().to_string();
@@ -307,53 +664,150 @@ pub async fn route_bbs_cgi(mut req: Request, ctx: RouteContext<Ctx>) -> Result<R
         ));
     };
 
-    if form.is_thread {
-        let title = form.subject.unwrap();
-
-        if let Err(e) = ctx
+    // Verified only for authenticated users, so that bcrypt never runs for anonymous floods
+    let cap = match cap_attempt {
+        Some(attempt) => match verify_cap(&ctx, board, &ip_addr, attempt).await {
+            Ok(cap) => Some(cap),
+            Err(message) => return response_bbs_cgi_error(&message),
+        },
+        None => None,
+    };
+    let role = cap.as_ref().map(|x| x.role);
+    let rate_limited = !role.is_some_and(|x| x.bypasses_rate_limit());
+    if rate_limited {
+        match ctx
             .data
-            .bbs_repository
-            .create_thread(CreatingThread {
-                board_id: board.id,
-                title,
-                name: form.name,
-                mail: form.mail,
-                body: form.body,
-                date: get_current_date_time_string(true),
-                author_id: calculate_author_id(&ip_addr),
-                ip_addr,
-                user_hash: user_token.clone(),
-            })
+            .rate_limiter
+            .check(user_token, &ip_addr, form.is_thread)
             .await
         {
-            return if e.to_string().contains("No results found") {
-                Response::error("Not Found - board not found", 404)
-            } else {
-                Response::error("internal server error - create thread", 500)
+            Ok(Ok(())) => {}
+            Ok(Err(violation)) => return response_bbs_cgi_error(&violation.to_string()),
+            Err(e) => {
+                console_error!("failed to check rate limit: {e:?}");
+                return response_bbs_cgi_error("書き込みに失敗しました。");
+            }
+        }
+    }
+    let is_thread = form.is_thread;
+    let limited_ip_addr = ip_addr.clone();
+
+    let ng_words = match get_ng_words(ctx.data.bbs_repository.as_ref()).await {
+        Ok(ng_words) => ng_words,
+        Err(e) => {
+            console_error!("failed to get ng words: {e:?}");
+            return response_bbs_cgi_error("書き込みに失敗しました。");
+        }
+    };
+    let bypasses_ng_words = role.is_some_and(|x| x.bypasses_ng_words());
+    let held_by = match screen_form(&mut form, board, &ng_words, bypasses_ng_words) {
+        Ok(held_by) => held_by,
+        Err(message) => return response_bbs_cgi_error(message),
+    };
+    let author_id = match author_id_of(&req, &ctx, board, &ip_addr, user_token) {
+        Ok(author_id) => author_id,
+        Err(message) => return response_bbs_cgi_error(message),
+    };
+    // After the NG words so that they never rewrite the cap name
+    let cap_id = cap.as_ref().map_or(0, |x| x.id);
+    if let Some(cap) = &cap {
+        form.name = cap.display_name(&form.name);
+    }
+
+    if form.is_thread {
+        let thread = CreatingThread {
+            board_id: board.id,
+            max_thread_count: board.max_thread_count,
+            title: form.subject.unwrap(),
+            name: form.name,
+            mail: form.mail,
+            body: form.body,
+            date: get_current_date_time_string(true),
+            author_id: author_id.clone(),
+            ip_addr,
+            user_hash: user_token.clone(),
+            cap_id,
+        };
+        if let Some(resp) = reject_duplicate_post(&ctx, board, (&thread).into()).await {
+            return resp;
+        }
+
+        let result = match held_by {
+            Some(ng_word_id) => {
+                let post = CreatingPost::Thread(thread);
+                ctx.data
+                    .bbs_repository
+                    .create_held_post(post, ng_word_id)
+                    .await
+            }
+            None => ctx.data.bbs_repository.create_thread(thread).await,
+        };
+        if let Err(e) = result {
+            return match e {
+                RepositoryError::NotFound => response_bbs_cgi_error("指定された板は存在しません。"),
+                e => {
+                    console_error!("failed to create thread: {e:?}");
+                    response_bbs_cgi_error("スレッドを作成できませんでした。")
+                }
             };
         }
-    } else if let Err(e) = ctx
-        .data
-        .bbs_repository
-        .create_response(CreatingResponse {
+    } else {
+        let response = CreatingResponse {
             board_id: board.id,
-            thread_key: form.thread_id.unwrap().parse().unwrap(),
+            thread_key: form.thread_key.unwrap(),
+            max_response_count: board.max_response_count,
             name: form.name,
             mail: form.mail,
             body: form.body,
             date: get_current_date_time_string(true),
-            author_id: calculate_author_id(&ip_addr),
+            author_id: author_id.clone(),
             ip_addr,
             user_hash: user_token.clone(),
-        })
-        .await
-    {
-        return if e.to_string().contains("No results found") {
-            Response::error("Not Found - thread not found", 404)
-        } else {
-            Response::error("internal server error - create response", 500)
+            cap_id,
+            allow_stopped: role.is_some_and(|x| x.can_post_in_stopped_thread()),
+        };
+        if let Some(resp) = reject_duplicate_post(&ctx, board, (&response).into()).await {
+            return resp;
+        }
+
+        let result = match held_by {
+            Some(ng_word_id) => {
+                let post = CreatingPost::Response(response);
+                ctx.data
+                    .bbs_repository
+                    .create_held_post(post, ng_word_id)
+                    .await
+            }
+            None => ctx.data.bbs_repository.create_response(response).await,
         };
+        if let Err(e) = result {
+            return match e {
+                RepositoryError::NotFound => {
+                    response_bbs_cgi_error("指定されたスレッドは存在しません。")
+                }
+                RepositoryError::ThreadStopped => {
+                    response_bbs_cgi_error("このスレッドには書き込めません。")
+                }
+                e => {
+                    console_error!("failed to create response: {e:?}");
+                    response_bbs_cgi_error("書き込みに失敗しました。")
+                }
+            };
+        }
     }
+
+    // The post is already stored, so a failure here only loosens the limit
+    if rate_limited {
+        if let Err(e) = ctx
+            .data
+            .rate_limiter
+            .record(user_token, &limited_ip_addr, is_thread)
+            .await
+        {
+            console_error!("failed to record rate limit: {e:?}");
+        }
+    }
+
     let data = encoding_rs::SHIFT_JIS
         .encode(
             r#"<html><!-- 2ch_X:true -->
@@ -383,3 +837,283 @@ pub async fn route_bbs_cgi(mut req: Request, ctx: RouteContext<Ctx>) -> Result<R
 
     Ok(resp)
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    fn percent_encode(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("%{x:02X}")).collect()
+    }
+
+    /// Percent-encodes `value` as Shift_JIS, the way browsers send bbs.cgi forms
+    fn shift_jis(value: &str) -> String {
+        percent_encode(&encoding_rs::SHIFT_JIS.encode(value).0)
+    }
+
+    /// Percent-encodes `value` as UTF-8, the way some scripts send bbs.cgi forms
+    fn utf8(value: &str) -> String {
+        percent_encode(value.as_bytes())
+    }
+
+    fn form_with(fields: &[(&str, &str)], encode: fn(&str) -> String) -> Vec<u8> {
+        fields
+            .iter()
+            .map(|(key, value)| format!("{key}={}", encode(value)))
+            .collect::<Vec<_>>()
+            .join("&")
+            .into_bytes()
+    }
+
+    fn form(fields: &[(&str, &str)]) -> Vec<u8> {
+        form_with(fields, shift_jis)
+    }
+
+    /// Submit labels sent by known browsers, `None` for those sending no `submit`
+    const SUBMIT_LABELS: [Option<&str>; 5] = [
+        Some("書き込む"),
+        Some("書き込み"),
+        Some("上記全てを承諾して書き込む"),
+        Some("新規スレッド作成"),
+        None,
+    ];
+
+    #[test]
+    fn detects_threads_and_replies_whatever_the_submit_label() {
+        for label in SUBMIT_LABELS {
+            for (encoding, encode) in [
+                ("Shift_JIS", shift_jis as fn(&str) -> String),
+                ("UTF-8", utf8),
+            ] {
+                let case = format!("{label:?} in {encoding}");
+                let submit = label.map(|label| ("submit", label));
+
+                let reply = [
+                    ("bbs", "test"),
+                    ("key", "1700000000"),
+                    ("FROM", "名無し"),
+                    ("MESSAGE", "本文"),
+                ];
+                let reply = form_with(&[&reply[..], submit.as_slice()].concat(), encode);
+                let reply = extract_forms(reply, None).expect(&case);
+                assert!(!reply.is_thread, "{case}");
+                assert_eq!(reply.thread_key, Some(1700000000), "{case}");
+                assert_eq!(reply.subject, None, "{case}");
+                assert_eq!(reply.name, "名無し", "{case}");
+                assert_eq!(reply.body, "本文", "{case}");
+
+                let thread = [
+                    ("bbs", "test"),
+                    ("subject", "スレタイ"),
+                    ("MESSAGE", "本文"),
+                ];
+                let thread = form_with(&[&thread[..], submit.as_slice()].concat(), encode);
+                let thread = extract_forms(thread, None).expect(&case);
+                assert!(thread.is_thread, "{case}");
+                assert_eq!(thread.thread_key, None, "{case}");
+                assert_eq!(thread.subject.as_deref(), Some("スレタイ"), "{case}");
+                assert_eq!(thread.body, "本文", "{case}");
+            }
+        }
+    }
+
+    #[test]
+    fn decodes_utf8_forms() {
+        for (subject, name, body) in [
+            ("スレタイ", "書き込み", "こんにちは"),
+            ("雑談スレ★1", "名無しさん", "ｷﾀ━━━(ﾟ∀ﾟ)━━━!!"),
+            ("Rust", "774", "漢字とひらがなとカタカナ\n2行目"),
+        ] {
+            let thread = form_with(
+                &[
+                    ("bbs", "test"),
+                    ("subject", subject),
+                    ("FROM", name),
+                    ("mail", "sage"),
+                    ("MESSAGE", body),
+                ],
+                utf8,
+            );
+            let thread = extract_forms(thread, None).unwrap();
+            assert_eq!(thread.subject.as_deref(), Some(subject));
+            assert_eq!(thread.name, name.replace('★', "☆"));
+            assert_eq!(thread.mail, "sage");
+            assert_eq!(thread.body, body.replace('\n', "<br>"));
+        }
+    }
+
+    #[test]
+    fn empty_key_with_subject_creates_a_thread() {
+        let form = extract_forms(
+            form(&[
+                ("bbs", "test"),
+                ("key", ""),
+                ("subject", "スレタイ"),
+                ("MESSAGE", "本文"),
+            ]),
+            None,
+        )
+        .unwrap();
+        assert!(form.is_thread);
+    }
+
+    #[test]
+    fn masks_cap_markers_with_or_without_a_trip() {
+        for marker in ["★", "&#9733;", "&#x2605;", "&#X2605;", "&#09733;", "&#9733"] {
+            for from in [
+                format!("名無し{marker}"),
+                format!("{marker}名無し"),
+                format!("名無し{marker}#trip"),
+            ] {
+                let form = extract_forms(
+                    form(&[
+                        ("bbs", "test"),
+                        ("subject", "スレタイ"),
+                        ("FROM", &from),
+                        ("MESSAGE", "本文"),
+                    ]),
+                    None,
+                )
+                .unwrap();
+                assert_eq!(mask_cap_marker(&form.name), form.name, "{from}");
+                assert!(!form.name.contains('★'), "{from}: {}", form.name);
+            }
+        }
+    }
+
+    fn replace_ng_word(pattern: &str, replacement: &str) -> NgWordsCtx {
+        NgWordsCtx::new(vec![crate::dtos::NgWord {
+            id: 1,
+            board_id: 0,
+            pattern: pattern.to_string(),
+            is_regex: 0,
+            action: "replace".to_string(),
+            replacement: replacement.to_string(),
+        }])
+    }
+
+    #[test]
+    fn validates_the_form_left_by_ng_word_replacements() {
+        let board = crate::bbs_repository::fixtures::board();
+        let reply = |body: &str| {
+            extract_forms(
+                form(&[("bbs", "test"), ("key", "1"), ("MESSAGE", body)]),
+                None,
+            )
+            .unwrap()
+        };
+
+        let emptied = replace_ng_word("spam", "");
+        assert_eq!(
+            screen_form(&mut reply("spam"), &board, &emptied, false),
+            Err("本文がありません！")
+        );
+        // A cap bypassing the NG words keeps the body as posted
+        assert_eq!(
+            screen_form(&mut reply("spam"), &board, &emptied, true),
+            Ok(None)
+        );
+
+        let lengthened = replace_ng_word("x", &"*".repeat(100));
+        let body = "x".repeat(41);
+        assert_eq!(
+            validate_form(&reply(&body), &board),
+            Ok(()),
+            "short enough as posted"
+        );
+        assert_eq!(
+            screen_form(&mut reply(&body), &board, &lengthened, false),
+            Err("本文が長すぎます！")
+        );
+    }
+
+    #[test]
+    fn takes_cap_attempts_off_the_mail_secrets() {
+        let token = "0123456789abcdef01234567";
+        let secrets = |x: &[&str]| x.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        for (mail, attempt, rest) in [
+            (secrets(&["12", "pass"]), Some((12, "pass")), secrets(&[])),
+            (
+                secrets(&[token, "12", "pass"]),
+                Some((12, "pass")),
+                secrets(&[token]),
+            ),
+            (secrets(&["pass"]), None, secrets(&["pass"])),
+            (secrets(&[token]), None, secrets(&[token])),
+            (secrets(&["12", token]), None, secrets(&["12", token])),
+            (secrets(&["0", "pass"]), None, secrets(&["0", "pass"])),
+            (secrets(&["cap", "pass"]), None, secrets(&["cap", "pass"])),
+        ] {
+            let mut secrets = mail.clone();
+            assert_eq!(
+                take_cap_attempt(&mut secrets),
+                attempt.map(|(id, password)| (id, password.to_string())),
+                "{mail:?}"
+            );
+            assert_eq!(secrets, rest, "{mail:?}");
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_bodies() {
+        assert_eq!(
+            extract_forms(b"bbs=test&key=1&MESSAGE=%8".to_vec(), None).unwrap_err(),
+            FormError::Malformed(UrlDecodeError::BrokenPercentEncoding)
+        );
+        assert_eq!(
+            extract_forms(b"bbs=test&key=1&MESSAGE=%82".to_vec(), None).unwrap_err(),
+            FormError::Malformed(UrlDecodeError::InvalidShiftJis)
+        );
+        assert_eq!(
+            extract_forms(b"bbs=test&bbs=test&key=1&MESSAGE=a".to_vec(), None).unwrap_err(),
+            FormError::DuplicateField("bbs".to_string())
+        );
+        assert_eq!(
+            extract_forms(b"bbs=test&key=1".to_vec(), None).unwrap_err(),
+            FormError::MissingField("MESSAGE")
+        );
+        assert_eq!(
+            extract_forms(b"bbs=test&key=-1&MESSAGE=a".to_vec(), None).unwrap_err(),
+            FormError::InvalidField("key")
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_on_any_body(bytes in proptest::collection::vec(any::<u8>(), 0..256)) {
+            let _ = extract_forms(bytes, None);
+        }
+
+        #[test]
+        fn never_panics_on_any_field_values(
+            subject in any::<String>(),
+            name in any::<String>(),
+            mail in any::<String>(),
+            message in any::<String>(),
+        ) {
+            let _ = extract_forms(form(&[
+                ("subject", &subject),
+                ("FROM", &name),
+                ("mail", &mail),
+                ("MESSAGE", &message),
+                ("bbs", "test"),
+            ]), None);
+        }
+
+        #[test]
+        fn never_panics_on_character_references(
+            subject in "[&#;xX0-9a-f★◆]{0,32}",
+            name in "[&#;xX0-9a-f★◆]{0,32}",
+        ) {
+            let result = extract_forms(form(&[
+                ("subject", &subject),
+                ("FROM", &name),
+                ("MESSAGE", "本文"),
+                ("bbs", "test"),
+            ]), None);
+            prop_assert!(result.is_ok());
+        }
+    }
+}
@@ -1,45 +1,681 @@
-use worker::{Cache, Request, Response, Result, RouteContext};
+use chrono::{DateTime, NaiveDateTime};
+use sha1::{Digest, Sha1};
+use worker::{Cache, Headers, Request, Response, Result, RouteContext};
 
-use crate::{utils, Ctx};
+use crate::{
+    bbs_repository::{BbsRepository, RepositoryError},
+    cap::mask_cap_marker,
+    dtos::{Board, Res, Thread},
+    routes::route_error::{RouteError, RouteResult},
+    utils::to_hex,
+    BoardsCtx, Ctx,
+};
+
+/// A DAT encoded in Shift_JIS along with its validators, answering conditional and `Range`
+/// requests the way dedicated browsers expect for differential fetching.
+pub(crate) struct DatBody {
+    bytes: Vec<u8>,
+    /// unix timestamp (seconds)
+    last_modified: i64,
+    etag: String,
+}
+
+impl DatBody {
+    pub(crate) fn new(dat: &str, last_modified: i64) -> Self {
+        let bytes = encoding_rs::SHIFT_JIS.encode(dat).0.into_owned();
+        let etag = Self::etag_of(&bytes);
+        Self {
+            bytes,
+            last_modified,
+            etag,
+        }
+    }
+
+    fn etag_of(bytes: &[u8]) -> String {
+        let hash = Sha1::digest(bytes);
+        format!("\"{}-{}\"", bytes.len(), to_hex(&hash[..8]))
+    }
+
+    async fn from_cached(mut resp: Response) -> Option<Self> {
+        let last_modified = resp
+            .headers()
+            .get("Last-Modified")
+            .ok()
+            .flatten()
+            .and_then(|x| parse_http_date(&x))?;
+        let bytes = resp.bytes().await.ok()?;
+        let etag = Self::etag_of(&bytes);
+        Some(Self {
+            bytes,
+            last_modified,
+            etag,
+        })
+    }
+
+    fn headers(&self, ttl: usize) -> Vec<(&'static str, String)> {
+        vec![
+            ("Content-Type", "text/plain".to_string()),
+            ("Cache-Control", format!("s-maxage={ttl}")),
+            ("Accept-Ranges", "bytes".to_string()),
+            ("ETag", self.etag.clone()),
+            ("Last-Modified", format_http_date(self.last_modified)),
+        ]
+    }
+
+    fn full_reply(&self, ttl: usize) -> DatReply {
+        DatReply {
+            status: 200,
+            headers: self.headers(ttl),
+            body: Some(self.bytes.clone()),
+        }
+    }
+
+    fn full_response(&self, ttl: usize) -> Result<Response> {
+        self.full_reply(ttl).into_response()
+    }
+
+    fn is_not_modified(&self, conditions: &DatConditions) -> bool {
+        if let Some(if_none_match) = &conditions.if_none_match {
+            return if_none_match
+                .split(',')
+                .map(|x| x.trim().trim_start_matches("W/"))
+                .any(|x| x == "*" || x == self.etag);
+        }
+        if let Some(if_modified_since) = &conditions.if_modified_since {
+            return parse_http_date(if_modified_since).is_some_and(|x| self.last_modified <= x);
+        }
+        false
+    }
+
+    // A range only applies while the DAT is still the representation named by If-Range
+    fn is_range_valid(&self, conditions: &DatConditions) -> bool {
+        match &conditions.if_range {
+            Some(if_range) if if_range.starts_with('"') || if_range.starts_with("W/") => {
+                *if_range == self.etag
+            }
+            Some(if_range) => parse_http_date(if_range) == Some(self.last_modified),
+            None => true,
+        }
+    }
+
+    fn reply(&self, conditions: &DatConditions, ttl: usize) -> DatReply {
+        if self.is_not_modified(conditions) {
+            return DatReply {
+                status: 304,
+                headers: self.headers(ttl),
+                body: None,
+            };
+        }
+
+        let Some(range) = &conditions.range else {
+            return self.full_reply(ttl);
+        };
+        if !self.is_range_valid(conditions) {
+            return self.full_reply(ttl);
+        }
+
+        let len = self.bytes.len();
+        match parse_byte_range(range, len) {
+            Some(Ok((start, end))) => {
+                let mut headers = self.headers(ttl);
+                headers.push(("Content-Range", format!("bytes {start}-{end}/{len}")));
+                DatReply {
+                    status: 206,
+                    headers,
+                    body: Some(self.bytes[start..=end].to_vec()),
+                }
+            }
+            // The DAT got shorter than what the browser has, i.e. it was rewritten
+            Some(Err(())) => {
+                let mut headers = self.headers(ttl);
+                headers.push(("Content-Range", format!("bytes */{len}")));
+                DatReply {
+                    status: 416,
+                    headers,
+                    body: None,
+                }
+            }
+            None => self.full_reply(ttl),
+        }
+    }
+
+    pub(crate) fn respond(&self, req: &Request, ttl: usize) -> Result<Response> {
+        self.reply(&DatConditions::from_request(req), ttl)
+            .into_response()
+    }
+}
+
+/// The request headers which decide how a DAT is answered
+#[derive(Debug, Clone, Default)]
+struct DatConditions {
+    if_none_match: Option<String>,
+    if_modified_since: Option<String>,
+    if_range: Option<String>,
+    range: Option<String>,
+}
+
+impl DatConditions {
+    fn from_request(req: &Request) -> Self {
+        let header = |name: &str| req.headers().get(name).ok().flatten();
+        Self {
+            if_none_match: header("If-None-Match"),
+            if_modified_since: header("If-Modified-Since"),
+            if_range: header("If-Range"),
+            range: header("Range"),
+        }
+    }
+}
+
+/// What [`DatBody`] answers, before it becomes a `Response`
+#[derive(Debug)]
+struct DatReply {
+    status: u16,
+    headers: Vec<(&'static str, String)>,
+    /// `None` for the statuses without a body
+    body: Option<Vec<u8>>,
+}
+
+impl DatReply {
+    fn into_response(self) -> Result<Response> {
+        let mut headers = Headers::new();
+        for (name, value) in &self.headers {
+            let _ = headers.set(name, value);
+        }
+        let resp = match self.body {
+            Some(body) => Response::from_bytes(body)?,
+            None => Response::empty()?,
+        };
+        Ok(resp.with_status(self.status).with_headers(headers))
+    }
+}
+
+/// Parses a single `bytes=` range into an inclusive byte span.
+///
+/// Returns `None` for ranges that should be ignored (other units, multiple ranges, garbage)
+/// and `Some(Err(()))` for ranges that cannot be satisfied.
+fn parse_byte_range(value: &str, len: usize) -> Option<std::result::Result<(usize, usize), ()>> {
+    let spec = value.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    if start.is_empty() {
+        let suffix = end.parse::<usize>().ok()?;
+        if suffix == 0 || len == 0 {
+            return Some(Err(()));
+        }
+        return Some(Ok((len.saturating_sub(suffix), len - 1)));
+    }
+
+    let start = start.parse::<usize>().ok()?;
+    let end = if end.is_empty() {
+        len.saturating_sub(1)
+    } else {
+        end.parse::<usize>().ok()?.min(len.saturating_sub(1))
+    };
+    if start >= len || end < start {
+        return Some(Err(()));
+    }
+    Some(Ok((start, end)))
+}
+
+fn format_http_date(timestamp: i64) -> String {
+    DateTime::from_timestamp(timestamp, 0)
+        .unwrap_or_default()
+        .format("%a, %d %b %Y %H:%M:%S GMT")
+        .to_string()
+}
+
+fn parse_http_date(value: &str) -> Option<i64> {
+    DateTime::parse_from_rfc2822(value)
+        .ok()
+        .map(|x| x.timestamp())
+}
+
+/// The newest `created_at` or `edited_at` of the responses, falling back to the thread's
+/// update time
+pub(crate) fn dat_last_modified(thread: &Thread, responses: &[Res]) -> i64 {
+    responses
+        .iter()
+        .filter_map(|x| {
+            NaiveDateTime::parse_from_str(&x.created_at, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(|created_at| created_at.and_utc().timestamp().max(x.edited_at))
+        })
+        .max()
+        .unwrap_or(thread.update_unix_timestamp)
+        .max(thread.update_unix_timestamp)
+}
 
 pub async fn route_dat(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    dat_response(&req, &ctx)
+        .await
+        .or_else(RouteError::into_response)
+}
+
+async fn dat_response(req: &Request, ctx: &RouteContext<Ctx>) -> RouteResult<Response> {
     let cache = Cache::default();
-    if let Ok(Some(s)) = cache.get(&req, false).await {
-        return Ok(s);
+    // Cached by URL so that a cached full DAT can answer any Range of the same thread
+    let cache_key = req.url()?.to_string();
+    if let Ok(Some(cached)) = cache.get(&cache_key, false).await {
+        if let Some(body) = DatBody::from_cached(cached).await {
+            return Ok(body.respond(req, 1)?);
+        }
     }
 
-    let board_key = ctx.param("boardKey").unwrap();
-    let thread_key = ctx.param("threadKey").unwrap().replace(".dat", "");
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(RouteError::NotFound("board not found"))?;
+    let thread_key = parse_dat_file_name(ctx.param("threadKey"))?;
 
-    let (thread, responses) = ctx
-        .data
-        .bbs_repository
-        .get_thread_with_responses(board_key, thread_key.parse().unwrap())
-        .await
-        .unwrap();
+    let repo = ctx.data.bbs_repository.as_ref();
+    let (board, thread, responses) =
+        match find_dat(repo, &ctx.data.boards, board_key, thread_key).await? {
+            DatLookup::Found(board, thread, responses) => (board, *thread, responses),
+            DatLookup::Moved(to_board) => {
+                // Sends browsers to the board which the thread moved to
+                let mut url = req.url()?;
+                url.set_path(&format!("/{}/dat/{thread_key}.dat", to_board.board_key));
+                url.set_query(None);
+                return Ok(Response::redirect_with_status(url, 301)?);
+            }
+        };
 
+    let dat = gen_dat(&thread, &responses, Some(board));
+    let body = DatBody::new(&dat, dat_last_modified(&thread, &responses));
+
+    if let Ok(full) = body.full_response(1) {
+        let _ = cache.put(&cache_key, full).await;
+    }
+
+    Ok(body.respond(req, 1)?)
+}
+
+/// The thread key of a `{threadKey}.dat` path segment
+pub(crate) fn parse_dat_file_name(name: Option<&String>) -> RouteResult<i64> {
+    name.and_then(|x| x.strip_suffix(".dat"))
+        .and_then(|x| x.parse::<i64>().ok())
+        .ok_or(RouteError::BadRequest("invalid thread key"))
+}
+
+#[derive(Debug)]
+enum DatLookup<'a> {
+    Found(&'a Board, Box<Thread>, Vec<Res>),
+    /// The thread has moved to this board
+    Moved(&'a Board),
+}
+
+async fn find_dat<'a>(
+    repo: &dyn BbsRepository,
+    boards: &'a BoardsCtx,
+    board_key: &str,
+    thread_key: i64,
+) -> RouteResult<DatLookup<'a>> {
+    let board = boards
+        .get_board_by_key(board_key)
+        .ok_or(RouteError::NotFound("board not found"))?;
+
+    match repo.get_thread_with_responses(board_key, thread_key).await {
+        Ok((thread, responses)) => Ok(DatLookup::Found(board, Box::new(thread), responses)),
+        Err(RepositoryError::NotFound) => repo
+            .get_thread_redirect(board.id, thread_key)
+            .await
+            .map_err(RouteError::Database)?
+            .and_then(|x| boards.get_board_by_id(x))
+            .map(DatLookup::Moved)
+            .ok_or(RouteError::NotFound("thread not found")),
+        Err(e) => Err(RouteError::Database(e)),
+    }
+}
+
+pub(crate) fn gen_dat(thread: &Thread, responses: &[Res], board: Option<&Board>) -> String {
+    let default_name = board.map_or("スケスケの名無し", |board| &board.default_name);
     let mut dat = String::new();
     for (idx, response) in responses.iter().enumerate() {
+        let title = if idx == 0 { &thread.title } else { "" };
+        if response.deleted == 1 {
+            dat.push_str(&format!(
+                "あぼーん<>あぼーん<>あぼーん<> あぼーん<>{title}\n"
+            ));
+            continue;
+        }
+        // Only a post made with a cap carries ★, whatever the stored name contains
+        let name = if response.cap_id != 0 {
+            format!("{} ★", response.name)
+        } else if response.name.is_empty() {
+            default_name.to_string()
+        } else {
+            mask_cap_marker(&response.name)
+        };
+        // Boards without IDs store an empty one
+        let id = if response.author_id.is_empty() {
+            String::new()
+        } else {
+            format!(" ID:{}", response.author_id)
+        };
         dat.push_str(&format!(
-            "{}<><>{} ID:{}<> {}<>{}\n",
-            if response.name.is_empty() {
-                "スケスケの名無し"
-            } else {
-                &response.name
-            },
-            response.date_text,
-            response.author_id,
-            response.body,
-            if idx == 0 { &thread.title } else { "" }
+            "{}<><>{}{}<> {}<>{}\n",
+            name, response.date_text, id, response.body, title
         ));
     }
 
-    let mut data = utils::response_shift_jis_text_plain_with_cache(&dat, 1)?;
-    if let Ok(result) = data.cloned() {
-        if result.status_code() == 200 {
-            let _ = cache.put(&req, result).await;
+    if let Some(board) = board {
+        let max = board.max_response_count;
+        if thread.response_count >= max {
+            dat.push_str(&format!(
+                "{}<><>Over {max} Thread<> このスレッドは{max}を超えました。<br>新しいスレッドを立ててください。<>\n",
+                max + 1
+            ));
+        } else if thread.stopped == 1 {
+            dat.push_str("停止<><>停止<> 真・スレッドストッパー。。。（￣ー￣）ニヤリッ<>\n");
+        }
+    }
+
+    dat
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::bbs_repository::{
+        fixtures::{board, creating_response, creating_thread, fixed_clock, BOARD_KEY, NOW_MILLIS},
+        CreatingResponse, FailPoint, InMemoryBbsRepository,
+    };
+
+    const THREAD_KEY: i64 = NOW_MILLIS / 1000;
+    /// 2023-11-14 22:13:20 UTC
+    const LAST_MODIFIED: i64 = NOW_MILLIS / 1000;
+    const LAST_MODIFIED_TEXT: &str = "Tue, 14 Nov 2023 22:13:20 GMT";
+
+    fn other_board() -> Board {
+        Board {
+            id: 2,
+            board_key: "other".to_string(),
+            ..board()
+        }
+    }
+
+    fn setup() -> (InMemoryBbsRepository, BoardsCtx) {
+        let boards = vec![board(), other_board()];
+        let repo = InMemoryBbsRepository::with_clock(boards.clone(), fixed_clock);
+        block_on(repo.create_thread(creating_thread("スレタイ"))).unwrap();
+        (repo, BoardsCtx::new(boards))
+    }
+
+    #[test]
+    fn finds_the_thread() {
+        let (repo, boards) = setup();
+        let lookup = block_on(find_dat(&repo, &boards, BOARD_KEY, THREAD_KEY)).unwrap();
+        let DatLookup::Found(board, thread, responses) = lookup else {
+            panic!("thread not found");
+        };
+        assert_eq!(board.board_key, BOARD_KEY);
+        assert_eq!(thread.thread_key, THREAD_KEY);
+        assert_eq!(responses.len(), 1);
+    }
+
+    #[test]
+    fn invalid_thread_key_is_a_bad_request() {
+        for name in [None, Some("1700000000"), Some("abc.dat"), Some(".dat")] {
+            let name = name.map(str::to_string);
+            let e = parse_dat_file_name(name.as_ref()).unwrap_err();
+            assert_eq!(e.status(), 400, "{name:?}");
         }
+        assert_eq!(
+            parse_dat_file_name(Some(&"1700000000.dat".to_string())).unwrap(),
+            THREAD_KEY
+        );
     }
 
-    Ok(data)
+    #[test]
+    fn unknown_board_is_not_found() {
+        let (repo, boards) = setup();
+        let e = block_on(find_dat(&repo, &boards, "unknown", THREAD_KEY)).unwrap_err();
+        assert_eq!(e.status(), 404);
+    }
+
+    #[test]
+    fn missing_thread_is_not_found() {
+        let (repo, boards) = setup();
+        let e = block_on(find_dat(&repo, &boards, BOARD_KEY, THREAD_KEY + 1)).unwrap_err();
+        assert_eq!(e.status(), 404);
+    }
+
+    #[test]
+    fn moved_thread_redirects_to_its_board() {
+        let (repo, boards) = setup();
+        let thread = block_on(repo.get_thread(BOARD_KEY, THREAD_KEY)).unwrap();
+        block_on(repo.move_thread(&thread.id, other_board().id)).unwrap();
+
+        let lookup = block_on(find_dat(&repo, &boards, BOARD_KEY, THREAD_KEY)).unwrap();
+        let DatLookup::Moved(to_board) = lookup else {
+            panic!("thread not moved");
+        };
+        assert_eq!(to_board.board_key, "other");
+    }
+
+    #[test]
+    fn database_failure_is_unavailable() {
+        let (repo, boards) = setup();
+        repo.fail_at(Some(FailPoint::Read));
+        let e = block_on(find_dat(&repo, &boards, BOARD_KEY, THREAD_KEY)).unwrap_err();
+        assert_eq!(e.status(), 503);
+    }
+
+    #[test]
+    fn parses_byte_ranges() {
+        for (value, expected) in [
+            ("bytes=0-4", Some(Ok((0, 4)))),
+            ("bytes=3-100", Some(Ok((3, 9)))),
+            // Open-ended
+            ("bytes=5-", Some(Ok((5, 9)))),
+            ("bytes=9-", Some(Ok((9, 9)))),
+            // Suffix
+            ("bytes=-3", Some(Ok((7, 9)))),
+            ("bytes=-20", Some(Ok((0, 9)))),
+            // Past the end, backwards or empty
+            ("bytes=10-", Some(Err(()))),
+            ("bytes=10-20", Some(Err(()))),
+            ("bytes=5-2", Some(Err(()))),
+            ("bytes=-0", Some(Err(()))),
+            // Ignored
+            ("items=0-4", None),
+            ("bytes=0-1,3-4", None),
+            ("bytes=a-", None),
+            ("bytes=0", None),
+        ] {
+            assert_eq!(parse_byte_range(value, 10), expected, "{value}");
+        }
+        assert_eq!(parse_byte_range("bytes=0-", 0), Some(Err(())));
+        assert_eq!(parse_byte_range("bytes=-1", 0), Some(Err(())));
+    }
+
+    fn dat_body() -> DatBody {
+        DatBody::new("0123456789", LAST_MODIFIED)
+    }
+
+    fn header<'a>(reply: &'a DatReply, name: &str) -> Option<&'a str> {
+        reply
+            .headers
+            .iter()
+            .find(|(x, _)| *x == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    #[test]
+    fn answers_without_conditions_in_full() {
+        let body = dat_body();
+        let reply = body.reply(&DatConditions::default(), 1);
+        assert_eq!(reply.status, 200);
+        assert_eq!(reply.body.as_deref(), Some(&b"0123456789"[..]));
+        assert_eq!(header(&reply, "ETag"), Some(body.etag.as_str()));
+        assert_eq!(header(&reply, "Last-Modified"), Some(LAST_MODIFIED_TEXT));
+        assert_eq!(header(&reply, "Accept-Ranges"), Some("bytes"));
+        assert_eq!(header(&reply, "Cache-Control"), Some("s-maxage=1"));
+        assert_eq!(header(&reply, "Content-Range"), None);
+    }
+
+    #[test]
+    fn answers_ranges_with_their_slice() {
+        let body = dat_body();
+        for (range, slice, content_range) in [
+            ("bytes=2-4", &b"234"[..], "bytes 2-4/10"),
+            ("bytes=7-", &b"789"[..], "bytes 7-9/10"),
+            ("bytes=-2", &b"89"[..], "bytes 8-9/10"),
+            ("bytes=0-99", &b"0123456789"[..], "bytes 0-9/10"),
+        ] {
+            let conditions = DatConditions {
+                range: Some(range.to_string()),
+                ..Default::default()
+            };
+            let reply = body.reply(&conditions, 1);
+            assert_eq!(reply.status, 206, "{range}");
+            assert_eq!(reply.body.as_deref(), Some(slice), "{range}");
+            assert_eq!(header(&reply, "Content-Range"), Some(content_range));
+            assert_eq!(header(&reply, "ETag"), Some(body.etag.as_str()));
+        }
+    }
+
+    #[test]
+    fn rejects_ranges_starting_past_the_end() {
+        let conditions = DatConditions {
+            range: Some("bytes=10-".to_string()),
+            ..Default::default()
+        };
+        let reply = dat_body().reply(&conditions, 1);
+        assert_eq!(reply.status, 416);
+        assert_eq!(reply.body, None);
+        assert_eq!(header(&reply, "Content-Range"), Some("bytes */10"));
+    }
+
+    #[test]
+    fn ignores_unsupported_ranges() {
+        let conditions = DatConditions {
+            range: Some("bytes=0-1,3-4".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(dat_body().reply(&conditions, 1).status, 200);
+    }
+
+    #[test]
+    fn answers_matching_validators_as_not_modified() {
+        let body = dat_body();
+        for (if_none_match, status) in [
+            (body.etag.clone(), 304),
+            (format!("W/{}", body.etag), 304),
+            (format!("\"other\", {}", body.etag), 304),
+            ("*".to_string(), 304),
+            // Otherwise the range is answered
+            ("\"other\"".to_string(), 206),
+        ] {
+            let conditions = DatConditions {
+                if_none_match: Some(if_none_match.clone()),
+                range: Some("bytes=2-".to_string()),
+                ..Default::default()
+            };
+            let reply = body.reply(&conditions, 1);
+            assert_eq!(reply.status, status, "{if_none_match}");
+            if status == 304 {
+                assert_eq!(reply.body, None);
+                assert_eq!(header(&reply, "ETag"), Some(body.etag.as_str()));
+            }
+        }
+
+        for (if_modified_since, status) in [
+            (LAST_MODIFIED_TEXT, 304),
+            ("Wed, 15 Nov 2023 00:00:00 GMT", 304),
+            ("Tue, 14 Nov 2023 22:13:19 GMT", 200),
+            ("garbage", 200),
+        ] {
+            let conditions = DatConditions {
+                if_modified_since: Some(if_modified_since.to_string()),
+                ..Default::default()
+            };
+            assert_eq!(
+                body.reply(&conditions, 1).status,
+                status,
+                "{if_modified_since}"
+            );
+        }
+
+        // If-None-Match decides alone when both are sent
+        let conditions = DatConditions {
+            if_none_match: Some("\"other\"".to_string()),
+            if_modified_since: Some(LAST_MODIFIED_TEXT.to_string()),
+            ..Default::default()
+        };
+        assert_eq!(body.reply(&conditions, 1).status, 200);
+    }
+
+    #[test]
+    fn falls_back_to_the_full_dat_when_if_range_does_not_match() {
+        let body = dat_body();
+        for (if_range, status) in [
+            (body.etag.clone(), 206),
+            ("\"10-0000000000000000\"".to_string(), 200),
+            (format!("W/{}", body.etag), 200),
+            (LAST_MODIFIED_TEXT.to_string(), 206),
+            ("Tue, 14 Nov 2023 22:13:19 GMT".to_string(), 200),
+        ] {
+            let conditions = DatConditions {
+                if_range: Some(if_range.clone()),
+                range: Some("bytes=5-".to_string()),
+                ..Default::default()
+            };
+            let reply = body.reply(&conditions, 1);
+            assert_eq!(reply.status, status, "{if_range}");
+            let expected = if status == 206 {
+                &b"56789"[..]
+            } else {
+                &b"0123456789"[..]
+            };
+            assert_eq!(reply.body.as_deref(), Some(expected), "{if_range}");
+        }
+    }
+
+    #[test]
+    fn full_threads_end_with_the_over_line() {
+        let (repo, _) = setup();
+        let board = Board {
+            max_response_count: 3,
+            ..board()
+        };
+        for body in ["2", "3"] {
+            let response = CreatingResponse {
+                max_response_count: board.max_response_count,
+                ..creating_response(THREAD_KEY, body)
+            };
+            block_on(repo.create_response(response)).unwrap();
+        }
+        let (thread, responses) =
+            block_on(repo.get_thread_with_responses(BOARD_KEY, THREAD_KEY)).unwrap();
+
+        let dat = gen_dat(&thread, &responses, Some(&board));
+        let lines = dat.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(
+            lines[3],
+            "4<><>Over 3 Thread<> このスレッドは3を超えました。<br>新しいスレッドを立ててください。<>"
+        );
+        // Not the stopper line, although the thread is stopped as well
+        assert_eq!(thread.stopped, 1);
+        assert!(!dat.contains("停止"));
+
+        // Without the board the limit is unknown
+        assert_eq!(gen_dat(&thread, &responses, None).lines().count(), 3);
+    }
+
+    #[test]
+    fn threads_below_the_limit_have_no_over_line() {
+        let (repo, _) = setup();
+        let (thread, responses) =
+            block_on(repo.get_thread_with_responses(BOARD_KEY, THREAD_KEY)).unwrap();
+        let dat = gen_dat(&thread, &responses, Some(&board()));
+        assert_eq!(dat.lines().count(), 1);
+        assert!(!dat.contains("Over"));
+    }
 }
@@ -0,0 +1,43 @@
+//! Data and checks shared by tests of the routes looking up a board
+
+use std::{fmt::Debug, future::Future};
+
+use futures::executor::block_on;
+
+use crate::{
+    bbs_repository::{
+        fixtures::{board, fixed_clock, BOARD_KEY},
+        BbsRepository, CreatingThread, FailPoint, InMemoryBbsRepository,
+    },
+    routes::route_error::RouteResult,
+    BoardsCtx,
+};
+
+/// The test board holding `threads`, created in order
+pub fn setup(
+    threads: impl IntoIterator<Item = CreatingThread>,
+) -> (InMemoryBbsRepository, BoardsCtx) {
+    let repo = InMemoryBbsRepository::with_clock(vec![board()], fixed_clock);
+    for thread in threads {
+        block_on(repo.create_thread(thread)).unwrap();
+    }
+    (repo, BoardsCtx::new(vec![board()]))
+}
+
+/// Asserts that `lookup` answers 404 for an unknown board key and 503 once reading from
+/// `repo` fails
+pub fn assert_board_lookup_failures<T, F>(
+    repo: &InMemoryBbsRepository,
+    lookup: impl Fn(&'static str) -> F,
+) where
+    T: Debug,
+    F: Future<Output = RouteResult<T>>,
+{
+    let e = block_on(lookup("unknown")).unwrap_err();
+    assert_eq!(e.status(), 404, "unknown board");
+
+    repo.fail_at(Some(FailPoint::Read));
+    let e = block_on(lookup(BOARD_KEY)).unwrap_err();
+    assert_eq!(e.status(), 503, "database failure");
+    repo.fail_at(None);
+}
@@ -0,0 +1,212 @@
+use worker::{Request, Response, Result, RouteContext};
+
+use crate::{
+    bbs_repository::BbsRepository,
+    dtos::{Board, Res, Thread},
+    routes::dat_routing::{dat_last_modified, gen_dat, parse_dat_file_name, DatBody},
+    routes::route_error::{RouteError, RouteResult},
+    routes::subject_txt::gen_subject_txt,
+    utils, BoardsCtx, Ctx,
+};
+
+/// Directory of an archived thread, e.g. `1700/17000/1700000000` for key 1700000000 (2ch style)
+fn kako_dir(thread_key: i64) -> Option<(String, String)> {
+    let key = thread_key.to_string();
+    if key.len() < 5 {
+        return None;
+    }
+    Some((key[..4].to_string(), key[..5].to_string()))
+}
+
+pub async fn route_kako_subject_txt(_: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    kako_subject_txt_response(&ctx)
+        .await
+        .or_else(RouteError::into_response)
+}
+
+async fn kako_subject_txt_response(ctx: &RouteContext<Ctx>) -> RouteResult<Response> {
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(RouteError::NotFound("board not found"))?;
+    let threads = load_archived_threads(
+        ctx.data.bbs_repository.as_ref(),
+        &ctx.data.boards,
+        board_key,
+    )
+    .await?;
+
+    Ok(utils::response_shift_jis_text_plain_with_cache(
+        &gen_subject_txt(&threads),
+        60,
+    )?)
+}
+
+async fn load_archived_threads(
+    repo: &dyn BbsRepository,
+    boards: &BoardsCtx,
+    board_key: &str,
+) -> RouteResult<Vec<Thread>> {
+    if boards.get_board_by_key(board_key).is_none() {
+        return Err(RouteError::NotFound("board not found"));
+    }
+    repo.get_archived_threads(board_key)
+        .await
+        .map_err(RouteError::Database)
+}
+
+pub async fn route_kako_dat(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    kako_dat_response(&req, &ctx)
+        .await
+        .or_else(RouteError::into_response)
+}
+
+async fn kako_dat_response(req: &Request, ctx: &RouteContext<Ctx>) -> RouteResult<Response> {
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(RouteError::NotFound("board not found"))?;
+    let thread_key = parse_dat_file_name(ctx.param("threadKey"))?;
+    let given_dir = ctx
+        .param("kakoDir1")
+        .cloned()
+        .zip(ctx.param("kakoDir2").cloned());
+
+    let (board, thread, responses) = find_kako_dat(
+        ctx.data.bbs_repository.as_ref(),
+        &ctx.data.boards,
+        board_key,
+        thread_key,
+        given_dir,
+    )
+    .await?;
+
+    let body = DatBody::new(
+        &gen_dat(&thread, &responses, Some(board)),
+        dat_last_modified(&thread, &responses),
+    );
+    // Archived threads never change again
+    Ok(body.respond(req, 86400)?)
+}
+
+/// The archived thread, as long as it is requested from its own directory
+async fn find_kako_dat<'a>(
+    repo: &dyn BbsRepository,
+    boards: &'a BoardsCtx,
+    board_key: &str,
+    thread_key: i64,
+    given_dir: Option<(String, String)>,
+) -> RouteResult<(&'a Board, Thread, Vec<Res>)> {
+    let expected_dir = kako_dir(thread_key);
+    if expected_dir.is_none() || expected_dir != given_dir {
+        return Err(RouteError::NotFound("thread not found"));
+    }
+    let Some(board) = boards.get_board_by_key(board_key) else {
+        return Err(RouteError::NotFound("board not found"));
+    };
+
+    let (thread, responses) = repo
+        .get_thread_with_responses(board_key, thread_key)
+        .await
+        .map_err(|e| RouteError::from_repository(e, "thread not found"))?;
+    if thread.archived != 1 {
+        return Err(RouteError::NotFound("thread not found"));
+    }
+    Ok((board, thread, responses))
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        bbs_repository::{
+            fixtures::{creating_thread, BOARD_KEY, NOW_MILLIS},
+            CreatingThread, InMemoryBbsRepository,
+        },
+        routes::fixtures::{assert_board_lookup_failures, setup as setup_board},
+    };
+
+    const THREAD_KEY: i64 = NOW_MILLIS / 1000;
+
+    fn kako_dir_of_thread() -> Option<(String, String)> {
+        Some(("1700".to_string(), "17000".to_string()))
+    }
+
+    /// A board whose only thread is archived as soon as it is created
+    fn setup(archived: bool) -> (InMemoryBbsRepository, BoardsCtx) {
+        setup_board([CreatingThread {
+            max_thread_count: if archived { 0 } else { 10 },
+            ..creating_thread("スレタイ")
+        }])
+    }
+
+    #[test]
+    fn lists_archived_threads() {
+        let (repo, boards) = setup(true);
+        let threads = block_on(load_archived_threads(&repo, &boards, BOARD_KEY)).unwrap();
+        assert_eq!(threads.len(), 1);
+
+        let (repo, boards) = setup(false);
+        let threads = block_on(load_archived_threads(&repo, &boards, BOARD_KEY)).unwrap();
+        assert!(threads.is_empty());
+    }
+
+    #[test]
+    fn finds_the_archived_thread() {
+        let (repo, boards) = setup(true);
+        let (board, thread, _) = block_on(find_kako_dat(
+            &repo,
+            &boards,
+            BOARD_KEY,
+            THREAD_KEY,
+            kako_dir_of_thread(),
+        ))
+        .unwrap();
+        assert_eq!(board.board_key, BOARD_KEY);
+        assert_eq!(thread.thread_key, THREAD_KEY);
+    }
+
+    #[test]
+    fn wrong_directory_is_not_found() {
+        let (repo, boards) = setup(true);
+        for given_dir in [
+            None,
+            Some(("1699".to_string(), "16999".to_string())),
+            Some(("1700".to_string(), "17001".to_string())),
+        ] {
+            let e = block_on(find_kako_dat(
+                &repo, &boards, BOARD_KEY, THREAD_KEY, given_dir,
+            ))
+            .unwrap_err();
+            assert_eq!(e.status(), 404);
+        }
+        // Too short to have a directory at all
+        let e = block_on(find_kako_dat(&repo, &boards, BOARD_KEY, 1234, None)).unwrap_err();
+        assert_eq!(e.status(), 404);
+    }
+
+    #[test]
+    fn live_thread_is_not_found() {
+        let (repo, boards) = setup(false);
+        let e = block_on(find_kako_dat(
+            &repo,
+            &boards,
+            BOARD_KEY,
+            THREAD_KEY,
+            kako_dir_of_thread(),
+        ))
+        .unwrap_err();
+        assert_eq!(e.status(), 404);
+    }
+
+    #[test]
+    fn fails_like_other_board_lookups() {
+        let (repo, boards) = setup(true);
+        assert_board_lookup_failures(&repo, |board_key| {
+            load_archived_threads(&repo, &boards, board_key)
+        });
+        assert_board_lookup_failures(&repo, |board_key| {
+            find_kako_dat(&repo, &boards, board_key, THREAD_KEY, kako_dir_of_thread())
+        });
+    }
+}
@@ -0,0 +1,85 @@
+use serde::Deserialize;
+use worker::{console_error, Date, Request, Response, Result, RouteContext};
+
+use crate::{
+    bbs_repository::CreatingReport,
+    get_user_token_cookie,
+    routes::route_error::{RouteError, RouteResult},
+    Ctx,
+};
+
+/// Longest reason accepted, in characters
+const MAX_REASON_LENGTH: usize = 200;
+
+#[derive(Debug, Deserialize)]
+struct ReportRequest {
+    board_key: String,
+    thread_key: i64,
+    /// 1-based number of the response in the thread
+    response_number: usize,
+    reason: String,
+}
+
+/// Reports a response to the moderators. Answers 204 when the report is queued, including
+/// when the user has already reported the response.
+pub async fn route_report(mut req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    report(&mut req, &ctx)
+        .await
+        .or_else(RouteError::into_response)
+}
+
+async fn report(req: &mut Request, ctx: &RouteContext<Ctx>) -> RouteResult<Response> {
+    let user_hash =
+        get_user_token_cookie(req).ok_or(RouteError::Unauthorized("user_token is required"))?;
+    let repo = &ctx.data.bbs_repository;
+    let user = repo
+        .get_user(&user_hash)
+        .await
+        .map_err(RouteError::Database)?
+        .ok_or(RouteError::Unauthorized("unknown user_token"))?;
+    if user.is_banned_at((Date::now().as_millis() / 1000) as i64) {
+        return Err(RouteError::Unauthorized("user is banned"));
+    }
+
+    let report = req
+        .json::<ReportRequest>()
+        .await
+        .map_err(|_| RouteError::BadRequest("invalid request body"))?;
+    let reason = report.reason.trim();
+    if reason.is_empty() {
+        return Err(RouteError::BadRequest("reason must not be empty"));
+    }
+    if reason.chars().count() > MAX_REASON_LENGTH {
+        return Err(RouteError::BadRequest("reason is too long"));
+    }
+
+    let rate_limiter = &ctx.data.rate_limiter;
+    if let Err(violation) = rate_limiter.check_report(&user_hash).await? {
+        return Err(RouteError::TooManyRequests(violation.to_string()));
+    }
+
+    let (_, responses) = repo
+        .get_thread_with_responses(&report.board_key, report.thread_key)
+        .await
+        .map_err(|e| RouteError::from_repository(e, "thread not found"))?;
+    let res = report
+        .response_number
+        .checked_sub(1)
+        .and_then(|idx| responses.into_iter().nth(idx))
+        .filter(|x| x.deleted == 0)
+        .ok_or(RouteError::NotFound("response not found"))?;
+
+    repo.create_report(CreatingReport {
+        response_id: res.id,
+        response_number: report.response_number as i32,
+        user_hash: user_hash.clone(),
+        reason: reason.to_string(),
+    })
+    .await
+    .map_err(RouteError::Database)?;
+    if let Err(e) = rate_limiter.record_report(&user_hash).await {
+        console_error!("failed to record report rate limit: {e:?}");
+    }
+
+    Ok(Response::empty()?.with_status(204))
+}
@@ -0,0 +1,91 @@
+use worker::{console_error, Response};
+
+use crate::bbs_repository::RepositoryError;
+
+/// Failures of the routes answering in plain text: the read-only ones (subject.txt,
+/// SETTING.TXT, DAT, kako), authentication and reports
+#[derive(Debug)]
+pub(crate) enum RouteError {
+    Unauthorized(&'static str),
+    NotFound(&'static str),
+    BadRequest(&'static str),
+    /// The message of the violated rate limit
+    TooManyRequests(String),
+    Database(RepositoryError),
+    Worker(worker::Error),
+}
+
+impl From<worker::Error> for RouteError {
+    fn from(e: worker::Error) -> Self {
+        RouteError::Worker(e)
+    }
+}
+
+impl RouteError {
+    /// Maps a repository error, treating missing rows as `NotFound(what)`
+    pub(crate) fn from_repository(e: RepositoryError, what: &'static str) -> Self {
+        match e {
+            RepositoryError::NotFound => RouteError::NotFound(what),
+            e => RouteError::Database(e),
+        }
+    }
+
+    pub(crate) fn status(&self) -> u16 {
+        match self {
+            RouteError::Unauthorized(_) => 401,
+            RouteError::NotFound(_) => 404,
+            RouteError::BadRequest(_) => 400,
+            RouteError::TooManyRequests(_) => 429,
+            RouteError::Database(RepositoryError::Unavailable(_)) => 503,
+            RouteError::Database(_) | RouteError::Worker(_) => 500,
+        }
+    }
+
+    pub(crate) fn into_response(self) -> worker::Result<Response> {
+        let status = self.status();
+        let message = match self {
+            RouteError::Unauthorized(what) => format!("Unauthorized - {what}"),
+            RouteError::NotFound(what) => format!("Not Found - {what}"),
+            RouteError::BadRequest(what) => format!("Bad request - {what}"),
+            RouteError::TooManyRequests(message) => message,
+            RouteError::Database(RepositoryError::Unavailable(e)) => {
+                console_error!("database unavailable: {e:?}");
+                "service unavailable - database".to_string()
+            }
+            RouteError::Database(e) => {
+                console_error!("database error: {e:?}");
+                "internal server error - database".to_string()
+            }
+            RouteError::Worker(e) => {
+                console_error!("worker error: {e:?}");
+                "internal server error".to_string()
+            }
+        };
+        Response::error(message, status)
+    }
+}
+
+pub(crate) type RouteResult<T> = std::result::Result<T, RouteError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn maps_each_failure_to_its_status() {
+        let cases = [
+            (RouteError::Unauthorized("unknown user_token"), 401),
+            (RouteError::NotFound("thread not found"), 404),
+            (RouteError::BadRequest("reason is too long"), 400),
+            (RouteError::TooManyRequests("wait".to_string()), 429),
+            (
+                RouteError::Database(RepositoryError::Unavailable(anyhow::anyhow!("down"))),
+                503,
+            ),
+            (RouteError::Database(RepositoryError::ThreadStopped), 500),
+        ];
+        for (e, status) in cases {
+            assert_eq!(e.status(), status, "{e:?}");
+        }
+    }
+}
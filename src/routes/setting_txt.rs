@@ -1,23 +1,84 @@
 use worker::{Request, Response, Result, RouteContext};
 
-use crate::{utils, Ctx};
+use crate::{
+    bbs_repository::BbsRepository,
+    dtos::Board,
+    routes::route_error::{RouteError, RouteResult},
+    utils, Ctx,
+};
 
 pub async fn route_setting_txt(_: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
-    let board_key = ctx.param("boardKey").unwrap();
+    setting_txt_response(&ctx)
+        .await
+        .or_else(RouteError::into_response)
+}
+
+async fn setting_txt_response(ctx: &RouteContext<Ctx>) -> RouteResult<Response> {
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(RouteError::NotFound("board not found"))?;
+
+    let board = load_board(ctx.data.bbs_repository.as_ref(), board_key).await?;
+    let setting_txt = gen_setting_txt(&board);
+
+    Ok(utils::response_shift_jis_text_plain(&setting_txt)?)
+}
 
-    let board = ctx
-        .data
-        .bbs_repository
-        .get_board(board_key)
+async fn load_board(repo: &dyn BbsRepository, board_key: &str) -> RouteResult<Board> {
+    repo.get_board(board_key)
         .await
-        .unwrap()
-        .unwrap();
-    let (title, default_name) = (board.name, "スケスケの名無し");
-    let setting_txt = format!(
+        .map_err(RouteError::Database)?
+        .ok_or(RouteError::NotFound("board not found"))
+}
+
+fn gen_setting_txt(board: &Board) -> String {
+    let title = &board.name;
+    format!(
         "BBS_TITLE={title}
 BBS_TITLE_ORIG={title}
-BBS_NONAME_NAME={default_name}"
-    );
+BBS_NONAME_NAME={}
+BBS_SUBJECT_COUNT={}
+BBS_NAME_COUNT={}
+BBS_MAIL_COUNT={}
+BBS_MESSAGE_COUNT={}
+BBS_LINE_NUMBER={}
+BBS_UNICODE={}
+",
+        board.default_name,
+        board.subject_max_length,
+        board.name_max_length,
+        board.mail_max_length,
+        board.message_max_length,
+        // SETTING.TXT conventionally holds half of the maximum number of lines
+        board.message_max_lines / 2,
+        if board.allow_unicode == 1 {
+            "pass"
+        } else {
+            "change"
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        bbs_repository::fixtures::BOARD_KEY,
+        routes::fixtures::{assert_board_lookup_failures, setup},
+    };
+
+    #[test]
+    fn loads_the_board() {
+        let (repo, _) = setup([]);
+        let board = block_on(load_board(&repo, BOARD_KEY)).unwrap();
+        assert_eq!(board.board_key, BOARD_KEY);
+    }
 
-    utils::response_shift_jis_text_plain(&setting_txt)
+    #[test]
+    fn fails_like_other_board_lookups() {
+        let (repo, _) = setup([]);
+        assert_board_lookup_failures(&repo, |board_key| load_board(&repo, board_key));
+    }
 }
@@ -1,8 +1,19 @@
 use worker::{Cache, Request, Response, Result, RouteContext};
 
-use crate::{dtos::Thread, utils, Ctx};
+use crate::{
+    bbs_repository::BbsRepository,
+    dtos::Thread,
+    routes::route_error::{RouteError, RouteResult},
+    utils, BoardsCtx, Ctx,
+};
 
 pub async fn route_subject_txt(req: Request, ctx: RouteContext<Ctx>) -> Result<Response> {
+    subject_txt_response(&req, &ctx)
+        .await
+        .or_else(RouteError::into_response)
+}
+
+async fn subject_txt_response(req: &Request, ctx: &RouteContext<Ctx>) -> RouteResult<Response> {
     let cache = Cache::default();
 
     let is_mate = req
@@ -32,18 +43,19 @@ pub async fn route_subject_txt(req: Request, ctx: RouteContext<Ctx>) -> Result<R
                 }
             }
         }
-    } else if let Ok(Some(s)) = cache.get(&req, false).await {
+    } else if let Ok(Some(s)) = cache.get(req, false).await {
         return Ok(s);
     }
 
-    let board_key = ctx.param("boardKey").unwrap();
-
-    let threads = ctx
-        .data
-        .bbs_repository
-        .get_threads(board_key)
-        .await
-        .unwrap();
+    let board_key = ctx
+        .param("boardKey")
+        .ok_or(RouteError::NotFound("board not found"))?;
+    let threads = load_threads(
+        ctx.data.bbs_repository.as_ref(),
+        &ctx.data.boards,
+        board_key,
+    )
+    .await?;
 
     let subject_txt = gen_subject_txt(&threads);
     let mate_subject_txt = gen_mate_subject_txt(&threads);
@@ -65,7 +77,7 @@ pub async fn route_subject_txt(req: Request, ctx: RouteContext<Ctx>) -> Result<R
                     let _ = cache.put(&req_mate, data_mate).await;
                 }
             }
-            let _ = cache.put(&req, data).await;
+            let _ = cache.put(req, data).await;
         }
         Ok(ret_data)
     } else {
@@ -73,7 +85,21 @@ pub async fn route_subject_txt(req: Request, ctx: RouteContext<Ctx>) -> Result<R
     }
 }
 
-fn gen_subject_txt(threads: &[Thread]) -> String {
+/// Live threads of the board, most recently updated first
+async fn load_threads(
+    repo: &dyn BbsRepository,
+    boards: &BoardsCtx,
+    board_key: &str,
+) -> RouteResult<Vec<Thread>> {
+    if boards.get_board_by_key(board_key).is_none() {
+        return Err(RouteError::NotFound("board not found"));
+    }
+    repo.get_threads(board_key)
+        .await
+        .map_err(RouteError::Database)
+}
+
+pub(crate) fn gen_subject_txt(threads: &[Thread]) -> String {
     let mut subject_txt = String::new();
     for thread in threads {
         subject_txt.push_str(&format!(
@@ -87,6 +113,13 @@ fn gen_subject_txt(threads: &[Thread]) -> String {
 fn gen_mate_subject_txt(threads: &[Thread]) -> String {
     let mut subject_txt = String::new();
     for thread in threads {
+        if thread.author_id.is_empty() {
+            subject_txt.push_str(&format!(
+                "{}.dat<>{} ({})\n",
+                thread.thread_key, thread.title, thread.response_count
+            ));
+            continue;
+        }
         subject_txt.push_str(&format!(
             "{}.dat<>{} [{}★] ({})\n",
             thread.thread_key, thread.title, thread.author_id, thread.response_count
@@ -94,3 +127,27 @@ fn gen_mate_subject_txt(threads: &[Thread]) -> String {
     }
     subject_txt
 }
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+
+    use super::*;
+    use crate::{
+        bbs_repository::fixtures::{creating_thread, BOARD_KEY},
+        routes::fixtures::{assert_board_lookup_failures, setup},
+    };
+
+    #[test]
+    fn lists_live_threads() {
+        let (repo, boards) = setup([creating_thread("スレタイ")]);
+        let threads = block_on(load_threads(&repo, &boards, BOARD_KEY)).unwrap();
+        assert_eq!(gen_subject_txt(&threads), "1700000000.dat<>スレタイ (1)\n");
+    }
+
+    #[test]
+    fn fails_like_other_board_lookups() {
+        let (repo, boards) = setup([creating_thread("スレタイ")]);
+        assert_board_lookup_failures(&repo, |board_key| load_threads(&repo, &boards, board_key));
+    }
+}
@@ -1,12 +1,103 @@
-use std::collections::HashMap;
+use std::fmt::Write;
 
-use chrono::NaiveDateTime;
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
+use sha3::Digest;
 use worker::{Date, Response};
 
+/// Why [`shift_jis_url_encodeded_body_to_vec`] rejected a body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UrlDecodeError {
+    /// A `%` not followed by two hex digits
+    BrokenPercentEncoding,
+    /// Values whose bytes are neither Shift_JIS nor UTF-8
+    InvalidShiftJis,
+    /// Values declared UTF-8 whose bytes are not
+    InvalidUtf8,
+}
+
+/// The charset of the values of a form body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormCharset {
+    ShiftJis,
+    Utf8,
+}
+
+impl FormCharset {
+    /// The `charset` parameter of a `Content-Type` header, if it names one of the charsets
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let charset = content_type.split(';').skip(1).find_map(|x| {
+            let (key, value) = x.split_once('=')?;
+            key.trim()
+                .eq_ignore_ascii_case("charset")
+                .then(|| value.trim().trim_matches('"').to_ascii_lowercase())
+        })?;
+        match charset.as_str() {
+            "utf-8" | "utf8" => Some(FormCharset::Utf8),
+            "shift_jis" | "shift-jis" | "sjis" | "x-sjis" | "windows-31j" | "cp932" => {
+                Some(FormCharset::ShiftJis)
+            }
+            _ => None,
+        }
+    }
+
+    /// The charset of values sent without one, decided once for the whole body.
+    ///
+    /// Much UTF-8 Japanese is also valid Shift_JIS, and the other way round. Values are taken
+    /// as UTF-8 when they are valid UTF-8 holding kana, kanji or full-width characters and no
+    /// two-byte characters (U+0080 to U+07FF): a half-width katakana in Shift_JIS always
+    /// starts such a character when it is read as UTF-8, e.g. "ﾃｽ" as "ý". Other values are
+    /// Shift_JIS unless they are not, then UTF-8. A client sending UTF-8 without Japanese
+    /// text, or with Latin, Greek or Cyrillic letters, has to declare the charset.
+    fn detect(values: &[Vec<u8>]) -> Self {
+        let is_japanese = |x: char| matches!(x, '\u{3000}'..='\u{30ff}' | '\u{4e00}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}');
+        let mut has_japanese = false;
+        for value in values {
+            let Ok(value) = std::str::from_utf8(value) else {
+                return FormCharset::ShiftJis;
+            };
+            if value.chars().any(|x| x.len_utf8() == 2) {
+                has_japanese = false;
+                break;
+            }
+            has_japanese |= value.chars().any(is_japanese);
+        }
+        if has_japanese {
+            return FormCharset::Utf8;
+        }
+        let is_shift_jis = values.iter().all(|x| {
+            encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(x)
+                .is_some()
+        });
+        if is_shift_jis {
+            FormCharset::ShiftJis
+        } else {
+            FormCharset::Utf8
+        }
+    }
+
+    fn decode(self, value: Vec<u8>) -> std::result::Result<String, UrlDecodeError> {
+        match self {
+            FormCharset::ShiftJis => encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(&value)
+                .map(|x| x.into_owned())
+                .ok_or(UrlDecodeError::InvalidShiftJis),
+            FormCharset::Utf8 => String::from_utf8(value).map_err(|_| UrlDecodeError::InvalidUtf8),
+        }
+    }
+}
+
+/// Decodes an `application/x-www-form-urlencoded` body whose values are percent-encoded
+/// Shift_JIS, or UTF-8 as some scripts send. Pairs are returned in order, including
+/// duplicates; a key without `=` gets an empty value.
+///
+/// Every value is decoded in `charset`, or the one [`FormCharset::detect`] picks when it is
+/// `None`. Fails on broken percent-encoding and on bytes which are not of the charset.
 pub fn shift_jis_url_encodeded_body_to_vec(
     data: &str,
-) -> std::result::Result<HashMap<&str, String>, ()> {
-    fn ascii_hex_digit_to_byte(value: u8) -> std::result::Result<u8, ()> {
+    charset: Option<FormCharset>,
+) -> std::result::Result<Vec<(&str, String)>, UrlDecodeError> {
+    fn ascii_hex_digit_to_byte(value: u8) -> std::result::Result<u8, UrlDecodeError> {
         if value.is_ascii_hexdigit() {
             if value.is_ascii_digit() {
                 // U+0030 '0' - U+0039 '9',
@@ -18,20 +109,18 @@ pub fn shift_jis_url_encodeded_body_to_vec(
                 // U+0061 'a' - U+0066 'f',
                 Ok(value - 0x61 + 0xa)
             } else {
-                Err(())
+                Err(UrlDecodeError::BrokenPercentEncoding)
             }
         } else {
-            Err(())
+            Err(UrlDecodeError::BrokenPercentEncoding)
         }
     }
 
-    data.split('&')
+    let (keys, values): (Vec<_>, Vec<_>) = data
+        .split('&')
+        .filter(|x| !x.is_empty())
         .map(|x| {
-            let split = x.split('=').collect::<Vec<_>>();
-            if split.len() != 2 {
-                return std::result::Result::Err(());
-            }
-            let (key, value) = (split[0], split[1]);
+            let (key, value) = x.split_once('=').unwrap_or((x, ""));
             let bytes = value.as_bytes();
             let len = bytes.len();
             let mut i = 0;
@@ -40,12 +129,13 @@ pub fn shift_jis_url_encodeded_body_to_vec(
                 let item = bytes[i];
                 if item == 0x25 {
                     // Look up the next two bytes from 0x25
-                    if let Some([next1, next2]) = bytes.get(i + 1..i + 3) {
-                        let first_byte = ascii_hex_digit_to_byte(*next1)?;
-                        let second_byte = ascii_hex_digit_to_byte(*next2)?;
-                        let code = first_byte * 0x10_u8 + second_byte;
-                        result.push(code);
-                    }
+                    let Some([next1, next2]) = bytes.get(i + 1..i + 3) else {
+                        return Err(UrlDecodeError::BrokenPercentEncoding);
+                    };
+                    let first_byte = ascii_hex_digit_to_byte(*next1)?;
+                    let second_byte = ascii_hex_digit_to_byte(*next2)?;
+                    let code = first_byte * 0x10_u8 + second_byte;
+                    result.push(code);
                     i += 2;
                 } else if item == 0x2b {
                     result.push(0x20);
@@ -54,10 +144,17 @@ pub fn shift_jis_url_encodeded_body_to_vec(
                 }
                 i += 1;
             }
-            let result = encoding_rs::SHIFT_JIS.decode(&result).0.to_string();
             Ok((key, result))
         })
-        .collect::<std::result::Result<HashMap<_, _>, ()>>()
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .into_iter()
+        .unzip();
+
+    let charset = charset.unwrap_or_else(|| FormCharset::detect(&values));
+    keys.into_iter()
+        .zip(values)
+        .map(|(key, value)| Ok((key, charset.decode(value)?)))
+        .collect()
 }
 
 pub fn response_shift_jis_text_plain(body: &str) -> worker::Result<Response> {
@@ -107,6 +204,39 @@ pub fn response_shift_jis_text_html(body: String) -> worker::Result<Response> {
     Ok(resp)
 }
 
+/// Lowercase hex of `bytes`, e.g. of a digest
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut acc, x| {
+        let _ = write!(acc, "{x:02x}");
+        acc
+    })
+}
+
+/// Names a user in the audit log and the admin API. The user hash doubles as the login token,
+/// so only this digest of it leaves the database.
+pub fn derive_user_hash(user_hash: &str) -> String {
+    let hash = sha3::Sha3_256::digest(format!("user:{user_hash}").as_bytes());
+    to_hex(&hash[..8])
+}
+
+/// Source of the current time as a unix timestamp in milliseconds, injected into the
+/// in-memory stores so that they also run natively (e.g. under `cargo test`)
+pub type Clock = fn() -> i64;
+
+/// `worker::Date` on wasm, where it is the only clock, and the system clock elsewhere
+pub fn system_clock() -> i64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Date::now().as_millis() as i64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |x| x.as_millis() as i64)
+    }
+}
+
 pub fn get_current_date_time() -> NaiveDateTime {
     let date = NaiveDateTime::from_timestamp_millis(Date::now().as_millis() as i64).unwrap();
     date.checked_add_signed(chrono::Duration::hours(9)).unwrap()
@@ -127,6 +257,20 @@ pub fn get_current_date_time_string(is_ja: bool) -> String {
     }
 }
 
+/// The date in JST at `utc`, on which the days of IDs and salts turn
+pub fn jst_date(utc: DateTime<Utc>) -> NaiveDate {
+    let jst = FixedOffset::east_opt(9 * 60 * 60).unwrap();
+    utc.with_timezone(&jst).date_naive()
+}
+
+/// Formats a unix timestamp (seconds) as JST, e.g. "2024/01/02 03:04"
+pub fn format_unix_timestamp_jst(timestamp: i64) -> String {
+    chrono::DateTime::from_timestamp(timestamp + 9 * 60 * 60, 0)
+        .unwrap_or_default()
+        .format("%Y/%m/%d %H:%M")
+        .to_string()
+}
+
 pub fn convert_weekday_to_ja(weekday: &str) -> &str {
     match weekday {
         "Mon" => "月",
@@ -139,3 +283,243 @@ pub fn convert_weekday_to_ja(weekday: &str) -> &str {
         x => x,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use proptest::prelude::*;
+
+    use super::*;
+
+    /// Percent-encodes every byte, as browsers do for Shift_JIS forms
+    fn percent_encode(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("%{x:02X}")).collect()
+    }
+
+    #[test]
+    fn decodes_shift_jis_values() {
+        let value = percent_encode(&encoding_rs::SHIFT_JIS.encode("書き込む").0);
+        let body = format!("submit={value}&FROM=&mail=sage&MESSAGE=a+b%21");
+        assert_eq!(
+            shift_jis_url_encodeded_body_to_vec(&body, None),
+            Ok(vec![
+                ("submit", "書き込む".to_string()),
+                ("FROM", String::new()),
+                ("mail", "sage".to_string()),
+                ("MESSAGE", "a b!".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn decodes_utf8_values() {
+        // Each of them is also valid Shift_JIS, e.g. "スレタイ" as "繧ｹ繝ｬ繧ｿ繧､"
+        for text in [
+            "スレタイ",
+            "書き込み",
+            "こんにちは",
+            "あ",
+            "名無し＠テスト",
+            "ｷﾀｰ!",
+        ] {
+            let body = format!(
+                "subject={}&FROM=&MESSAGE={}",
+                percent_encode(text.as_bytes()),
+                percent_encode(format!("{text}\n{text}").as_bytes())
+            );
+            assert_eq!(
+                shift_jis_url_encodeded_body_to_vec(&body, None),
+                Ok(vec![
+                    ("subject", text.to_string()),
+                    ("FROM", String::new()),
+                    ("MESSAGE", format!("{text}\n{text}")),
+                ]),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn decides_the_charset_once_per_body() {
+        // "……" alone would be taken as the Shift_JIS "窶ｦ窶ｦ"
+        let body = format!(
+            "FROM={}&MESSAGE={}",
+            percent_encode("……".as_bytes()),
+            percent_encode("本文".as_bytes())
+        );
+        assert_eq!(
+            shift_jis_url_encodeded_body_to_vec(&body, None),
+            Ok(vec![
+                ("FROM", "……".to_string()),
+                ("MESSAGE", "本文".to_string())
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_half_width_katakana_shift_jis() {
+        // Also valid UTF-8: C3 BD is "ý", and C2 82 E1 88 A0 is "\u{82}ሠ"
+        for text in ["ﾃｽ", "ﾂゃ唖"] {
+            let value = percent_encode(&encoding_rs::SHIFT_JIS.encode(text).0);
+            assert_eq!(
+                shift_jis_url_encodeded_body_to_vec(&format!("MESSAGE={value}"), None),
+                Ok(vec![("MESSAGE", text.to_string())]),
+                "{text}"
+            );
+        }
+    }
+
+    #[test]
+    fn follows_the_declared_charset() {
+        let utf8 = format!("MESSAGE={}", percent_encode("é".as_bytes()));
+        assert_eq!(
+            shift_jis_url_encodeded_body_to_vec(&utf8, Some(FormCharset::Utf8)),
+            Ok(vec![("MESSAGE", "é".to_string())])
+        );
+        assert_eq!(
+            shift_jis_url_encodeded_body_to_vec(&utf8, Some(FormCharset::ShiftJis)),
+            Ok(vec![("MESSAGE", "ﾃｩ".to_string())])
+        );
+
+        let shift_jis = format!(
+            "MESSAGE={}",
+            percent_encode(&encoding_rs::SHIFT_JIS.encode("本文").0)
+        );
+        assert_eq!(
+            shift_jis_url_encodeded_body_to_vec(&shift_jis, Some(FormCharset::Utf8)),
+            Err(UrlDecodeError::InvalidUtf8)
+        );
+    }
+
+    #[test]
+    fn reads_the_charset_of_content_types() {
+        for (content_type, charset) in [
+            ("application/x-www-form-urlencoded", None),
+            (
+                "application/x-www-form-urlencoded; charset=UTF-8",
+                Some(FormCharset::Utf8),
+            ),
+            (
+                "application/x-www-form-urlencoded;charset=\"utf-8\"",
+                Some(FormCharset::Utf8),
+            ),
+            (
+                "application/x-www-form-urlencoded; Charset=Shift_JIS",
+                Some(FormCharset::ShiftJis),
+            ),
+            (
+                "application/x-www-form-urlencoded; charset=x-sjis",
+                Some(FormCharset::ShiftJis),
+            ),
+            ("application/x-www-form-urlencoded; charset=euc-jp", None),
+        ] {
+            assert_eq!(
+                FormCharset::from_content_type(content_type),
+                charset,
+                "{content_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_broken_percent_encoding() {
+        for body in ["a=%", "a=%4", "a=%zz", "a=%4g", "a=%%41", "a=%あ"] {
+            assert_eq!(
+                shift_jis_url_encodeded_body_to_vec(body, None),
+                Err(UrlDecodeError::BrokenPercentEncoding),
+                "{body}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_shift_jis() {
+        // A lead byte without its trail byte, followed by an invalid trail byte, and a byte
+        // which is never part of Shift_JIS, none of them being UTF-8 either
+        for body in ["a=%82", "a=%82%20", "a=%FF"] {
+            assert_eq!(
+                shift_jis_url_encodeded_body_to_vec(body, None),
+                Err(UrlDecodeError::InvalidShiftJis),
+                "{body}"
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn never_panics_on_any_body(body in any::<String>()) {
+            let _ = shift_jis_url_encodeded_body_to_vec(&body, None);
+        }
+
+        #[test]
+        fn never_panics_on_percent_heavy_bodies(body in "[%0-9A-Fa-fg&=+]{0,64}") {
+            let _ = shift_jis_url_encodeded_body_to_vec(&body, None);
+        }
+
+        #[test]
+        fn arbitrary_bytes_decode_or_fail_typed(
+            bytes in proptest::collection::vec(any::<u8>(), 0..64)
+        ) {
+            let body = format!("k={}", percent_encode(&bytes));
+            let utf8 = std::str::from_utf8(&bytes).ok();
+            let shift_jis = encoding_rs::SHIFT_JIS
+                .decode_without_bom_handling_and_without_replacement(&bytes);
+            let expected = match (utf8, shift_jis) {
+                (Some(utf8), _)
+                    if utf8.chars().all(|x| x.len_utf8() != 2)
+                        && utf8.chars().any(|x| {
+                            matches!(x, '\u{3000}'..='\u{30ff}' | '\u{4e00}'..='\u{9fff}' | '\u{ff00}'..='\u{ffef}')
+                        }) =>
+                {
+                    Some(utf8.to_string())
+                }
+                (_, Some(shift_jis)) => Some(shift_jis.into_owned()),
+                (utf8, None) => utf8.map(str::to_string),
+            };
+            match expected {
+                Some(value) => prop_assert_eq!(
+                    shift_jis_url_encodeded_body_to_vec(&body, None),
+                    Ok(vec![("k", value)])
+                ),
+                None => prop_assert_eq!(
+                    shift_jis_url_encodeded_body_to_vec(&body, None),
+                    Err(UrlDecodeError::InvalidShiftJis)
+                ),
+            }
+        }
+
+        #[test]
+        fn round_trips_utf8_japanese_text(text in "[ -~ぁ-んァ-ヶｱ-ﾝ亜漢字本文━∀★…\n]{0,32}") {
+            let text = format!("{text}あ");
+            let body = format!("k={}", percent_encode(text.as_bytes()));
+            prop_assert_eq!(
+                shift_jis_url_encodeded_body_to_vec(&body, None),
+                Ok(vec![("k", text)])
+            );
+        }
+
+        #[test]
+        fn round_trips_encodable_text(text in "[ -~ぁ-んァ-ヶｱ-ﾝ亜唖娃阿漢字書込板名無日本語\n]{0,32}") {
+            let body = format!("k={}", percent_encode(&encoding_rs::SHIFT_JIS.encode(&text).0));
+            prop_assert_eq!(
+                shift_jis_url_encodeded_body_to_vec(&body, None),
+                Ok(vec![("k", text)])
+            );
+        }
+    }
+
+    #[test]
+    fn to_hex_pads_each_byte() {
+        assert_eq!(to_hex(&[]), "");
+        assert_eq!(to_hex(&[0x00, 0x0f, 0xa0, 0xff]), "000fa0ff");
+    }
+
+    #[test]
+    fn derived_user_hashes_hide_the_token() {
+        let token = "0123456789abcdef01234567";
+        let derived = derive_user_hash(token);
+        assert_eq!(derived.len(), 16);
+        assert_eq!(derived, derive_user_hash(token));
+        assert_ne!(derived, derive_user_hash("0123456789abcdef01234568"));
+        assert!(!token.contains(&derived));
+    }
+}